@@ -0,0 +1,202 @@
+//! Terminal client for playing against the mahjong server.
+//!
+//! Uses the headless client in `mahjong-headless` to speak the same protocol as
+//! the Unity client, and renders the state of the table as text after each turn.
+//! This is mainly a tool for exercising the server during development without
+//! having to launch the Unity editor.
+
+use mahjong::{
+    anyhow::{anyhow, Context, Result},
+    match_state::MatchState,
+    messages::*,
+    render::{self, Style},
+    strum::IntoEnumIterator,
+    tile::{TileId, Wind},
+};
+use mahjong_headless::Client;
+use std::io::Write;
+use structopt::StructOpt;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Play mahjong against the server from the terminal")]
+struct Options {
+    /// The websocket endpoint of the server to connect to.
+    #[structopt(long, default_value = "ws://127.0.0.1:3030/client")]
+    server: String,
+
+    /// Render tiles using plain ASCII instead of the Unicode mahjong tiles.
+    #[structopt(long)]
+    ascii: bool,
+
+    /// The account ID to log in with. Requires `--token`.
+    #[structopt(long, requires = "token")]
+    account: Option<u64>,
+
+    /// The token for the account specified with `--account`.
+    #[structopt(long, requires = "account")]
+    token: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let options = Options::from_args();
+    let style = if options.ascii {
+        Style::Ascii
+    } else {
+        Style::Unicode
+    };
+
+    let credentials = match (options.account, options.token.clone()) {
+        (Some(id), Some(token)) => Some(Credentials {
+            id: AccountId::new(id),
+            token,
+        }),
+        _ => None,
+    };
+
+    let mut client = Client::connect(&options.server, credentials)
+        .await
+        .with_context(|| format!("Failed to connect to {}", options.server))?;
+
+    println!(
+        "Logged in as account {} (token: {})",
+        client.credentials().id,
+        client.credentials().token,
+    );
+    println!("Points balance: {}", client.account().points);
+
+    // Start a new match. For now the server always seats us as East, with the other
+    // three seats played by the computer.
+    let mut state = client.start_match().await?;
+    let seat = Wind::East;
+
+    println!("Started match {}, playing as {:?}", state.id(), seat);
+
+    let mut stdin = BufReader::new(tokio::io::stdin()).lines();
+
+    loop {
+        // If it's our turn, display the table and wait for the player to pick a tile
+        // to discard. The server will send back the discard event, so we don't apply
+        // the discard to the local state here.
+        if state.current_turn == seat && state.player(seat).current_draw().is_some() {
+            print_table(&state, seat, style);
+
+            let tile = prompt_discard(&state, seat, &mut stdin).await?;
+            client.discard_tile(state.id(), seat, tile).await?;
+        }
+
+        let event = client.next_event().await?;
+        state
+            .apply_event(&event)
+            .context("Local state is out of sync with the server")?;
+
+        match event {
+            MatchEvent::TileDrawn { .. } => {}
+
+            MatchEvent::TileDiscarded {
+                seat: discarder,
+                tile,
+            } => {
+                println!("{:?} discarded {}", discarder, render::tile_id(tile, style));
+            }
+
+            MatchEvent::MatchEnded => {
+                print_table(&state, seat, style);
+                println!("The wall is empty, the match is over");
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Displays the discards for every seat and the player's own hand.
+///
+/// The hands of the other players are shown face-down, since a real client
+/// wouldn't be able to see them.
+fn print_table(state: &MatchState, seat: Wind, style: Style) {
+    let back = match style {
+        Style::Unicode => "\u{1F02B}",
+        Style::Ascii => "##",
+    };
+
+    println!();
+    println!("Tiles left in wall: {}", state.wall.len());
+
+    for other in Wind::iter() {
+        let hand = state.player(other);
+
+        if other == seat {
+            println!("{:?} (you): {}", other, render::hand(hand, other, style));
+        } else {
+            let mut concealed = hand.tiles().len();
+            if hand.current_draw().is_some() {
+                concealed += 1;
+            }
+
+            println!("{:?}: {}", other, vec![back; concealed].join(""));
+        }
+
+        for row in render::discards(hand.discards(), style).lines() {
+            println!("    {}", row);
+        }
+    }
+
+    println!();
+}
+
+/// Prompts the player to select a tile to discard.
+///
+/// Tiles are selected by their position in the hand, with the current draw being
+/// the last tile. Entering nothing discards the current draw.
+async fn prompt_discard<R>(
+    state: &MatchState,
+    seat: Wind,
+    stdin: &mut tokio::io::Lines<R>,
+) -> Result<TileId>
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+{
+    let hand = state.player(seat);
+    let draw = hand.current_draw().expect("No current draw to discard");
+
+    loop {
+        let choices = hand
+            .tiles()
+            .iter()
+            .enumerate()
+            .map(|(index, tile)| format!("{}:{}", index + 1, tile.tile))
+            .collect::<Vec<_>>()
+            .join(" ");
+        print!(
+            "{} | {}:{}\nDiscard which tile? [default {}] ",
+            choices,
+            hand.tiles().len() + 1,
+            draw.tile,
+            draw.tile,
+        );
+        std::io::stdout().flush()?;
+
+        let line = stdin
+            .next_line()
+            .await?
+            .ok_or_else(|| anyhow!("Input closed while waiting for discard"))?;
+        let line = line.trim();
+
+        if line.is_empty() {
+            return Ok(draw.id);
+        }
+
+        match line.parse::<usize>() {
+            Ok(index) if (1..=hand.tiles().len()).contains(&index) => {
+                return Ok(hand.tiles()[index - 1].id);
+            }
+
+            Ok(index) if index == hand.tiles().len() + 1 => return Ok(draw.id),
+
+            _ => println!("Invalid selection {:?}, enter the number of a tile", line),
+        }
+    }
+}
@@ -83,9 +83,16 @@ public async UniTask<NextScreen> Run(ClientState client, WebSocket socket)
             // Request that the server start a new match, and load our tile prefabs in
             // the background. Wait for both of these operations to complete before
             // attempting to instantiate any tiles.
-            await UniTask.WhenAll(
-                RequestStartMatch(_cancellation.Token),
-                LoadTilePrefabs(_cancellation.Token));
+            var loadPrefabs = LoadTilePrefabs(_cancellation.Token);
+            var matchStarted = await RequestStartMatch(_cancellation.Token);
+            await loadPrefabs;
+
+            // TODO: Show the player why the match couldn't be started, rather than only
+            // logging the error.
+            if (!matchStarted)
+            {
+                return NextScreen.Home;
+            }
 
             // TODO: Have the server data specify which player is controlled by this
             // client, rather than hard coding it to always control the east seat.
@@ -96,28 +103,7 @@ public async UniTask<NextScreen> Run(ClientState client, WebSocket socket)
 
             // Once we have the match data, instantiate the tiles for each player's
             // starting hand.
-            //
-            // TODO: Move tile placement logic into `PlayerHand`. The match controller
-            // should only need to add and remove tiles from the hands as the match's
-            // state advances, and the `PlayerHand` script should handle layout and
-            // positioning.
-            foreach (var seat in EnumUtils.GetValues<Wind>())
-            {
-                var hand = _hands[(int)seat];
-
-                var tiles = _localState.PlayerHand(seat);
-
-                foreach (var tile in tiles)
-                {
-                    hand.AddToHand(InstantiateTile(tile));
-                }
-
-                if (_localState.PlayerHasCurrentDraw(seat))
-                {
-                    var currentDraw = _localState.CurrentDraw(seat);
-                    hand.DrawTile(InstantiateTile(currentDraw));
-                }
-            }
+            PopulateHands();
 
             // If the local player has the first turn, have them discard a tile now.
             if (_localState.CurrentTurn() == _seat)
@@ -130,10 +116,59 @@ public async UniTask<NextScreen> Run(ClientState client, WebSocket socket)
             while (!matchEnded)
             {
                 // Wait to receive the next update from the server.
-                var eventJson = await _socket.RecvStringAsync(_cancellation.Token);
+                var message = await _socket.RecvMessageAsync(_client, _cancellation.Token);
+
+                // The only requests we send during the match are discards, so an error
+                // means the server rejected our last discard and our local state no
+                // longer matches the server.
+                var error = _client.ErrorMessage(message);
+                if (error.Length > 0)
+                {
+                    Debug.LogWarning($"Server rejected request: {error}", this);
+                    await Resync();
+                    continue;
+                }
+
+                IMatchEvent update;
+                switch (_client.HandleEvent(message))
+                {
+                    // Feed the incoming event into the server state, and use the checksum
+                    // included with the event to verify that we're still in sync with the
+                    // server. If not, replace our state with a fresh snapshot from the
+                    // server.
+                    case EventStatus.Received received:
+                    {
+                        update = received.Event;
+                        _serverState.HandleEvent(update);
+                        if (!_serverState.IsSynced(received.Checksum))
+                        {
+                            await Resync();
+                            continue;
+                        }
+                    }
+                    break;
+
+                    // If we missed any events we can't catch up by applying the events
+                    // that follow, so get a fresh snapshot from the server instead.
+                    case EventStatus.Missed _:
+                    {
+                        await Resync();
+                        continue;
+                    }
 
-                // Feed the incoming event into the server state.
-                IMatchEvent update = _serverState.HandleEvent(eventJson);
+                    // Skip any events that were already included in the snapshot we
+                    // received when resyncing with the server.
+                    case EventStatus.Stale _:
+                    {
+                        continue;
+                    }
+
+                    default:
+                    {
+                        Debug.LogWarning("Ignoring unexpected message from server", this);
+                        continue;
+                    }
+                }
 
                 // Apply the received update to the local state, updating both the game
                 // state tracking and the visual state.
@@ -144,8 +179,8 @@ public async UniTask<NextScreen> Run(ClientState client, WebSocket socket)
                         // Update the game state tracking for the client.
                         if (!_localState.TryDrawTile(draw.Seat))
                         {
-                            // TODO: Handle the client being out of sync with the server.
-                            throw new NotImplementedException("Client out of sync with server");
+                            await Resync();
+                            continue;
                         }
 
                         var localDraw = _localState.CurrentDraw(draw.Seat);
@@ -193,9 +228,12 @@ public async UniTask<NextScreen> Run(ClientState client, WebSocket socket)
                             if (discard.Seat != _seat
                                 || discard.Tile.Element0 != lastDiscard.Element0)
                             {
-                                throw new OutOfSyncException(
-                                    $"Previously discarded tile {lastDiscard}, but received" +
-                                    $"discard event {discard}");
+                                Debug.LogWarning(
+                                    $"Previously discarded tile {lastDiscard}, but received " +
+                                    $"discard event {discard}",
+                                    this);
+                                await Resync();
+                                continue;
                             }
 
                             // Clear local tracking for discarded tile now that the server has
@@ -213,7 +251,9 @@ public async UniTask<NextScreen> Run(ClientState client, WebSocket socket)
                         }
                         else
                         {
-                            throw new OutOfSyncException($"Could not apply discard event locally: {discard}");
+                            Debug.LogWarning($"Could not apply discard event locally: {discard}", this);
+                            await Resync();
+                            continue;
                         }
 
                         // TODO: Reconcile our local state with the updated server state to
@@ -236,25 +276,134 @@ public async UniTask<NextScreen> Run(ClientState client, WebSocket socket)
             // Exit the match, indicating that we should return to the home screen.
             return NextScreen.Home;
 
-            // Helper method to handle requesting match creation from the server.
-            async UniTask RequestStartMatch(CancellationToken cancellation = default)
+            // Helper method to handle requesting match creation from the server. Returns
+            // whether the match was started.
+            async UniTask<bool> RequestStartMatch(CancellationToken cancellation = default)
             {
                 // Request that the server start a match.
                 var request = _client.CreateStartMatchRequest();
-                _socket.SendString(request);
-                var responseJson = await _socket.RecvStringAsync(cancellation);
+                _socket.SendMessage(request);
+                var response = await _socket.RecvMessageAsync(_client, cancellation);
+
+                switch (_client.HandleStartMatchResponse(response))
+                {
+                    case ResponseStatus.Success:
+                        break;
+
+                    case ResponseStatus.Rejected:
+                        Debug.LogError($"Server rejected request to start a match: {_client.RequestError()}", this);
+                        return false;
 
-                // TODO: Add some kind of error handling around failure. Probably not doable
-                // until we can return more structured data from Rust functions.
-                _serverState = _client.HandleStartMatchResponse(responseJson);
+                    default:
+                        Debug.LogError("Received invalid response to start match request", this);
+                        return false;
+                }
 
                 // TODO: Clone the server state directly to get the initial local state.
                 // This will require cs-bindgen to generate `Clone()` methods. For now
-                // we'll have to re-deserialize the server response to get a fresh copy
-                // of the match state.
-                _localState = _client.HandleStartMatchResponse(responseJson);
+                // we'll have to recreate the match state from the response to get a
+                // fresh copy.
+                _serverState = _client.ReceivedMatchState();
+                _localState = _client.ReceivedMatchState();
 
                 Debug.Log($"Started match, ID: {_serverState.Id()}", this);
+                return true;
+            }
+        }
+
+        /// <summary>
+        /// Instantiates the tiles for each player's hand based on the local state.
+        /// </summary>
+        //
+        // TODO: Move tile placement logic into `PlayerHand`. The match controller
+        // should only need to add and remove tiles from the hands as the match's
+        // state advances, and the `PlayerHand` script should handle layout and
+        // positioning.
+        private void PopulateHands()
+        {
+            foreach (var seat in EnumUtils.GetValues<Wind>())
+            {
+                var hand = _hands[(int)seat];
+
+                foreach (var tile in _localState.PlayerHand(seat))
+                {
+                    hand.AddToHand(InstantiateTile(tile));
+                }
+
+                if (_localState.PlayerHasCurrentDraw(seat))
+                {
+                    var currentDraw = _localState.CurrentDraw(seat);
+                    hand.DrawTile(InstantiateTile(currentDraw));
+                }
+
+                foreach (var tile in _localState.PlayerDiscards(seat))
+                {
+                    hand.AddToDiscards(InstantiateTile(tile));
+                }
+            }
+        }
+
+        /// <summary>
+        /// Replaces the match state with a fresh snapshot from the server, used when our
+        /// state is out of sync with the server.
+        /// </summary>
+        ///
+        /// <returns>
+        /// A task that resolves once the state and the visuals have been reset, and the
+        /// player has discarded if it's their turn.
+        /// </returns>
+        private async UniTask Resync()
+        {
+            Debug.LogWarning("Client is out of sync with the server, requesting snapshot", this);
+
+            var request = _client.CreateResyncRequest(_serverState.Id());
+            _socket.SendMessage(request);
+
+            // Events that the server sent before receiving the request may arrive
+            // before the response. They're already included in the snapshot, so we can
+            // skip them. Errors for requests we sent before the resync are skipped too.
+            List<byte> response;
+            do
+            {
+                response = await _socket.RecvMessageAsync(_client, _cancellation.Token);
+            }
+            while (!_client.IsResyncResponse(response));
+
+            // TODO: Recover from a failed resync, e.g. by leaving the match. For now we
+            // keep our current state and carry on.
+            switch (_client.HandleResyncResponse(response))
+            {
+                case ResponseStatus.Success:
+                    break;
+
+                case ResponseStatus.Rejected:
+                    Debug.LogError($"Server rejected resync request: {_client.RequestError()}", this);
+                    return;
+
+                default:
+                    Debug.LogError("Received invalid response to resync request", this);
+                    return;
+            }
+
+            _serverState.Dispose();
+            _serverState = _client.ReceivedMatchState();
+
+            _localState.Dispose();
+            _localState = _client.ReceivedMatchState();
+
+            // Any discard we were waiting on is either included in the snapshot or was
+            // rejected by the server.
+            _lastDiscard = null;
+
+            foreach (var hand in _hands)
+            {
+                hand.Clear();
+            }
+            PopulateHands();
+
+            if (_localState.CurrentTurn() == _seat && _localState.PlayerHasCurrentDraw(_seat))
+            {
+                await DiscardTile();
             }
         }
 
@@ -295,8 +444,8 @@ private async UniTask DiscardTile()
 
             // If the local attempt to discard the tile succeeded, send a request to the
             // server to perform the action.
-            var request = _serverState.RequestDiscardTile(hand.Seat, id);
-            _socket.SendString(request);
+            var request = _client.CreateDiscardTileRequest(_serverState.Id(), hand.Seat, id);
+            _socket.SendMessage(request);
         }
 
         #region Unity Lifecycle Methods
@@ -111,42 +111,73 @@ public void MoveToDiscard(TileId id)
                 throw new ArgumentException($"Tile {id} is not in {Seat} player's hand");
             }
 
-            // Add the discarded tile to the list of discards.
-            _discards.Add(discarded);
+            // Remove the click handler so that we don't get click events from discarded
+            // tiles.
+            discarded.Clicked -= OnTileClicked;
 
             // Make the discarded tile a child of the root object for the discard pile,
             // but keep its world position so that we can animate it from its current
             // position to its target position int the discard pile.
-            discarded.transform.SetParent(_discardRoot, worldPositionStays: true);
-
+            //
             // TODO: Actually do a tween. For now we'll immediately display the tile in
             // the player's discards.
+            AddToDiscards(discarded, worldPositionStays: true);
+
+            // If we didn't discard the drawn tile, merge the drawn tile into the
+            // player's hand.
+            if (_currentDraw != null)
+            {
+                AddToHand(_currentDraw);
+                _currentDraw = null;
+            }
+        }
+
+        /// <summary>
+        /// Adds a tile directly to the player's discard pile.
+        /// </summary>
+        public void AddToDiscards(TileView tile, bool worldPositionStays = false)
+        {
+            _discards.Add(tile);
+            tile.transform.SetParent(_discardRoot, worldPositionStays);
 
             // Layout the discarded tiles in rows of 6 tiles.
             var leftSide = TileWidth * -6 * 0.5f;
-            foreach (var (index, tile) in _discards.Enumerate())
+            foreach (var (index, discard) in _discards.Enumerate())
             {
                 int row = index / 6;
                 int col = index % 6;
-                tile.transform.localPosition = new Vector3(
+                discard.transform.localPosition = new Vector3(
                     leftSide + col * TileWidth,
                     0f,
                     -row * TileLength);
 
-                tile.transform.localRotation = Quaternion.identity;
+                discard.transform.localRotation = Quaternion.identity;
             }
+        }
 
-            // Remove the click handler so that we don't get click events from discarded
-            // tiles.
-            discarded.Clicked -= OnTileClicked;
+        /// <summary>
+        /// Destroys every tile in the hand, including the current draw and discards.
+        /// </summary>
+        public void Clear()
+        {
+            foreach (var tile in _tiles)
+            {
+                Destroy(tile.gameObject);
+            }
+
+            foreach (var tile in _discards)
+            {
+                Destroy(tile.gameObject);
+            }
 
-            // If we didn't discard the drawn tile, merge the drawn tile into the
-            // player's hand.
             if (_currentDraw != null)
             {
-                AddToHand(_currentDraw);
-                _currentDraw = null;
+                Destroy(_currentDraw.gameObject);
             }
+
+            _tiles.Clear();
+            _discards.Clear();
+            _currentDraw = null;
         }
 
         public UniTask<TileId> OnClickTileAsync(CancellationToken cancellation = default)
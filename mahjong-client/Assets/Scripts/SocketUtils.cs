@@ -0,0 +1,66 @@
+﻿using System.Collections.Generic;
+using System.Threading;
+using System.Threading.Tasks;
+using UnityEngine;
+
+namespace Synapse.Mahjong
+{
+    public static class SocketUtils
+    {
+        /// <summary>
+        /// Sends a message created by <see cref="ClientState"/> as a binary frame.
+        /// </summary>
+        ///
+        /// <remarks>
+        /// Messages are encoded with the encoding negotiated during the handshake,
+        /// which may not be valid UTF-8, so they must not be sent as text frames.
+        /// </remarks>
+        public static void SendMessage(this WebSocket socket, List<byte> message)
+        {
+            socket.Send(message.ToArray());
+        }
+
+        /// <summary>
+        /// Waits for the next message from the server, responding to any heartbeats
+        /// received in the meantime.
+        /// </summary>
+        ///
+        /// <remarks>
+        /// The server sends heartbeats periodically for the whole session, and will
+        /// disconnect the client if it doesn't respond to them. All messages from the
+        /// server after the handshake should be received through this method rather
+        /// than calling <see cref="WebSocket.RecvAsync"/> directly.
+        ///
+        /// Messages are returned as raw bytes, since the server may send them as
+        /// binary frames depending on the negotiated encoding.
+        ///
+        /// Error responses from the server are returned to the caller the same as any
+        /// other message, and can be recognized with <see cref="ClientState.ErrorMessage"/>.
+        /// </remarks>
+        ///
+        /// <param name="socket">The socket connected to the server.</param>
+        /// <param name="client">The client state used to process heartbeats.</param>
+        /// <param name="cancellation">Cancellation token for the receive operation.</param>
+        ///
+        /// <returns>
+        /// The next message from the server that isn't a heartbeat.
+        /// </returns>
+        public static async Task<List<byte>> RecvMessageAsync(
+            this WebSocket socket,
+            ClientState client,
+            CancellationToken cancellation = default)
+        {
+            while (true)
+            {
+                var message = new List<byte>(await socket.RecvAsync(cancellation));
+                if (client.IsHeartbeat(message))
+                {
+                    socket.SendMessage(client.CreateHeartbeat());
+                    continue;
+                }
+
+                return message;
+            }
+        }
+    }
+}
@@ -33,11 +33,12 @@ private async void Start()
                 // TODO: Make server address configurable.
                 _socket = await WebSocket.ConnectAsync(new Uri("ws://localhost:3030/client"));
 
-                // HACK: Due to a bug in WebSocketSharp, the first message that we'll receive
-                // when waiting will be the initial ping sent by the server to trigger the
-                // client connection.
-                var pingHack = await _socket.RecvStringAsync();
-                Debug.Assert(pingHack == "ping", $"Received unexpected first message: {pingHack}");
+                // The server sends a heartbeat as soon as the connection is established in
+                // order to work around a bug in WebSocketSharp that prevents it from
+                // recognizing the connection until a message is received. It's skipped
+                // when waiting for the handshake response rather than answered, since the
+                // session (and the negotiated encoding) only starts once the handshake
+                // completes.
 
                 Debug.Log("Established connection with server, beginning handshake");
 
@@ -48,14 +49,35 @@ private async void Start()
                 // * The client sends account ID and initial configuration data.
                 // * Server sends current account data and any updated cache data.
                 _socket.SendString(_state.CreateHandshakeRequest());
-                if (!_state.HandleHandshakeResponse(await _socket.RecvStringAsync()))
+
+                List<byte> response;
+                do
+                {
+                    response = new List<byte>(await _socket.RecvAsync());
+                } while (_state.IsHeartbeat(response));
+
+                var status = _state.HandleHandshakeResponse(response);
+                switch (status)
                 {
-                    // TODO: Add better handling for the case where we failed to connect to
-                    // the server. Obviously we need better error handling coming from the
-                    // Rust side, be we should probably have some option for the player to
-                    // re-attempt the connection.
-                    Debug.LogError("Handshake with server failed :'(");
-                    return;
+                    case HandshakeStatus.Success:
+                        break;
+
+                    // TODO: Show a prompt directing the player to update the game, rather
+                    // than only logging the error.
+                    case HandshakeStatus.UpdateRequired:
+                        Debug.LogError($"Client must be updated to connect to the server: {_state.HandshakeError()}");
+                        return;
+
+                    // TODO: Once we load cached credentials, discard them and reconnect
+                    // without credentials in order to create a new account.
+                    case HandshakeStatus.InvalidCredentials:
+                        Debug.LogError($"Server rejected account credentials: {_state.HandshakeError()}");
+                        return;
+
+                    // TODO: Add some option for the player to re-attempt the connection.
+                    default:
+                        Debug.LogError($"Handshake with server failed: {status}");
+                        return;
                 }
 
                 Debug.Log($"Handshake completed, account ID: {_state.AccountId()}, points balance: {_state.Points()}");
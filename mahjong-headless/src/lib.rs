@@ -0,0 +1,765 @@
+//! Headless Rust client for the mahjong server.
+//!
+//! Implements the client side of the server protocol without any presentation
+//! layer, for use in integration tests, bots, and development tools. The Unity
+//! client uses the cs-bindgen helpers on `ClientState` instead, but both speak the
+//! same protocol using the message types defined in `mahjong::messages`.
+//!
+//! ```no_run
+//! # async fn run() -> mahjong::anyhow::Result<()> {
+//! use mahjong_headless::Client;
+//!
+//! let mut client = Client::connect("ws://127.0.0.1:3030/client", None).await?;
+//! let mut state = client.start_match().await?;
+//!
+//! loop {
+//!     let event = client.next_event().await?;
+//!     state.apply_event(&event)?;
+//!
+//!     // Respond to the event...
+//! #   break;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::prelude::*;
+use mahjong::{
+    anyhow::{anyhow, bail, Context, Result},
+    codec::Encoding,
+    match_state::{MatchId, MatchState, Ruleset},
+    messages::*,
+    spectator::{SpectatorEvent, SpectatorSnapshot, SpectatorView},
+    sync::Checksum,
+    tile::{TileId, Wind},
+};
+use serde::Serialize;
+use std::collections::VecDeque;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, Message},
+    WebSocketStream,
+};
+use tracing::*;
+
+/// An active, authenticated session with the server.
+#[derive(Debug)]
+pub struct Client {
+    connection: Connection,
+    credentials: Credentials,
+    account: PlayerState,
+    active_match: Option<MatchId>,
+}
+
+impl Client {
+    /// Connects to the server at `url` and performs the initial handshake.
+    ///
+    /// Only plain `ws` URLs are supported, the client can't connect over TLS.
+    ///
+    /// If `credentials` is `None`, the server will create a new account for the
+    /// client. The credentials for the session can be retrieved with
+    /// [`credentials`](#method.credentials) once the connection is established.
+    ///
+    /// The client offers every encoding in `Encoding::SUPPORTED`, so the server will
+    /// select its preferred binary encoding.
+    pub async fn connect(url: &str, credentials: Option<Credentials>) -> Result<Self> {
+        Self::connect_with_encodings(url, credentials, Encoding::SUPPORTED).await
+    }
+
+    /// Connects to the server, offering only the specified encodings for messages
+    /// after the handshake.
+    ///
+    /// The server falls back to JSON if it doesn't support any of `encodings`.
+    pub async fn connect_with_encodings(
+        url: &str,
+        credentials: Option<Credentials>,
+        encodings: &[Encoding],
+    ) -> Result<Self> {
+        let request = url
+            .into_client_request()
+            .with_context(|| format!("Invalid server URL: {}", url))?;
+
+        // TODO: Support `wss` once the server can be deployed behind TLS.
+        if request.uri().scheme_str() != Some("ws") {
+            bail!("Only `ws` server URLs are supported: {}", url);
+        }
+
+        let host = request
+            .uri()
+            .host()
+            .ok_or_else(|| anyhow!("Server URL has no host: {}", url))?
+            .to_owned();
+        let port = request.uri().port_u16().unwrap_or(80);
+
+        let stream = TcpStream::connect((host.as_str(), port))
+            .await
+            .with_context(|| format!("Failed to connect to {}", url))?;
+        let (socket, _) = tokio_tungstenite::client_async(request, stream)
+            .await
+            .context("Failed to establish websocket connection")?;
+
+        let mut connection = Connection {
+            socket,
+            next_request_id: RequestId::default(),
+            next_sequence: 0,
+            checksum: None,
+            pending: VecDeque::new(),
+            encoding: Encoding::Json,
+        };
+
+        trace!("Connected to server, sending handshake request");
+
+        let mut request = HandshakeRequest::new(credentials.clone());
+        request.encodings = encodings.to_vec();
+        connection.send(&request).await?;
+
+        // NOTE: The server sends a heartbeat as soon as the connection is established.
+        // It doesn't expect a response until the handshake is complete, and any
+        // response we sent now would have to be encoded before we know which encoding
+        // the server selected, so the heartbeat is skipped without responding.
+        let response = loop {
+            let frame = connection
+                .recv_frame()
+                .await
+                .ok_or_else(|| anyhow!("Server closed the connection during handshake"))??;
+            let text = String::from_utf8(frame).context("Handshake response is not text")?;
+            if !ServerMessage::is_heartbeat(&text) {
+                break text;
+            }
+        };
+        let response = serde_json::from_str::<HandshakeResult>(&response)
+            .with_context(|| format!("Invalid handshake response: {}", response))?
+            .context("Server rejected handshake")?;
+        connection.encoding = response.encoding;
+
+        // Use the new credentials if the server issued them, otherwise the server
+        // accepted the credentials we provided.
+        let credentials = response
+            .new_credentials
+            .or(credentials)
+            .ok_or_else(|| anyhow!("Server did not provide credentials for new account"))?;
+
+        info!(id = %credentials.id, encoding = ?response.encoding, "Completed handshake with server");
+
+        Ok(Self {
+            connection,
+            credentials,
+            account: response.account_data,
+            active_match: response.active_match,
+        })
+    }
+
+    /// The credentials for the account that the client is logged into.
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    /// The encoding used for messages after the handshake, as selected by the server.
+    pub fn encoding(&self) -> Encoding {
+        self.connection.encoding
+    }
+
+    /// The account data received from the server during the handshake.
+    pub fn account(&self) -> &PlayerState {
+        &self.account
+    }
+
+    /// The match that the account was playing in when the client connected, if any.
+    ///
+    /// The match can be rejoined with [`resume_match`](#method.resume_match).
+    pub fn active_match(&self) -> Option<MatchId> {
+        self.active_match
+    }
+
+    /// Requests that the server start a new match, returning the initial state of
+    /// the match.
+    pub async fn start_match(&mut self) -> Result<MatchState> {
+        let request_id = self
+            .connection
+            .send_request(ClientRequest::StartMatch)
+            .await?;
+
+        match self.connection.recv_response(request_id).await? {
+            Response::StartMatch(response) => {
+                let state = MatchState::from_snapshot(&response.snapshot)
+                    .context("Server sent an invalid match snapshot")?;
+                self.connection.next_sequence = 0;
+                self.connection.checksum = Some(response.snapshot.checksum());
+                Ok(state)
+            }
+
+            response => bail!("Unexpected response to start match request: {:?}", response),
+        }
+    }
+
+    /// Joins the matchmaking queue, returning the client's initial place in the
+    /// queue.
+    ///
+    /// Use [`wait_for_match`](#method.wait_for_match) to wait until the server has
+    /// placed the client in a match.
+    pub async fn join_queue(&mut self) -> Result<QueueStatus> {
+        let request_id = self
+            .connection
+            .send_request(ClientRequest::JoinQueue)
+            .await?;
+
+        match self.connection.recv_response(request_id).await? {
+            Response::JoinQueue(status) => Ok(status),
+            response => bail!("Unexpected response to join queue request: {:?}", response),
+        }
+    }
+
+    /// Leaves the matchmaking queue.
+    ///
+    /// If the server placed the client in a match just before the request arrived,
+    /// the client may still receive `MatchFound` afterwards, but the player won't be
+    /// in the match until they rejoin it with
+    /// [`resume_match`](#method.resume_match).
+    pub async fn leave_queue(&mut self) -> Result<()> {
+        let request_id = self
+            .connection
+            .send_request(ClientRequest::LeaveQueue)
+            .await?;
+
+        match self.connection.recv_response(request_id).await? {
+            Response::LeaveQueue => Ok(()),
+            response => bail!("Unexpected response to leave queue request: {:?}", response),
+        }
+    }
+
+    /// Creates a private room and takes `seat` in it, returning the state of the room.
+    ///
+    /// If `ruleset` is `None`, the server's default ruleset is used. Other players
+    /// join the room using the room's code.
+    pub async fn create_room(
+        &mut self,
+        name: &str,
+        ruleset: Option<Ruleset>,
+        seat: Wind,
+    ) -> Result<RoomInfo> {
+        let request = ClientRequest::CreateRoom(CreateRoomRequest {
+            name: name.into(),
+            ruleset,
+            seat,
+        });
+        let request_id = self.connection.send_request(request).await?;
+
+        match self.connection.recv_response(request_id).await? {
+            Response::CreateRoom(info) => Ok(info),
+            response => bail!("Unexpected response to create room request: {:?}", response),
+        }
+    }
+
+    /// Joins the private room with the code `code`, taking `seat` in it.
+    pub async fn join_room(&mut self, code: &str, seat: Wind) -> Result<RoomInfo> {
+        let request = ClientRequest::JoinRoom(JoinRoomRequest {
+            code: RoomCode::new(code),
+            seat,
+        });
+        let request_id = self.connection.send_request(request).await?;
+
+        match self.connection.recv_response(request_id).await? {
+            Response::JoinRoom(info) => Ok(info),
+            response => bail!("Unexpected response to join room request: {:?}", response),
+        }
+    }
+
+    /// Leaves the client's room. If the client is the room's host, the room is
+    /// closed.
+    pub async fn leave_room(&mut self) -> Result<()> {
+        let request_id = self
+            .connection
+            .send_request(ClientRequest::LeaveRoom)
+            .await?;
+
+        match self.connection.recv_response(request_id).await? {
+            Response::LeaveRoom => Ok(()),
+            response => bail!("Unexpected response to leave room request: {:?}", response),
+        }
+    }
+
+    /// Starts the match for the room that the client is hosting.
+    ///
+    /// Like every other player in the room, the client then needs to call
+    /// [`wait_for_match`](#method.wait_for_match) to get the initial state of the
+    /// match.
+    pub async fn start_room(&mut self, fill_with_bots: bool) -> Result<()> {
+        let request = ClientRequest::StartRoom(StartRoomRequest { fill_with_bots });
+        let request_id = self.connection.send_request(request).await?;
+
+        match self.connection.recv_response(request_id).await? {
+            Response::StartRoom => Ok(()),
+            response => bail!("Unexpected response to start room request: {:?}", response),
+        }
+    }
+
+    /// Waits for the next change to the client's room, returning `None` if the room
+    /// was closed.
+    pub async fn next_room_update(&mut self) -> Result<Option<RoomInfo>> {
+        loop {
+            let message = self
+                .connection
+                .next_message()
+                .await
+                .ok_or_else(|| anyhow!("Server closed the connection"))??;
+
+            match message {
+                ServerMessage::RoomUpdated(info) => return Ok(Some(info)),
+                ServerMessage::RoomClosed => return Ok(None),
+                ServerMessage::Error(err) => return Err(err.into()),
+
+                message => warn!(?message, "Ignoring unexpected message from server"),
+            }
+        }
+    }
+
+    /// Waits to be placed in a match by matchmaking or by the host of the client's
+    /// room, returning the seat the client controls and the initial state of the
+    /// match.
+    ///
+    /// Queue status and room updates received while waiting are skipped.
+    pub async fn wait_for_match(&mut self) -> Result<(Wind, MatchState)> {
+        let found = self
+            .connection
+            .next_match_found()
+            .await
+            .ok_or_else(|| anyhow!("Server closed the connection"))??;
+
+        let state = MatchState::from_snapshot(&found.snapshot)
+            .context("Server sent an invalid match snapshot")?;
+        self.connection.next_sequence = 0;
+        self.connection.checksum = Some(found.snapshot.checksum());
+        Ok((found.seat, state))
+    }
+
+    /// Rejoins a match that the account was playing in before the client
+    /// disconnected.
+    ///
+    /// `events_received` is the number of events the previous client received
+    /// before disconnecting, as returned by
+    /// [`events_received`](#method.events_received). The response includes every
+    /// event after those, along with the current state of the match.
+    pub async fn resume_match(
+        &mut self,
+        id: MatchId,
+        events_received: usize,
+    ) -> Result<ResumeMatchResponse> {
+        let request = ClientRequest::ResumeMatch(ResumeMatchRequest {
+            id,
+            events_received,
+        });
+        let request_id = self.connection.send_request(request).await?;
+
+        match self.connection.recv_response(request_id).await? {
+            Response::ResumeMatch(response) => {
+                self.connection.next_sequence = events_received + response.missed_events.len();
+                self.connection.checksum = Some(response.snapshot.checksum());
+                Ok(response)
+            }
+
+            response => bail!(
+                "Unexpected response to resume match request: {:?}",
+                response
+            ),
+        }
+    }
+
+    /// Requests a fresh snapshot of the match, returning the current state of the
+    /// match.
+    ///
+    /// Use this to recover if the local state no longer matches
+    /// [`checksum`](#method.checksum). Events already included in the snapshot are
+    /// skipped by [`next_event`](#method.next_event).
+    pub async fn resync(&mut self, id: MatchId) -> Result<MatchState> {
+        let request = ClientRequest::Resync(ResyncRequest { id });
+        let request_id = self.connection.send_request(request).await?;
+
+        match self.connection.recv_response(request_id).await? {
+            Response::Resync(response) => {
+                let state = MatchState::from_snapshot(&response.snapshot)
+                    .context("Server sent an invalid match snapshot")?;
+                self.connection.next_sequence = response.events_applied;
+                self.connection.checksum = Some(response.snapshot.checksum());
+                Ok(state)
+            }
+
+            response => bail!("Unexpected response to resync request: {:?}", response),
+        }
+    }
+
+    /// Requests that the server discard `tile` for the player at `seat`.
+    ///
+    /// The server doesn't respond to the request directly. If the discard is valid,
+    /// the server will broadcast a `TileDiscarded` event, otherwise the next call to
+    /// [`next_event`](#method.next_event) returns an `ErrorResponse` with the
+    /// returned request ID.
+    pub async fn discard_tile(
+        &mut self,
+        id: MatchId,
+        seat: Wind,
+        tile: TileId,
+    ) -> Result<RequestId> {
+        let request = ClientRequest::DiscardTile(DiscardTileRequest {
+            id,
+            player: seat,
+            tile,
+        });
+        self.connection.send_request(request).await
+    }
+
+    /// Waits for the next match event from the server.
+    ///
+    /// If the server sends an error instead, the returned error can be downcast to
+    /// an `ErrorResponse`. Fails if the server skips any events in the match.
+    pub async fn next_event(&mut self) -> Result<MatchEvent> {
+        self.connection
+            .next_event()
+            .await
+            .ok_or_else(|| anyhow!("Server closed the connection"))?
+    }
+
+    /// Starts spectating the match with the ID `id`, returning the state of the match
+    /// before its first event.
+    ///
+    /// Every event in the match is then available from
+    /// [`next_spectator_event`](#method.next_spectator_event), starting with the
+    /// first.
+    pub async fn spectate(
+        &mut self,
+        id: MatchId,
+        view: SpectatorView,
+    ) -> Result<SpectatorSnapshot> {
+        let request = ClientRequest::Spectate(SpectateRequest { id, view });
+        let request_id = self.connection.send_request(request).await?;
+
+        match self.connection.recv_response(request_id).await? {
+            Response::Spectate(response) => {
+                self.connection.next_sequence = 0;
+                self.connection.checksum = None;
+                Ok(response.snapshot)
+            }
+
+            response => bail!("Unexpected response to spectate request: {:?}", response),
+        }
+    }
+
+    /// Stops spectating the current match.
+    ///
+    /// Events that the server had already sent may still be received afterwards.
+    pub async fn stop_spectating(&mut self) -> Result<()> {
+        let request_id = self
+            .connection
+            .send_request(ClientRequest::StopSpectating)
+            .await?;
+
+        match self.connection.recv_response(request_id).await? {
+            Response::StopSpectating => Ok(()),
+            response => bail!(
+                "Unexpected response to stop spectating request: {:?}",
+                response
+            ),
+        }
+    }
+
+    /// Waits for the next event in the match that the client is spectating.
+    ///
+    /// Fails if the server skips any events in the match.
+    pub async fn next_spectator_event(&mut self) -> Result<SpectatorEvent> {
+        self.connection
+            .next_spectator_event()
+            .await
+            .ok_or_else(|| anyhow!("Server closed the connection"))?
+    }
+
+    /// The number of events received so far in the current match.
+    ///
+    /// If the client disconnects in the middle of a match, pass this to
+    /// [`resume_match`](#method.resume_match) after reconnecting to receive the
+    /// events that were missed.
+    pub fn events_received(&self) -> usize {
+        self.connection.next_sequence
+    }
+
+    /// The checksum of the server's match state as of the most recent event or
+    /// snapshot, or `None` if the client hasn't joined a match.
+    ///
+    /// After applying each event, the local state should have the same checksum (see
+    /// `MatchState::checksum`). If it doesn't, the local state is out of sync and
+    /// the client should call [`resync`](#method.resync).
+    pub fn checksum(&self) -> Option<Checksum> {
+        self.connection.checksum
+    }
+
+    /// Returns a stream of the match events sent by the server.
+    ///
+    /// The stream yields an error if a message fails to parse or if the server sends
+    /// an error, and ends once the server closes the connection.
+    pub fn events(&mut self) -> impl Stream<Item = Result<MatchEvent>> + '_ {
+        stream::unfold(self, |client| async move {
+            let event = client.connection.next_event().await?;
+            Some((event, client))
+        })
+    }
+
+    /// Closes the connection to the server.
+    pub async fn close(mut self) -> Result<()> {
+        self.connection
+            .socket
+            .close(None)
+            .await
+            .context("Failed to close connection")
+    }
+}
+
+/// Helper for sending and receiving messages over the socket.
+#[derive(Debug)]
+struct Connection {
+    socket: WebSocketStream<TcpStream>,
+
+    /// The ID to assign to the next request sent to the server.
+    next_request_id: RequestId,
+
+    /// The sequence number expected for the next match event.
+    next_sequence: usize,
+
+    /// The checksum sent with the most recent match event.
+    checksum: Option<Checksum>,
+
+    /// Messages that were received while waiting for the response to a request.
+    pending: VecDeque<ServerMessage>,
+
+    /// The encoding for sent and received messages. Always JSON during the
+    /// handshake.
+    encoding: Encoding,
+}
+
+impl Connection {
+    /// Sends `request` to the server, returning the ID assigned to the request.
+    async fn send_request(&mut self, request: ClientRequest) -> Result<RequestId> {
+        let request_id = self.next_request_id;
+        self.next_request_id = request_id.next();
+
+        self.send(&ClientMessage {
+            request_id,
+            request,
+        })
+        .await?;
+
+        Ok(request_id)
+    }
+
+    async fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        let bytes = self
+            .encoding
+            .encode(message)
+            .context("Failed to serialize message")?;
+        let message = if self.encoding.is_binary() {
+            Message::Binary(bytes)
+        } else {
+            Message::Text(String::from_utf8(bytes).context("JSON message was not UTF-8")?)
+        };
+
+        self.socket
+            .send(message)
+            .await
+            .context("Failed to send message to server")
+    }
+
+    /// Waits for the response to the request with the ID `request_id`.
+    ///
+    /// Any other messages received in the meantime are queued, to be returned by
+    /// later calls to `next_event`.
+    async fn recv_response(&mut self, request_id: RequestId) -> Result<Response> {
+        loop {
+            let message = self
+                .recv_message()
+                .await
+                .ok_or_else(|| anyhow!("Server closed the connection"))??;
+
+            match message {
+                ServerMessage::Response {
+                    request_id: id,
+                    response,
+                } if id == request_id => return Ok(response),
+
+                ServerMessage::Error(err) if err.request_id == Some(request_id) => {
+                    return Err(err.into())
+                }
+
+                message => self.pending.push_back(message),
+            }
+        }
+    }
+
+    /// Waits for the next match event, returning `None` if the connection has been
+    /// closed.
+    async fn next_event(&mut self) -> Option<Result<MatchEvent>> {
+        loop {
+            let message = match self.next_message().await? {
+                Ok(message) => message,
+                Err(err) => return Some(Err(err)),
+            };
+
+            match message {
+                ServerMessage::Event {
+                    sequence,
+                    event,
+                    checksum,
+                } => {
+                    // NOTE: Events from before a resync may arrive after the response,
+                    // but they're already included in the snapshot.
+                    if sequence < self.next_sequence {
+                        debug!(sequence, "Skipping event included in snapshot");
+                        continue;
+                    }
+
+                    if sequence != self.next_sequence {
+                        return Some(Err(anyhow!(
+                            "Missed match events, expected event #{} but received #{}",
+                            self.next_sequence,
+                            sequence,
+                        )));
+                    }
+
+                    self.next_sequence += 1;
+                    self.checksum = Some(checksum);
+                    return Some(Ok(event));
+                }
+
+                ServerMessage::Error(err) => return Some(Err(err.into())),
+
+                message => warn!(?message, "Ignoring unexpected message from server"),
+            }
+        }
+    }
+
+    /// Waits for the next event in the match that the client is spectating, returning
+    /// `None` if the connection has been closed.
+    async fn next_spectator_event(&mut self) -> Option<Result<SpectatorEvent>> {
+        loop {
+            let message = match self.next_message().await? {
+                Ok(message) => message,
+                Err(err) => return Some(Err(err)),
+            };
+
+            match message {
+                ServerMessage::SpectatorEvent { sequence, event } => {
+                    if sequence != self.next_sequence {
+                        return Some(Err(anyhow!(
+                            "Missed match events, expected event #{} but received #{}",
+                            self.next_sequence,
+                            sequence,
+                        )));
+                    }
+
+                    // Revealing the hands doesn't count as a match event.
+                    if !matches!(event, SpectatorEvent::HandsRevealed { .. }) {
+                        self.next_sequence += 1;
+                    }
+                    return Some(Ok(event));
+                }
+
+                ServerMessage::Error(err) => return Some(Err(err.into())),
+
+                message => warn!(?message, "Ignoring unexpected message from server"),
+            }
+        }
+    }
+
+    /// Waits for the server to place the client in a match, returning `None` if the
+    /// connection has been closed.
+    async fn next_match_found(&mut self) -> Option<Result<MatchFound>> {
+        loop {
+            let message = match self.next_message().await? {
+                Ok(message) => message,
+                Err(err) => return Some(Err(err)),
+            };
+
+            match message {
+                ServerMessage::MatchFound(found) => return Some(Ok(found)),
+
+                ServerMessage::QueueStatus(status) => {
+                    debug!(?status, "Received matchmaking queue status");
+                }
+
+                ServerMessage::RoomUpdated(info) => {
+                    debug!(?info, "Received room update");
+                }
+
+                ServerMessage::RoomClosed => {
+                    return Some(Err(anyhow!("Room was closed before the match started")))
+                }
+
+                ServerMessage::Error(err) => return Some(Err(err.into())),
+
+                message => warn!(?message, "Ignoring unexpected message from server"),
+            }
+        }
+    }
+
+    /// Returns the oldest message queued while waiting for a response, or waits for
+    /// the next message from the server if there isn't one.
+    async fn next_message(&mut self) -> Option<Result<ServerMessage>> {
+        match self.pending.pop_front() {
+            Some(message) => Some(Ok(message)),
+            None => self.recv_message().await,
+        }
+    }
+
+    /// Waits for the next message from the server, returning `None` if the
+    /// connection has been closed.
+    ///
+    /// Heartbeats from the server are answered automatically.
+    async fn recv_message(&mut self) -> Option<Result<ServerMessage>> {
+        loop {
+            let frame = match self.recv_frame().await? {
+                Ok(frame) => frame,
+                Err(err) => return Some(Err(err)),
+            };
+
+            let message = match self.encoding.decode(&frame) {
+                Ok(message) => message,
+                Err(err) => return Some(Err(err).context("Failed to parse message from server")),
+            };
+
+            if let ServerMessage::Heartbeat = message {
+                trace!("Received heartbeat from server");
+                if let Err(err) = self.send_request(ClientRequest::Heartbeat).await {
+                    return Some(Err(err));
+                }
+                continue;
+            }
+
+            return Some(Ok(message));
+        }
+    }
+
+    /// Waits for the next text or binary message from the server, returning `None`
+    /// if the connection has been closed.
+    ///
+    /// Websocket control messages (e.g. pings) are skipped.
+    async fn recv_frame(&mut self) -> Option<Result<Vec<u8>>> {
+        loop {
+            let message = match self.socket.next().await? {
+                Ok(message) => message,
+                Err(err) => return Some(Err(err).context("Failed to receive message")),
+            };
+
+            match message {
+                Message::Text(text) => return Some(Ok(text.into_bytes())),
+                Message::Binary(bytes) => return Some(Ok(bytes)),
+
+                Message::Close(frame) => {
+                    if let Some(frame) = frame {
+                        let reason = DisconnectReason::from_close_code(frame.code.into());
+                        info!(?reason, code = %frame.code, "Server closed the connection");
+                    }
+
+                    return None;
+                }
+
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Generation and verification of account tokens.
+//!
+//! Tokens are random strings generated by the server and given to the client,
+//! which then uses the token to log back into its account. The server only stores
+//! a hash of each token, so that the tokens can't be recovered from the stored
+//! account data.
+
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// The number of random bytes used to generate a token.
+pub const TOKEN_BYTES: usize = 32;
+
+/// Generates a new cryptographically random token.
+///
+/// The token is returned as a hex-encoded string.
+pub fn generate_token() -> String {
+    let mut bytes = [0; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// A hashed account token.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TokenHash([u8; 32]);
+
+impl TokenHash {
+    /// Hashes `token` for storage.
+    pub fn new(token: &str) -> Self {
+        let digest = Sha256::digest(token.as_bytes());
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&digest);
+        Self(hash)
+    }
+
+    /// Creates a `TokenHash` from a previously-hashed token, e.g. one loaded from
+    /// storage.
+    pub fn from_bytes(hash: [u8; 32]) -> Self {
+        Self(hash)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Checks if `token` matches the hashed token.
+    ///
+    /// The comparison takes the same amount of time regardless of how much of the
+    /// hash matches, in order to avoid leaking information about the stored hash.
+    pub fn verify(&self, token: &str) -> bool {
+        let other = Self::new(token);
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .fold(0, |diff, (left, right)| diff | (left ^ right))
+            == 0
+    }
+}
+
+// NOTE: The hash is deliberately omitted from the debug output so that it doesn't
+// end up in the logs.
+impl fmt::Debug for TokenHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TokenHash(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_unique() {
+        let first = generate_token();
+        let second = generate_token();
+
+        assert_eq!(TOKEN_BYTES * 2, first.len());
+        assert!(first.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn verify_token() {
+        let token = generate_token();
+        let hash = TokenHash::new(&token);
+
+        assert!(hash.verify(&token));
+        assert!(!hash.verify(&generate_token()));
+        assert!(!hash.verify(""));
+    }
+}
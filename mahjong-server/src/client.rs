@@ -1,12 +1,26 @@
-use crate::{match_controller::*, GameState};
+use crate::{
+    config::Config, match_controller::*, matchmaking::MatchmakerProxy, room::RoomProxy, GameState,
+};
 use derive_more::Display;
 use futures::{
     prelude::*,
     stream::{SplitSink, SplitStream},
 };
-use mahjong::{anyhow::*, messages::*, tile::Wind};
-use std::sync::atomic::{AtomicU64, Ordering};
+use mahjong::{
+    anyhow::*,
+    codec::{CodecError, Encoding},
+    hand::DiscardError,
+    match_state::{InsufficientTiles, InvalidDiscard, MatchId},
+    messages::*,
+    spectator::SpectatorEvent,
+    sync::{Checksum, MatchSnapshot},
+    tile::Wind,
+};
+use serde::{de::IgnoredAny, Deserialize};
+use std::sync::Arc;
 use thespian::{Actor, Remote, StageBuilder};
+use thiserror::Error;
+use tokio::time;
 use tracing::*;
 use warp::{filters::ws::Message as WsMessage, ws::WebSocket};
 
@@ -18,44 +32,60 @@ pub struct ClientController {
     /// The sender half of the socket connection with the client.
     sink: SplitSink<WebSocket, WsMessage>,
     game: <GameState as Actor>::Proxy,
+    matchmaker: MatchmakerProxy,
     state: ClientState,
 
+    /// The account that the client is logged into.
+    account: AccountId,
+
+    /// The encoding negotiated during the handshake, used for all subsequent
+    /// messages in both directions.
+    encoding: Encoding,
+
     remote: Remote<Self>,
 }
 
 impl ClientController {
     /// Attempts to perform the session handshake with the client, returning a new
     /// `ClientConnection` if it succeeds.
-    #[instrument(skip(socket, game))]
+    #[instrument(skip(socket, game, config))]
     pub async fn perform_handshake(
         id: ClientId,
         socket: WebSocket,
         mut game: <GameState as Actor>::Proxy,
+        config: Arc<Config>,
     ) -> Result<(<ClientController as Actor>::Proxy, SplitStream<WebSocket>)> {
         info!("Starting client handshake");
 
         let (mut sink, mut stream) = socket.split();
 
-        // HACK: Send an initial text message to the client after establishing a
-        // connection. It looks like there's a bug in WebSocketSharp that means it won't
-        // recognize that the connection has been established unit it receives a message,
-        // causing the client to hang. This won't be necessary once we move off of web
-        // sockets.
-        sink.send(WsMessage::text("ping"))
-            .await
-            .expect("Failed to send initial ping");
-
-        trace!("Sent the client the initial ping, awaiting the handshake request");
-
-        // Wait for the client to send the handshake.
-        //
-        // TODO: Include a timeout so that we don't wait forever, otherwise this is a vector
-        // for DOS attacks.
-        let request = stream
-            .next()
+        // Send an initial heartbeat to the client after establishing a connection. It
+        // looks like there's a bug in WebSocketSharp that means it won't recognize that
+        // the connection has been established until it receives a message, causing the
+        // client to hang.
+        let heartbeat = serde_json::to_string(&ServerMessage::Heartbeat)
+            .expect("Failed to serialize heartbeat");
+        sink.send(WsMessage::text(heartbeat))
             .await
-            .ok_or(anyhow!("Client disconnected during initial handshake"))?
-            .context("Waiting for response to handshake ping")?;
+            .context("Failed to send initial heartbeat")?;
+
+        trace!("Sent the client the initial heartbeat, awaiting the handshake request");
+
+        // Wait for the client to send the handshake, disconnecting the client if it takes
+        // too long so that idle connections can't pile up.
+        let request = match time::timeout(config.handshake_timeout, stream.next()).await {
+            Ok(request) => request
+                .ok_or(anyhow!("Client disconnected during initial handshake"))?
+                .context("Waiting for response to initial heartbeat")?,
+
+            Err(_) => {
+                close(&mut sink, DisconnectReason::HandshakeTimeout).await?;
+                bail!(
+                    "Client did not send handshake within {:?}",
+                    config.handshake_timeout
+                );
+            }
+        };
 
         // Parse the request data.
         let request = request
@@ -65,35 +95,63 @@ impl ClientController {
 
         trace!("Received handshake request from client");
 
-        // Verify that the client is compatible with the current server version. For now
-        // we only check that the client version matches the server version, which is
-        // enough for development purposes. Once we're in production we may want a more
-        // permissive strategy that allows us to push server updates without invalidating
-        // existing clients.
+        // Verify that the client is compatible with the current server version before
+        // doing anything else. See `HandshakeRequest::check_version` for the details of
+        // the compatibility policy.
         let server_version =
             Version::parse(env!("CARGO_PKG_VERSION")).expect("Failed to parse server version");
-        if server_version != request.client_version {
-            todo!("Handle incompatible client version");
-        }
+        let version_check = request.check_version(&server_version, &config.min_client_version);
 
         // Get account information from the server, creating a new account if the client
         // did not provide credentials for an existing account.
-        let account = match request.credentials {
-            Some(..) => todo!("Support logging into an existing account"),
-            None => game.create_account()?.await,
+        let account = match (version_check, request.credentials) {
+            (Err(err), _) => Err(err),
+            (Ok(()), Some(credentials)) => game
+                .log_in(credentials)?
+                .await?
+                .ok_or(HandshakeError::InvalidCredentials),
+            (Ok(()), None) => Ok(game.create_account()?.await?),
         };
 
-        info!("Verified handshake request, completing client connection");
+        // Create the response message and send it to the client. If the handshake was
+        // rejected we still notify the client of the reason before disconnecting.
+        let response: HandshakeResult = match account {
+            Ok((credentials, account_data)) => {
+                // Let the client know if it was disconnected from a match that's still in
+                // progress, so that it can rejoin the match.
+                let active_match = game.active_match(credentials.id)?.await.map(|(id, _)| id);
+
+                Ok(HandshakeResponse {
+                    server_version,
+                    new_credentials: Some(credentials),
+                    account_data,
+                    active_match,
+                    encoding: Encoding::negotiate(&request.encodings),
+                })
+            }
 
-        // Create the response message and send it to the client.
-        let response = HandshakeResponse {
-            server_version,
-            new_credentials: Some(account.credentials),
-            account_data: account.data,
+            Err(err) => Err(err),
+        };
+        let text = serde_json::to_string(&response).expect("Failed to serialize `HandshakeResult`");
+        sink.send(WsMessage::text(text)).await?;
+
+        let (account, encoding) = match response {
+            Ok(response) => (
+                response.new_credentials.expect("No credentials issued").id,
+                response.encoding,
+            ),
+            Err(err) => {
+                close(&mut sink, DisconnectReason::HandshakeRejected).await?;
+                bail!("Rejected handshake request: {}", err);
+            }
         };
-        let response =
-            serde_json::to_string(&response).expect("Failed to serialize `HandshakeResponse`");
-        sink.send(WsMessage::text(response)).await?;
+
+        info!(
+            ?encoding,
+            "Verified handshake request, completing client connection"
+        );
+
+        let matchmaker = game.matchmaker()?.await;
 
         // Create the actor for the client connection and spawn it.
         let (builder, remote) = StageBuilder::new();
@@ -101,111 +159,681 @@ impl ClientController {
             id,
             sink,
             game,
+            matchmaker,
             state: ClientState::Idle,
+            account,
+            encoding,
             remote,
         });
         let client = stage.proxy();
         tokio::spawn(stage.run());
 
-        // TODO: Track the active session in the central game state.
+        // Register the session with the game state so that the session can be tracked
+        // by account.
+        game.start_session(id, account, client.clone())?.await;
 
         Ok((client, stream))
     }
 
-    /// Sends the provided string as a message to the client.
-    async fn send_text(&mut self, text: String) -> Result<()> {
+    /// Notifies the client that the server failed to handle one of its requests.
+    async fn send_error(
+        &mut self,
+        request_id: Option<RequestId>,
+        kind: ErrorKind,
+        message: String,
+    ) -> Result<()> {
+        self.send_message(&ServerMessage::Error(ErrorResponse {
+            request_id,
+            kind,
+            message,
+        }))
+        .await
+    }
+
+    /// Encodes `message` with the session's encoding and sends it to the client.
+    async fn send_message(&mut self, message: &ServerMessage) -> Result<()> {
+        let bytes = self
+            .encoding
+            .encode(message)
+            .expect("Failed to serialize `ServerMessage`");
+
+        // NOTE: JSON encoding always produces valid UTF-8, so it's safe to send it
+        // as a text frame.
+        let message = if self.encoding.is_binary() {
+            WsMessage::binary(bytes)
+        } else {
+            WsMessage::text(String::from_utf8(bytes).expect("JSON message was not UTF-8"))
+        };
+
         self.sink
-            .send(WsMessage::text(text))
+            .send(message)
             .await
             .context("Failed to send message to client")
     }
-}
 
-#[thespian::actor]
-impl ClientController {
-    pub async fn handle_message(&mut self, message: WsMessage) -> Result<()> {
-        let span = trace_span!("handle_message", id = %self.id);
-        let _span = span.enter();
-
-        let text = match message.to_str() {
-            Ok(text) => text,
-            Err(_) => bail!("Received non-text message: {:?}", message),
-        };
+    /// Fails with an `InvalidRequest` error if the client is in, waiting for, or
+    /// spectating a match, using `action` to describe the rejected request.
+    fn ensure_idle(&self, action: &str) -> Result<()> {
+        match self.state {
+            ClientState::Idle => Ok(()),
+            ClientState::Queued => bail!(InvalidRequest::new(format!(
+                "Cannot {} while in the matchmaking queue",
+                action
+            ))),
+            ClientState::InRoom { .. } => bail!(InvalidRequest::new(format!(
+                "Cannot {} while in a room",
+                action
+            ))),
+            ClientState::InMatch { .. } => bail!(InvalidRequest::new(format!(
+                "Cannot {} while already in a match",
+                action
+            ))),
+            ClientState::Spectating { .. } => bail!(InvalidRequest::new(format!(
+                "Cannot {} while spectating a match",
+                action
+            ))),
+        }
+    }
 
-        let request = serde_json::from_str::<ClientRequest>(text)?;
-        info!(?request, "Handling incoming request");
+    async fn send_response(&mut self, request_id: RequestId, response: Response) -> Result<()> {
+        self.send_message(&ServerMessage::Response {
+            request_id,
+            response,
+        })
+        .await
+    }
 
+    async fn handle_request(
+        &mut self,
+        request_id: RequestId,
+        request: ClientRequest,
+    ) -> Result<()> {
         match request {
             ClientRequest::StartMatch => {
-                // TODO: Do an error if the client is already in a match (or would otherwise not be
-                // able to start a match).
+                self.ensure_idle("start a match")?;
 
                 trace!("Asking the game controller to start a match...");
 
-                let mut controller = self.game.start_match().unwrap().await;
+                let mut controller = self.game.create_match(None)?.await?;
 
                 // Join the match as the East player.
-                let state = controller
-                    .join(self.remote.proxy(), Wind::East)
-                    .unwrap()
-                    .await
-                    .expect("Failed to join the match that we just started???");
+                let snapshot = controller
+                    .join(self.id, self.remote.proxy(), self.account, Wind::East)?
+                    .await?;
+                controller.start()?.await?;
 
                 trace!("Match started, joined as East player");
 
-                let response = serde_json::to_string(&StartMatchResponse { state })
-                    .expect("Failed to serialize `StartMatchResponse`");
-                self.send_text(response).await?;
+                let response = Response::StartMatch(StartMatchResponse { snapshot });
+                self.send_response(request_id, response).await?;
 
                 trace!("Sent initial state to client, transitioning controller to `InMatch`");
-                self.state = ClientState::InMatch { controller };
+                self.state = ClientState::InMatch {
+                    controller,
+                    seat: Wind::East,
+                };
             }
 
-            ClientRequest::DiscardTile(request) => {
+            ClientRequest::ResumeMatch(request) => {
+                self.ensure_idle("resume a match")?;
+
+                let (id, mut controller) = match self.game.active_match(self.account)?.await {
+                    Some(active) => active,
+                    None => bail!(InvalidRequest::new("Account has no active match to resume")),
+                };
+                if id != request.id {
+                    bail!(InvalidRequest::new(format!(
+                        "Requested to resume match {}, but active match is {}",
+                        request.id, id,
+                    )));
+                }
+
+                let response = controller
+                    .rejoin(
+                        self.id,
+                        self.remote.proxy(),
+                        self.account,
+                        request.events_received,
+                    )?
+                    .await?;
+                let seat = response.seat;
+
+                trace!(?seat, "Rejoined match, sending current state to client");
+
+                self.send_response(request_id, Response::ResumeMatch(response))
+                    .await?;
+
+                self.state = ClientState::InMatch { controller, seat };
+            }
+
+            ClientRequest::Resync(request) => {
+                let controller = match &mut self.state {
+                    ClientState::InMatch { controller, .. } => controller,
+                    _ => bail!(InvalidRequest::new("Cannot resync when not in a match")),
+                };
+
+                let response = controller.resync(request.id)?.await?;
+
+                info!(
+                    events_applied = response.events_applied,
+                    "Client was out of sync, sending match snapshot",
+                );
+
+                self.send_response(request_id, Response::Resync(response))
+                    .await?;
+            }
+
+            ClientRequest::JoinQueue => {
+                self.ensure_idle("join the matchmaking queue")?;
+
+                let rating = self.game.rating(self.account)?.await?;
+                let status = self
+                    .matchmaker
+                    .join(self.id, self.remote.proxy(), self.account, rating)?
+                    .await?;
+
+                // NOTE: The matchmaker may have already found a match for the client, in
+                // which case the notification arrives once we're done handling the
+                // request, so we still need to transition to `Queued` here.
+                self.state = ClientState::Queued;
+
+                self.send_response(request_id, Response::JoinQueue(status))
+                    .await?;
+            }
+
+            ClientRequest::LeaveQueue => {
+                if !matches!(self.state, ClientState::Queued) {
+                    bail!(InvalidRequest::new("Not in the matchmaking queue"));
+                }
+
+                self.matchmaker.leave(self.id)?.await;
+                self.state = ClientState::Idle;
+
+                self.send_response(request_id, Response::LeaveQueue).await?;
+            }
+
+            ClientRequest::CreateRoom(request) => {
+                self.ensure_idle("create a room")?;
+
+                let mut room = self
+                    .game
+                    .create_room(request.name, request.ruleset, self.account)?
+                    .await?;
+                let info = room
+                    .join(self.id, self.remote.proxy(), self.account, request.seat)?
+                    .await?;
+
+                info!(code = %info.code, "Created private room");
+
+                self.state = ClientState::InRoom { room };
+                self.send_response(request_id, Response::CreateRoom(info))
+                    .await?;
+            }
+
+            ClientRequest::JoinRoom(request) => {
+                self.ensure_idle("join a room")?;
+
+                let mut room = match self.game.find_room(request.code.clone())?.await {
+                    Some(room) => room,
+                    None => bail!(InvalidRequest::new(format!(
+                        "No open room with code {}",
+                        request.code
+                    ))),
+                };
+                let info = room
+                    .join(self.id, self.remote.proxy(), self.account, request.seat)?
+                    .await?;
+
+                self.state = ClientState::InRoom { room };
+                self.send_response(request_id, Response::JoinRoom(info))
+                    .await?;
+            }
+
+            ClientRequest::LeaveRoom => {
+                let room = match &mut self.state {
+                    ClientState::InRoom { room } => room,
+                    _ => bail!(InvalidRequest::new("Not in a room")),
+                };
+
+                room.leave(self.id)?.await?;
+                self.state = ClientState::Idle;
+
+                self.send_response(request_id, Response::LeaveRoom).await?;
+            }
+
+            ClientRequest::StartRoom(request) => {
+                let room = match &mut self.state {
+                    ClientState::InRoom { room } => room,
+                    _ => bail!(InvalidRequest::new("Not in a room")),
+                };
+
+                // NOTE: The client is notified that the match started once we're done
+                // handling the request, the same as every other player in the room.
+                room.start(self.id, request.fill_with_bots)?.await?;
+
+                self.send_response(request_id, Response::StartRoom).await?;
+            }
+
+            ClientRequest::Spectate(request) => {
+                self.ensure_idle("spectate a match")?;
+
+                let mut controller = match self.game.find_match(request.id)?.await {
+                    Some(controller) => controller,
+                    None => bail!(InvalidRequest::new(format!(
+                        "No match in progress with ID {}",
+                        request.id
+                    ))),
+                };
+                let response = controller
+                    .spectate(self.id, self.remote.proxy(), self.account, request.view)?
+                    .await?;
+
+                // NOTE: The match's events are only sent once we're done handling the
+                // request, so the client always receives the response first.
+                self.state = ClientState::Spectating {
+                    id: request.id,
+                    controller,
+                };
+                self.send_response(request_id, Response::Spectate(response))
+                    .await?;
+            }
+
+            ClientRequest::StopSpectating => {
                 let controller = match &mut self.state {
-                    ClientState::InMatch { controller } => controller,
-                    _ => bail!("Cannot discard a tile when not in a match"),
+                    ClientState::Spectating { controller, .. } => controller,
+                    _ => bail!(InvalidRequest::new("Not spectating a match")),
                 };
 
+                // NOTE: The match may have already ended, in which case there's nothing
+                // to stop.
+                if let Ok(stop) = controller.stop_spectating(self.id) {
+                    stop.await;
+                }
+                self.state = ClientState::Idle;
+
+                self.send_response(request_id, Response::StopSpectating)
+                    .await?;
+            }
+
+            ClientRequest::Heartbeat => {
+                trace!("Received heartbeat from client");
+            }
+
+            ClientRequest::DiscardTile(request) => {
+                let (controller, seat) = match &mut self.state {
+                    ClientState::InMatch { controller, seat } => (controller, *seat),
+                    _ => bail!(InvalidRequest::new(
+                        "Cannot discard a tile when not in a match"
+                    )),
+                };
+
+                if request.player != seat {
+                    bail!(InvalidRequest::new(format!(
+                        "Cannot discard for {:?} player, client controls {:?}",
+                        request.player, seat,
+                    )));
+                }
+
                 trace!("Forwarding discard request to match controller");
 
-                let result = controller
-                    .discard_tile(request.player, request.tile)
-                    .expect("Match controller died before match ended")
+                // NOTE: The discard can fail without the client doing anything wrong,
+                // e.g. if the turn timer expired while the request was in flight, so
+                // the error is reported back to the client.
+                controller
+                    .discard_tile(request.id, self.id, seat, request.tile)?
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[thespian::actor]
+impl ClientController {
+    pub async fn handle_message(&mut self, message: WsMessage) -> Result<()> {
+        let span = trace_span!("handle_message", id = %self.id);
+        let _span = span.enter();
+
+        if !message.is_text() && !message.is_binary() {
+            bail!("Received non-data message: {:?}", message);
+        }
+        let bytes = message.as_bytes();
+
+        let message = match self.encoding.decode::<ClientMessage>(bytes) {
+            Ok(message) => message,
+            Err(err) => {
+                warn!(%err, "Failed to parse client request");
+
+                // Attempt to recover the request ID so that the client can tell which of
+                // its requests was rejected.
+                let request_id = self
+                    .encoding
+                    .decode::<UnparsedMessage>(bytes)
+                    .ok()
+                    .map(|message| message.request_id);
+
+                return self
+                    .send_error(request_id, ErrorKind::InvalidMessage, err.to_string())
                     .await;
+            }
+        };
+
+        let request_id = message.request_id;
+        info!(%request_id, request = ?message.request, "Handling incoming request");
+
+        if let Err(err) = self.handle_request(request_id, message.request).await {
+            let kind = error_kind(&err);
+
+            // NOTE: Internal errors may include details about the server that we don't
+            // want to expose to clients, so we only log the full error.
+            let message = if kind == ErrorKind::Internal {
+                error!("Error handling client request: {:?}", err);
+                String::from("Internal server error")
+            } else {
+                info!(%err, ?kind, "Rejected client request");
+                err.to_string()
+            };
+
+            self.send_error(Some(request_id), kind, message).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn send_heartbeat(&mut self) -> Result<()> {
+        self.send_message(&ServerMessage::Heartbeat).await
+    }
+
+    /// Closes the connection with the client, notifying the client of the reason.
+    #[tracing::instrument(skip(self), fields(id = %self.id))]
+    pub async fn disconnect(&mut self, reason: DisconnectReason) {
+        info!("Disconnecting client");
 
-                match result {
-                    Ok(()) => {}
-                    Err(err) => todo!("Notify client that discard failed? {}", err),
+        if let Err(err) = close(&mut self.sink, reason).await {
+            info!(%err, "Failed to send close message, client may have already disconnected");
+        }
+    }
+
+    /// Cleans up the session once the connection with the client has closed.
+    ///
+    /// If the client was in a match, the match is notified that the client's seat
+    /// is no longer connected. If the client was waiting for a match, it's removed
+    /// from the matchmaking queue or room it was waiting in.
+    #[tracing::instrument(skip(self), fields(id = %self.id))]
+    pub async fn shut_down(&mut self) {
+        match std::mem::replace(&mut self.state, ClientState::Idle) {
+            ClientState::InMatch {
+                mut controller,
+                seat,
+            } => {
+                info!(?seat, "Leaving match after client disconnected");
+
+                // NOTE: The match may have already ended, in which case there's nothing
+                // to leave.
+                if let Ok(leave) = controller.leave(seat, self.id) {
+                    leave.await;
                 }
             }
+
+            ClientState::Queued => {
+                info!("Leaving matchmaking queue after client disconnected");
+                self.matchmaker
+                    .leave(self.id)
+                    .expect("Matchmaker actor has stopped")
+                    .await;
+            }
+
+            ClientState::InRoom { mut room } => {
+                info!("Leaving room after client disconnected");
+
+                // NOTE: The room may have already closed, in which case there's nothing
+                // to leave.
+                if let Ok(leave) = room.leave(self.id) {
+                    if let Err(err) = leave.await {
+                        warn!(%err, "Failed to leave room");
+                    }
+                }
+            }
+
+            ClientState::Spectating { mut controller, .. } => {
+                info!("Stopping spectating after client disconnected");
+
+                if let Ok(stop) = controller.stop_spectating(self.id) {
+                    stop.await;
+                }
+            }
+
+            ClientState::Idle => {}
         }
 
-        Ok(())
+        // Close our half of the socket. This fails if the connection was already
+        // closed, which is expected in most cases.
+        let _ = self.sink.close().await;
+    }
+
+    /// Notifies the client of its current place in the matchmaking queue.
+    pub async fn send_queue_status(&mut self, status: QueueStatus) {
+        // NOTE: The client may have left the queue after the matchmaker sent the
+        // status.
+        if !matches!(self.state, ClientState::Queued) {
+            return;
+        }
+
+        if let Err(err) = self.send_message(&ServerMessage::QueueStatus(status)).await {
+            info!(%err, "Failed to send queue status, client may have disconnected");
+        }
+    }
+
+    /// Moves the client into the match that it was placed in by the matchmaker or by
+    /// the host of its room.
+    ///
+    /// The client has already joined `controller` as `seat`.
+    #[tracing::instrument(skip(self, controller, snapshot), fields(id = %self.id))]
+    pub async fn match_found(
+        &mut self,
+        mut controller: MatchControllerProxy,
+        seat: Wind,
+        snapshot: MatchSnapshot,
+    ) {
+        // If the client left the queue or disconnected after the matchmaker had already
+        // seated it, leave the match the same as if the client had disconnected. The
+        // player keeps their seat and can still rejoin with `ResumeMatch`.
+        if !matches!(self.state, ClientState::Queued | ClientState::InRoom { .. }) {
+            info!("Client stopped waiting before the match was found, leaving match");
+            let _ = controller.leave(seat, self.id);
+            return;
+        }
+
+        info!(?seat, "Found a match for the client");
+
+        self.state = ClientState::InMatch { controller, seat };
+
+        let message = ServerMessage::MatchFound(MatchFound { snapshot, seat });
+        if let Err(err) = self.send_message(&message).await {
+            info!(%err, "Failed to notify client of match, client may have disconnected");
+        }
+    }
+
+    /// Notifies the client that a player joined or left its room.
+    pub async fn room_updated(&mut self, info: RoomInfo) {
+        if !matches!(self.state, ClientState::InRoom { .. }) {
+            return;
+        }
+
+        if let Err(err) = self.send_message(&ServerMessage::RoomUpdated(info)).await {
+            info!(%err, "Failed to send room update, client may have disconnected");
+        }
+    }
+
+    /// Notifies the client that its room was closed because the host left.
+    #[tracing::instrument(skip(self), fields(id = %self.id))]
+    pub async fn room_closed(&mut self) {
+        if !matches!(self.state, ClientState::InRoom { .. }) {
+            return;
+        }
+
+        info!("Room closed, returning client to idle");
+        self.state = ClientState::Idle;
+
+        if let Err(err) = self.send_message(&ServerMessage::RoomClosed).await {
+            info!(%err, "Failed to notify client, client may have disconnected");
+        }
     }
 
     /// Sends an event to the client independent of the request/response flow.
+    ///
+    /// `sequence` is the index of the event within the match, and `checksum` is the
+    /// checksum of the match state after the event. The client leaves the match once
+    /// the match has ended, so that it can start another.
     // TODO: Generalize this to work for all kinds of server-sent events once we have
     // other events to send.
-    pub async fn send_event(&mut self, event: MatchEvent) {
-        trace!(id = %self.id, ?event, "Sending a server event to the client");
+    pub async fn send_event(&mut self, sequence: usize, event: MatchEvent, checksum: Checksum) {
+        trace!(id = %self.id, sequence, ?event, "Sending a server event to the client");
+
+        // NOTE: Events may still arrive after the client has left the match, since the
+        // match controller may have sent them before it was notified.
+        if !matches!(self.state, ClientState::InMatch { .. }) {
+            debug!("Received match event when client wasn't in a match, ignoring");
+            return;
+        }
 
-        assert!(
-            matches!(self.state, ClientState::InMatch { .. }),
-            "Received match event when client wasn't in a match"
-        );
+        if let MatchEvent::MatchEnded = event {
+            self.state = ClientState::Idle;
+        }
 
-        let message = serde_json::to_string(&event).expect("Failed to serialize match event");
-        self.send_text(message)
-            .await
-            .expect("Disconnected from the client, probably");
+        let message = ServerMessage::Event {
+            sequence,
+            event,
+            checksum,
+        };
+        if let Err(err) = self.send_message(&message).await {
+            info!(%err, "Failed to send event, client may have disconnected");
+        }
+    }
+
+    /// Sends an event in the match with the ID `id` to the client, which is
+    /// spectating the match.
+    ///
+    /// The client stops spectating once the match has ended.
+    pub async fn send_spectator_event(
+        &mut self,
+        id: MatchId,
+        sequence: usize,
+        event: SpectatorEvent,
+    ) {
+        trace!(id = %self.id, sequence, ?event, "Sending a spectator event to the client");
+
+        // NOTE: Events that were already queued may still arrive after the client has
+        // stopped spectating, possibly after it has started spectating another match.
+        match self.state {
+            ClientState::Spectating { id: spectating, .. } if spectating == id => {}
+            _ => {
+                debug!("Received event for a match the client isn't spectating, ignoring");
+                return;
+            }
+        }
+
+        if let SpectatorEvent::MatchEnded = event {
+            self.state = ClientState::Idle;
+        }
+
+        let message = ServerMessage::SpectatorEvent { sequence, event };
+        if let Err(err) = self.send_message(&message).await {
+            info!(%err, "Failed to send event, client may have disconnected");
+        }
+    }
+}
+
+/// Error for a request that isn't valid in the current state of the session or
+/// match, e.g. discarding a tile when the client isn't in a match.
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct InvalidRequest(String);
+
+impl InvalidRequest {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
     }
 }
 
+/// Determines which kind of error to report to the client for `err`.
+fn error_kind(err: &Error) -> ErrorKind {
+    for cause in err.chain() {
+        if cause.is::<InvalidRequest>() {
+            return ErrorKind::InvalidRequest;
+        }
+
+        if let Some(err) = cause.downcast_ref::<InvalidDiscard>() {
+            return match err {
+                InvalidDiscard::TileNotInHand => ErrorKind::TileNotInHand,
+                InvalidDiscard::IncorrectTurn { .. } => ErrorKind::IncorrectTurn,
+            };
+        }
+
+        if let Some(err) = cause.downcast_ref::<DiscardError>() {
+            return match err {
+                DiscardError::NoDraw => ErrorKind::NoDraw,
+                DiscardError::NotInHand => ErrorKind::TileNotInHand,
+            };
+        }
+
+        if cause.is::<InsufficientTiles>() {
+            return ErrorKind::InsufficientTiles;
+        }
+
+        if cause.is::<CodecError>() || cause.is::<serde_json::Error>() {
+            return ErrorKind::InvalidMessage;
+        }
+    }
+
+    ErrorKind::Internal
+}
+
+/// Used to recover the request ID from a message that otherwise failed to parse.
+///
+/// NOTE: The `request` field must be declared even though it's ignored, since binary
+/// encodings may encode structs as arrays, which fail to parse if they have more
+/// elements than the struct has fields.
+#[derive(Deserialize)]
+struct UnparsedMessage {
+    request_id: RequestId,
+
+    #[serde(default)]
+    #[allow(dead_code)]
+    request: IgnoredAny,
+}
+
+/// Sends a close message with the specified reason to the client.
+async fn close(sink: &mut SplitSink<WebSocket, WsMessage>, reason: DisconnectReason) -> Result<()> {
+    let message = WsMessage::close_with(reason.close_code(), reason.to_string());
+    sink.send(message)
+        .await
+        .context("Failed to send close message to client")
+}
+
 #[derive(Debug, Clone)]
 enum ClientState {
     Idle,
-    InMatch { controller: MatchControllerProxy },
+
+    /// The client is waiting in the matchmaking queue.
+    Queued,
+
+    /// The client is waiting in a private room for the host to start the match.
+    InRoom {
+        room: RoomProxy,
+    },
+
+    InMatch {
+        controller: MatchControllerProxy,
+        seat: Wind,
+    },
+
+    /// The client is watching a match that it isn't playing in.
+    Spectating {
+        id: MatchId,
+        controller: MatchControllerProxy,
+    },
 }
 
 /// Identifier for a connected client session.
@@ -214,21 +842,14 @@ enum ClientState {
 /// IDs are not guaranteed to be unique over the lifetime of the server application
 /// (IDs may be reused after enough sessions are created), but are guaranteed to be
 /// unique while the session is active (i.e. no two active sessions will have the
-/// same ID).
-// TODO: Actually guarantee that IDs are unique. This will require some kind of
-// tracking of active sessions IDs to prevent duplicates from being issued.
+/// same ID). IDs are allocated by `GameState::open_session`, which tracks the active
+/// sessions.
 #[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[display(fmt = "{}", _0)]
 pub struct ClientId(u64);
 
-pub struct ClientIdGenerator(AtomicU64);
-
-impl ClientIdGenerator {
-    pub fn new() -> Self {
-        Self(AtomicU64::new(0))
-    }
-
-    pub fn next(&self) -> ClientId {
-        ClientId(self.0.fetch_add(1, Ordering::SeqCst))
+impl ClientId {
+    pub fn new(id: u64) -> Self {
+        Self(id)
     }
 }
@@ -0,0 +1,626 @@
+//! Server configuration.
+//!
+//! Configuration is loaded from the following sources, with later sources taking
+//! precedence over earlier ones:
+//!
+//! 1. The built-in defaults.
+//! 2. A TOML config file, specified with `--config` or `MAHJONG_CONFIG`. If neither
+//!    is set, `mahjong.toml` in the working directory is used if it exists.
+//! 3. Environment variables, e.g. `MAHJONG_BIND_ADDRESS`.
+//! 4. Command line flags, e.g. `--bind-address`.
+//!
+//! An example config file specifying every option:
+//!
+//! ```toml
+//! bind_address = "127.0.0.1:3030"
+//! starting_balance = 10000
+//! default_ruleset = "Riichi"
+//! default_bot = "Efficiency"
+//! persistence_path = "mahjong.sqlite"
+//! min_client_version = "0.1.0"
+//!
+//! [log]
+//! level = "trace"
+//! format = "full"
+//!
+//! [timeouts]
+//! # All timeouts are specified in seconds.
+//! handshake = 10
+//! idle = 300
+//! turn = 30
+//!
+//! [matchmaking]
+//! # Seconds a queued player waits before empty seats are filled with bots, or 0 to
+//! # never fill seats with bots.
+//! bot_backfill = 30
+//! # The largest rating difference allowed between matched players. If omitted,
+//! # players are matched in the order they joined the queue. Ratings aren't updated
+//! # after matches yet, so this currently has no effect.
+//! rating_range = 200
+//!
+//! [spectators]
+//! # Seconds that spectators who can see every player's hand are kept behind the
+//! # live match.
+//! delay = 120
+//! ```
+
+use mahjong::{
+    anyhow::{anyhow, bail, Context, Result},
+    bot::BotKind,
+    match_state::Ruleset,
+    messages::Version,
+};
+use serde::Deserialize;
+use std::{
+    env, fmt, fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use structopt::StructOpt;
+use tracing::Level;
+
+/// The config file that is loaded if no other config file is specified.
+pub const DEFAULT_CONFIG_PATH: &str = "mahjong.toml";
+
+/// The prefix for environment variables that override config values.
+pub const ENV_PREFIX: &str = "MAHJONG_";
+
+/// Fully resolved and validated configuration for the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// The address that the server listens on.
+    pub bind_address: SocketAddr,
+
+    /// The maximum level of log messages to output.
+    pub log_level: Level,
+
+    /// The format used for log output.
+    pub log_format: LogFormat,
+
+    /// The number of points that new accounts start with.
+    pub starting_balance: u64,
+
+    /// The ruleset used for new matches, unless another ruleset is requested.
+    pub default_ruleset: Ruleset,
+
+    /// The strategy used by computer-controlled players.
+    pub default_bot: BotKind,
+
+    /// How long to wait for a newly-connected client to send its handshake.
+    pub handshake_timeout: Duration,
+
+    /// How long a client may go without sending any messages before it is
+    /// disconnected.
+    pub idle_timeout: Duration,
+
+    /// How long a player has to take their turn during a match.
+    pub turn_timeout: Duration,
+
+    /// The SQLite database used to persist game data. If `None`, data is only
+    /// stored in memory and is lost when the server shuts down.
+    pub persistence_path: Option<PathBuf>,
+
+    /// The oldest client version that is allowed to connect. Older clients are told
+    /// to update during the handshake.
+    pub min_client_version: Version,
+
+    /// How long a player waits in the matchmaking queue before any seats that
+    /// couldn't be filled with other players are given to bots. If `None`, players
+    /// wait until enough other players have joined the queue.
+    pub bot_backfill: Option<Duration>,
+
+    /// The largest difference in rating allowed between players in the same match.
+    /// If `None`, ratings are ignored and players are matched in the order they
+    /// joined the queue.
+    ///
+    /// NOTE: Ratings aren't updated after matches yet, so every player has
+    /// `matchmaking::STARTING_RATING` and this currently has no effect.
+    pub rating_range: Option<u32>,
+
+    /// How far behind the live match spectators who can see every player's hand are
+    /// kept, so that they can't pass on what they see to a player in the match.
+    pub spectator_delay: Duration,
+}
+
+impl Config {
+    /// How often the server sends a heartbeat to each client.
+    ///
+    /// Heartbeats are sent often enough that a client that responds to each one will
+    /// never hit the idle timeout.
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.idle_timeout / 3
+    }
+
+    /// Loads the config using the command line arguments and environment variables
+    /// for the current process.
+    pub fn load() -> Result<Self> {
+        let options = Options::from_args();
+
+        let mut config = Config::default();
+
+        // Load the config file, if one was specified or the default one exists.
+        let file_path = options
+            .config
+            .clone()
+            .or_else(|| env::var_os(format!("{}CONFIG", ENV_PREFIX)).map(PathBuf::from));
+        match file_path {
+            Some(path) => config.apply(ConfigLayer::from_file(&path)?)?,
+            None => {
+                let path = Path::new(DEFAULT_CONFIG_PATH);
+                if path.exists() {
+                    config.apply(ConfigLayer::from_file(path)?)?;
+                }
+            }
+        }
+
+        config.apply(ConfigLayer::from_env()?)?;
+        config.apply(options.into_layer())?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Overrides any config values specified in `layer`.
+    fn apply(&mut self, layer: ConfigLayer) -> Result<()> {
+        if let Some(bind_address) = layer.bind_address {
+            self.bind_address = bind_address;
+        }
+
+        if let Some(level) = layer.log.level {
+            self.log_level = Level::from_str(&level).map_err(|_| {
+                anyhow!(
+                    "Invalid log level {:?}, expected one of \"trace\", \"debug\", \"info\", \"warn\", or \"error\"",
+                    level,
+                )
+            })?;
+        }
+
+        if let Some(format) = layer.log.format {
+            self.log_format = format;
+        }
+
+        if let Some(starting_balance) = layer.starting_balance {
+            self.starting_balance = starting_balance;
+        }
+
+        if let Some(ruleset) = layer.default_ruleset {
+            self.default_ruleset = ruleset;
+        }
+
+        if let Some(bot) = layer.default_bot {
+            self.default_bot = bot;
+        }
+
+        if let Some(secs) = layer.timeouts.handshake {
+            self.handshake_timeout = Duration::from_secs(secs);
+        }
+
+        if let Some(secs) = layer.timeouts.idle {
+            self.idle_timeout = Duration::from_secs(secs);
+        }
+
+        if let Some(secs) = layer.timeouts.turn {
+            self.turn_timeout = Duration::from_secs(secs);
+        }
+
+        if let Some(path) = layer.persistence_path {
+            self.persistence_path = Some(path);
+        }
+
+        if let Some(version) = layer.min_client_version {
+            self.min_client_version = version;
+        }
+
+        if let Some(secs) = layer.matchmaking.bot_backfill {
+            self.bot_backfill = match secs {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            };
+        }
+
+        if let Some(range) = layer.matchmaking.rating_range {
+            self.rating_range = Some(range);
+        }
+
+        if let Some(secs) = layer.spectators.delay {
+            self.spectator_delay = Duration::from_secs(secs);
+        }
+
+        Ok(())
+    }
+
+    /// Verifies that the combination of config values is usable.
+    pub fn validate(&self) -> Result<()> {
+        // NOTE: The server has no way to start an MJAI engine, so `Mjai` bots would
+        // only ever discard their draws.
+        if self.default_bot == BotKind::Mjai {
+            bail!("The server doesn't support `Mjai` bots");
+        }
+
+        for (name, timeout) in &[
+            ("handshake", self.handshake_timeout),
+            ("idle", self.idle_timeout),
+            ("turn", self.turn_timeout),
+        ] {
+            if *timeout == Duration::from_secs(0) {
+                bail!("The {} timeout must be at least 1 second", name);
+            }
+        }
+
+        if let Some(path) = &self.persistence_path {
+            if path.is_dir() {
+                bail!(
+                    "Persistence path {} is a directory, expected a file",
+                    path.display(),
+                );
+            }
+
+            if let Some(parent) = path.parent() {
+                if !parent.as_os_str().is_empty() && !parent.is_dir() {
+                    bail!(
+                        "The directory for persistence path {} does not exist",
+                        path.display(),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address: ([127, 0, 0, 1], 3030).into(),
+            log_level: Level::TRACE,
+            log_format: LogFormat::Full,
+            starting_balance: 10_000,
+            default_ruleset: Ruleset::Riichi,
+            default_bot: BotKind::Efficiency,
+            handshake_timeout: Duration::from_secs(10),
+            idle_timeout: Duration::from_secs(300),
+            turn_timeout: Duration::from_secs(30),
+            persistence_path: None,
+            min_client_version: Version::new(0, 1, 0),
+            bot_backfill: Some(Duration::from_secs(30)),
+            rating_range: None,
+            spectator_delay: Duration::from_secs(120),
+        }
+    }
+}
+
+/// The format used for log output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable output including the full span context for each event.
+    Full,
+
+    /// Human-readable output with less verbose span information.
+    Compact,
+
+    /// Newline-delimited JSON, for consumption by log aggregation tools.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = mahjong::anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "full" => Ok(LogFormat::Full),
+            "compact" => Ok(LogFormat::Compact),
+            "json" => Ok(LogFormat::Json),
+            _ => bail!(
+                "Invalid log format {:?}, expected one of \"full\", \"compact\", or \"json\"",
+                s,
+            ),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogFormat::Full => "full",
+            LogFormat::Compact => "compact",
+            LogFormat::Json => "json",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Command line flags for the server.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "mahjong-server",
+    about = "Game server for the mahjong prototype"
+)]
+struct Options {
+    /// The config file to load.
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// The address to listen on, e.g. "127.0.0.1:3030".
+    #[structopt(long)]
+    bind_address: Option<SocketAddr>,
+
+    /// The maximum level of log messages to output.
+    #[structopt(long)]
+    log_level: Option<String>,
+
+    /// The format for log output, one of "full", "compact", or "json".
+    #[structopt(long)]
+    log_format: Option<LogFormat>,
+
+    /// The number of points that new accounts start with.
+    #[structopt(long)]
+    starting_balance: Option<u64>,
+
+    /// The ruleset used for new matches.
+    #[structopt(long)]
+    default_ruleset: Option<Ruleset>,
+
+    /// The strategy used by computer-controlled players, e.g. "Efficiency".
+    #[structopt(long)]
+    default_bot: Option<BotKind>,
+
+    /// Seconds to wait for a client to send its handshake.
+    #[structopt(long)]
+    handshake_timeout: Option<u64>,
+
+    /// Seconds a client may be idle before being disconnected.
+    #[structopt(long)]
+    idle_timeout: Option<u64>,
+
+    /// Seconds a player has to take their turn.
+    #[structopt(long)]
+    turn_timeout: Option<u64>,
+
+    /// The file used to persist game data.
+    #[structopt(long, parse(from_os_str))]
+    persistence_path: Option<PathBuf>,
+
+    /// The oldest client version allowed to connect, e.g. "0.1.0".
+    #[structopt(long)]
+    min_client_version: Option<Version>,
+
+    /// Seconds a queued player waits before empty seats are filled with bots, or 0
+    /// to never fill seats with bots.
+    #[structopt(long)]
+    bot_backfill: Option<u64>,
+
+    /// The largest rating difference allowed between matched players. Currently has
+    /// no effect, since ratings aren't updated after matches yet.
+    #[structopt(long)]
+    rating_range: Option<u32>,
+
+    /// Seconds that spectators who can see every player's hand are kept behind the
+    /// live match.
+    #[structopt(long)]
+    spectator_delay: Option<u64>,
+}
+
+impl Options {
+    fn into_layer(self) -> ConfigLayer {
+        ConfigLayer {
+            bind_address: self.bind_address,
+            starting_balance: self.starting_balance,
+            default_ruleset: self.default_ruleset,
+            default_bot: self.default_bot,
+            persistence_path: self.persistence_path,
+            min_client_version: self.min_client_version,
+            log: LogLayer {
+                level: self.log_level,
+                format: self.log_format,
+            },
+            timeouts: TimeoutLayer {
+                handshake: self.handshake_timeout,
+                idle: self.idle_timeout,
+                turn: self.turn_timeout,
+            },
+            matchmaking: MatchmakingLayer {
+                bot_backfill: self.bot_backfill,
+                rating_range: self.rating_range,
+            },
+            spectators: SpectatorLayer {
+                delay: self.spectator_delay,
+            },
+        }
+    }
+}
+
+/// A partial set of config values from a single source.
+///
+/// Matches the structure of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigLayer {
+    bind_address: Option<SocketAddr>,
+    starting_balance: Option<u64>,
+    default_ruleset: Option<Ruleset>,
+    default_bot: Option<BotKind>,
+    persistence_path: Option<PathBuf>,
+    min_client_version: Option<Version>,
+
+    #[serde(default)]
+    log: LogLayer,
+
+    #[serde(default)]
+    timeouts: TimeoutLayer,
+
+    #[serde(default)]
+    matchmaking: MatchmakingLayer,
+
+    #[serde(default)]
+    spectators: SpectatorLayer,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LogLayer {
+    level: Option<String>,
+    format: Option<LogFormat>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimeoutLayer {
+    handshake: Option<u64>,
+    idle: Option<u64>,
+    turn: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MatchmakingLayer {
+    bot_backfill: Option<u64>,
+    rating_range: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpectatorLayer {
+    delay: Option<u64>,
+}
+
+impl ConfigLayer {
+    fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    fn from_env() -> Result<Self> {
+        Ok(Self {
+            bind_address: env_var("BIND_ADDRESS")?,
+            starting_balance: env_var("STARTING_BALANCE")?,
+            default_ruleset: env_var("DEFAULT_RULESET")?,
+            default_bot: env_var("DEFAULT_BOT")?,
+            persistence_path: env_var("PERSISTENCE_PATH")?,
+            min_client_version: env_var("MIN_CLIENT_VERSION")?,
+            log: LogLayer {
+                level: env_var("LOG_LEVEL")?,
+                format: env_var("LOG_FORMAT")?,
+            },
+            timeouts: TimeoutLayer {
+                handshake: env_var("HANDSHAKE_TIMEOUT")?,
+                idle: env_var("IDLE_TIMEOUT")?,
+                turn: env_var("TURN_TIMEOUT")?,
+            },
+            matchmaking: MatchmakingLayer {
+                bot_backfill: env_var("BOT_BACKFILL")?,
+                rating_range: env_var("RATING_RANGE")?,
+            },
+            spectators: SpectatorLayer {
+                delay: env_var("SPECTATOR_DELAY")?,
+            },
+        })
+    }
+}
+
+/// Reads and parses the environment variable `MAHJONG_{name}`, if it is set.
+fn env_var<T>(name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let key = format!("{}{}", ENV_PREFIX, name);
+    match env::var(&key) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|err| anyhow!("Invalid value {:?} for {}: {}", value, key, err)),
+
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(env::VarError::NotUnicode(_)) => bail!("{} is not valid unicode", key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_overrides_defaults() {
+        let layer = toml::from_str::<ConfigLayer>(
+            r#"
+            bind_address = "0.0.0.0:8080"
+            starting_balance = 500
+            min_client_version = "0.1.2"
+            default_bot = "FirstTile"
+
+            [log]
+            level = "info"
+            format = "json"
+
+            [timeouts]
+            turn = 15
+
+            [spectators]
+            delay = 0
+            "#,
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        config.apply(layer).unwrap();
+
+        assert_eq!(SocketAddr::from(([0, 0, 0, 0], 8080)), config.bind_address);
+        assert_eq!(500, config.starting_balance);
+        assert_eq!(Level::INFO, config.log_level);
+        assert_eq!(LogFormat::Json, config.log_format);
+        assert_eq!(Duration::from_secs(15), config.turn_timeout);
+        assert_eq!(Version::new(0, 1, 2), config.min_client_version);
+        assert_eq!(Duration::from_secs(0), config.spectator_delay);
+        assert_eq!(BotKind::FirstTile, config.default_bot);
+
+        // Values not specified in the file keep their defaults.
+        assert_eq!(Config::default().idle_timeout, config.idle_timeout);
+        assert_eq!(None, config.persistence_path);
+    }
+
+    #[test]
+    fn matchmaking_options() {
+        let layer = toml::from_str::<ConfigLayer>(
+            r#"
+            [matchmaking]
+            bot_backfill = 0
+            rating_range = 150
+            "#,
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        config.apply(layer).unwrap();
+
+        // A backfill time of 0 disables bot backfill entirely.
+        assert_eq!(None, config.bot_backfill);
+        assert_eq!(Some(150), config.rating_range);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(toml::from_str::<ConfigLayer>("bind_adress = \"0.0.0.0:8080\"").is_err());
+        assert!(toml::from_str::<ConfigLayer>("[timeouts]\nhandshak = 5").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut config = Config::default();
+        let layer = toml::from_str::<ConfigLayer>("[log]\nlevel = \"loud\"").unwrap();
+        assert!(config.apply(layer).is_err());
+
+        let mut config = Config::default();
+        config.turn_timeout = Duration::from_secs(0);
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.default_bot = BotKind::Mjai;
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,584 @@
+//! Game server for the mahjong prototype.
+//!
+//! The server is split into a library and a thin binary so that it can also be
+//! run in-process, e.g. by the integration tests.
+
+use crate::{
+    auth::{self, TokenHash},
+    client::*,
+    config::Config,
+    match_controller::*,
+    matchmaking::{Matchmaker, MatchmakerProxy},
+    room::{Room, RoomProxy},
+    storage::{Account, MatchRecord, Storage},
+};
+use futures::prelude::*;
+use mahjong::{
+    anyhow::{anyhow, bail, Context, Result},
+    bot::BotKind,
+    match_state::*,
+    messages::*,
+};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use thespian::*;
+use tokio::time::{self, Instant};
+use tracing::*;
+use tracing_futures::Instrument;
+use warp::{ws::WebSocket, Filter};
+
+pub mod auth;
+pub mod client;
+pub mod config;
+pub mod match_controller;
+pub mod matchmaking;
+pub mod room;
+pub mod storage;
+
+/// Spawns the central game state actor, returning a proxy to it.
+///
+/// Fails if the storage backend specified in `config` can't be opened. Must be
+/// called from within a tokio runtime.
+pub fn spawn_game(config: &Config) -> Result<GameStateProxy> {
+    let storage = storage::open(config)?;
+
+    // NOTE: The matchmaker needs a proxy to the game state, so it's spawned before
+    // the game state stage is finished.
+    let (builder, remote) = StageBuilder::new();
+    let matchmaker = Matchmaker::spawn(config, remote.proxy());
+    let stage = builder.finish(GameState::new(config, storage, matchmaker, remote));
+    let game = stage.proxy();
+    tokio::spawn(stage.run());
+
+    Ok(game)
+}
+
+/// Creates the warp filter for the server's routes.
+///
+/// Each client that connects to the `/client` endpoint is given its own
+/// `ClientController`, which communicates with `game` on behalf of the client.
+pub fn routes(
+    game: GameStateProxy,
+    config: Arc<Config>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let client = warp::path("client")
+        .and(warp::ws())
+        .map(move |ws: warp::ws::Ws| {
+            let game = game.clone();
+            let config = config.clone();
+            ws.on_upgrade(move |socket| run_session(socket, game, config))
+        });
+
+    let index = warp::path::end().map(|| warp::reply::html(INDEX_HTML));
+
+    index.or(client)
+}
+
+/// Runs the session for a newly-connected client until the client disconnects.
+async fn run_session(socket: WebSocket, mut game: GameStateProxy, config: Arc<Config>) {
+    let id = game
+        .open_session()
+        .expect("Game state actor has stopped")
+        .await;
+
+    pump_messages(id, socket, game.clone(), config)
+        .instrument(trace_span!("Socket message pump", %id))
+        .await;
+
+    game.end_session(id)
+        .expect("Game state actor has stopped")
+        .await;
+}
+
+/// Performs the handshake with the client and then forwards incoming messages to
+/// the client's `ClientController` until the connection is closed.
+async fn pump_messages(id: ClientId, socket: WebSocket, game: GameStateProxy, config: Arc<Config>) {
+    // Perform the handshake sequence with the client in order to initiate the session.
+    let (mut client, mut stream) =
+        match ClientController::perform_handshake(id, socket, game, config.clone()).await {
+            Ok(result) => result,
+
+            // Log the failed connection attempt and then disconnect from the client.
+            Err(err) => {
+                warn!(%err, "Failed to establish connection with client");
+                return;
+            }
+        };
+
+    // Periodically send heartbeats to the client so that it has something to respond
+    // to, and disconnect the client if it goes too long without sending anything.
+    let heartbeat_interval = config.heartbeat_interval();
+    let mut heartbeat = time::interval_at(Instant::now() + heartbeat_interval, heartbeat_interval);
+    let mut idle_deadline = Instant::now() + config.idle_timeout;
+
+    loop {
+        let message = tokio::select! {
+            message = stream.next() => message,
+
+            _ = heartbeat.tick() => {
+                let result = client
+                    .send_heartbeat()
+                    .expect("Failed to communicate with client actor")
+                    .await;
+
+                if let Err(err) = result {
+                    info!(%err, "Failed to send heartbeat, cancelling connection");
+                    break;
+                }
+
+                continue;
+            }
+
+            _ = time::delay_until(idle_deadline) => {
+                info!("Client was idle for too long, disconnecting");
+                client
+                    .disconnect(DisconnectReason::IdleTimeout)
+                    .expect("Failed to communicate with client actor")
+                    .await;
+                break;
+            }
+        };
+
+        let message = match message {
+            Some(Ok(message)) => message,
+
+            Some(Err(err)) => {
+                info!(%err, "Received error message from socket, cancelling connection");
+                break;
+            }
+
+            None => break,
+        };
+
+        if message.is_close() {
+            info!("Socket connection closed, shutting down client");
+            break;
+        }
+
+        idle_deadline = Instant::now() + config.idle_timeout;
+
+        let result = client
+            .handle_message(message)
+            .expect("Failed to communicate with client actor")
+            .await;
+
+        if let Err(err) = result {
+            error!("Error handling client message: {:?}", err);
+        }
+    }
+
+    client
+        .shut_down()
+        .expect("Failed to communicate with client actor")
+        .await;
+}
+
+/// Starts the server on the configured address, returning the address the server
+/// is bound to and the future that runs the server.
+///
+/// Use port 0 in the bind address to have the OS assign an unused port, e.g. for
+/// running tests. The server doesn't start accepting connections until the
+/// returned future is spawned or awaited.
+///
+/// Fails if the storage backend can't be opened or if the server can't be bound to
+/// the configured address, e.g. because the port is already in use.
+pub fn bind(config: Config) -> Result<(SocketAddr, impl Future<Output = ()>)> {
+    let game = spawn_game(&config)?;
+    let bind_address = config.bind_address;
+    let server = warp::serve(routes(game, Arc::new(config)))
+        .try_bind_ephemeral(bind_address)
+        .with_context(|| format!("Failed to bind server to {}", bind_address))?;
+    Ok(server)
+}
+
+/// Central state for the game.
+///
+/// Account data and match history are kept in the configured storage backend, and
+/// all access to that data goes through this actor.
+#[derive(Debug, Actor)]
+pub struct GameState {
+    storage: Box<dyn Storage>,
+
+    /// Every connected client, including clients that haven't finished the handshake.
+    sessions: HashMap<ClientId, Session>,
+
+    /// The active session for each logged-in account.
+    account_sessions: HashMap<AccountId, ClientId>,
+
+    /// The match that each account is currently playing in, if any.
+    active_matches: HashMap<AccountId, (MatchId, MatchControllerProxy)>,
+
+    matchmaker: MatchmakerProxy,
+
+    /// The private rooms that players can still join, by join code.
+    rooms: HashMap<RoomCode, RoomProxy>,
+
+    client_id_counter: u64,
+
+    starting_balance: u64,
+    default_ruleset: Ruleset,
+    default_bot: BotKind,
+    turn_timeout: Duration,
+    spectator_delay: Duration,
+
+    remote: Remote<Self>,
+}
+
+impl GameState {
+    pub fn new(
+        config: &Config,
+        storage: Box<dyn Storage>,
+        matchmaker: MatchmakerProxy,
+        remote: Remote<Self>,
+    ) -> Self {
+        Self {
+            storage,
+            sessions: Default::default(),
+            account_sessions: Default::default(),
+            active_matches: Default::default(),
+            matchmaker,
+            rooms: Default::default(),
+            client_id_counter: 0,
+            starting_balance: config.starting_balance,
+            default_ruleset: config.default_ruleset,
+            default_bot: config.default_bot,
+            turn_timeout: config.turn_timeout,
+            spectator_delay: config.spectator_delay,
+            remote,
+        }
+    }
+}
+
+#[thespian::actor]
+impl GameState {
+    /// Registers a newly-connected client, returning the ID for the client's session.
+    ///
+    /// The returned ID is guaranteed to be unique among all open sessions. The session
+    /// must be closed with `end_session` once the client disconnects.
+    pub fn open_session(&mut self) -> ClientId {
+        let id = loop {
+            let id = ClientId::new(self.client_id_counter);
+            self.client_id_counter = self.client_id_counter.wrapping_add(1);
+
+            if !self.sessions.contains_key(&id) {
+                break id;
+            }
+        };
+
+        self.sessions.insert(id, Session::Connecting);
+        id
+    }
+
+    /// Associates an open session with the account the client logged into.
+    ///
+    /// Only one session may be active for an account at a time. If the account
+    /// already has an active session, the older session is disconnected.
+    #[tracing::instrument(skip(self, controller))]
+    pub fn start_session(
+        &mut self,
+        id: ClientId,
+        account: AccountId,
+        controller: ClientControllerProxy,
+    ) {
+        if let Some(previous) = self.account_sessions.insert(account, id) {
+            info!(%previous, "Account already has an active session, disconnecting it");
+
+            // NOTE: We don't wait for the disconnect to complete, since the other client
+            // controller may itself be waiting on the game state.
+            if let Some(Session::Active { controller, .. }) = self.sessions.get_mut(&previous) {
+                let _ = controller
+                    .disconnect(DisconnectReason::LoggedInElsewhere)
+                    .expect("Failed to communicate with client actor");
+            }
+        }
+
+        self.sessions.insert(
+            id,
+            Session::Active {
+                account,
+                controller,
+            },
+        );
+    }
+
+    /// Removes a session once the client has disconnected.
+    #[tracing::instrument(skip(self))]
+    pub fn end_session(&mut self, id: ClientId) {
+        let session = match self.sessions.remove(&id) {
+            Some(session) => session,
+            None => {
+                warn!("Attempted to end a session that doesn't exist");
+                return;
+            }
+        };
+
+        // Only remove the account's session if it hasn't already been replaced by a
+        // newer session.
+        if let Session::Active { account, .. } = session {
+            if self.account_sessions.get(&account) == Some(&id) {
+                self.account_sessions.remove(&account);
+            }
+        }
+
+        info!("Ended client session");
+    }
+
+    /// Creates a new account, returning the credentials for the account and its
+    /// initial state.
+    #[tracing::instrument(skip(self))]
+    pub fn create_account(&mut self) -> Result<(Credentials, PlayerState)> {
+        let id = self.storage.next_account_id()?;
+
+        info!(%id, "Creating new player account");
+
+        // Generate the token for the new account. Only the hash of the token is
+        // stored, the token itself is only ever given to the client.
+        let token = auth::generate_token();
+        let token_hash = TokenHash::new(&token);
+
+        // Setup initial state for the account.
+        let data = PlayerState {
+            points: self.starting_balance,
+        };
+
+        // Store the new account.
+        let account = Account {
+            id,
+            token_hash,
+            data: data.clone(),
+            rating: matchmaking::STARTING_RATING,
+        };
+        self.storage.save_account(&account)?;
+
+        Ok((Credentials { id, token }, data))
+    }
+
+    /// Logs into an existing account using the credentials provided by the client.
+    ///
+    /// On success, a new token is issued for the account and the previous token is
+    /// invalidated. The client is given the new credentials as part of the handshake
+    /// response. Returns `None` if the credentials are not valid.
+    #[tracing::instrument(skip(self, credentials), fields(id = %credentials.id))]
+    pub fn log_in(
+        &mut self,
+        credentials: Credentials,
+    ) -> Result<Option<(Credentials, PlayerState)>> {
+        let mut account = match self.storage.load_account(credentials.id)? {
+            Some(account) => account,
+            None => {
+                info!("Attempted to log into an account that doesn't exist");
+                return Ok(None);
+            }
+        };
+
+        if !account.token_hash.verify(&credentials.token) {
+            info!("Rejected invalid token for account");
+            return Ok(None);
+        }
+
+        // Rotate the token for the account.
+        let token = auth::generate_token();
+        account.token_hash = TokenHash::new(&token);
+        self.storage.save_account(&account)?;
+
+        info!("Player logged into existing account");
+
+        let credentials = Credentials {
+            id: account.id,
+            token,
+        };
+        Ok(Some((credentials, account.data)))
+    }
+
+    /// Creates a new match using `ruleset`, or the default ruleset if `None`.
+    ///
+    /// Players take their seats with `MatchController::join`, and then the match is
+    /// played once `MatchController::start` is called. Any seats that no player
+    /// joined are played by the computer.
+    #[tracing::instrument(skip(self))]
+    pub fn create_match(&mut self, ruleset: Option<Ruleset>) -> Result<MatchControllerProxy> {
+        let id = self.storage.next_match_id()?;
+
+        let ruleset = ruleset.unwrap_or(self.default_ruleset);
+        info!(%id, ?ruleset, "Creating a new match");
+
+        Ok(MatchController::spawn(
+            id,
+            ruleset,
+            self.default_bot,
+            self.turn_timeout,
+            self.spectator_delay,
+            self.remote.proxy(),
+        ))
+    }
+
+    /// Registers a match that has just started as the active match for each of
+    /// `accounts`.
+    #[tracing::instrument(skip(self, controller))]
+    pub fn match_started(
+        &mut self,
+        id: MatchId,
+        controller: MatchControllerProxy,
+        accounts: Vec<AccountId>,
+    ) {
+        for account in accounts {
+            self.active_matches
+                .insert(account, (id, controller.clone()));
+        }
+    }
+
+    /// Creates a private room hosted by `host`, with a new match using `ruleset`.
+    ///
+    /// The host still needs to join the room, the same as any other player.
+    #[tracing::instrument(skip(self))]
+    pub fn create_room(
+        &mut self,
+        name: String,
+        ruleset: Option<Ruleset>,
+        host: AccountId,
+    ) -> Result<RoomProxy> {
+        let name = name.trim().to_owned();
+        if name.is_empty() || name.chars().count() > room::MAX_NAME_LENGTH {
+            bail!(InvalidRequest::new(format!(
+                "Room name must be between 1 and {} characters",
+                room::MAX_NAME_LENGTH,
+            )));
+        }
+
+        let ruleset = ruleset.unwrap_or(self.default_ruleset);
+        let controller = self.create_match(Some(ruleset))?;
+
+        let code = loop {
+            let code = room::generate_code();
+            if !self.rooms.contains_key(&code) {
+                break code;
+            }
+        };
+
+        info!(%code, "Creating private room");
+
+        let room = Room::spawn(
+            code.clone(),
+            name,
+            ruleset,
+            host,
+            controller,
+            self.remote.proxy(),
+        );
+        self.rooms.insert(code, room.clone());
+
+        Ok(room)
+    }
+
+    /// Returns the room with the join code `code`, if it's still open.
+    pub fn find_room(&self, code: RoomCode) -> Option<RoomProxy> {
+        self.rooms.get(&RoomCode::new(code.as_str())).cloned()
+    }
+
+    /// Removes a room once its match has started or its host has left, so that no
+    /// more players can join it.
+    #[tracing::instrument(skip(self))]
+    pub fn close_room(&mut self, code: RoomCode) {
+        if self.rooms.remove(&code).is_some() {
+            info!("Closed private room");
+        }
+    }
+
+    /// Returns the matchmaking rating for `account`.
+    pub fn rating(&self, account: AccountId) -> Result<u32> {
+        let account = self
+            .storage
+            .load_account(account)?
+            .ok_or_else(|| anyhow!("Account {} does not exist", account))?;
+        Ok(account.rating)
+    }
+
+    pub fn matchmaker(&self) -> MatchmakerProxy {
+        self.matchmaker.clone()
+    }
+
+    /// Returns the match that `account` is currently playing in, if any.
+    pub fn active_match(&self, account: AccountId) -> Option<(MatchId, MatchControllerProxy)> {
+        self.active_matches.get(&account).cloned()
+    }
+
+    /// Returns the match with the ID `id`, if it's still being played.
+    pub fn find_match(&self, id: MatchId) -> Option<MatchControllerProxy> {
+        self.active_matches
+            .values()
+            .find(|(match_id, _)| *match_id == id)
+            .map(|(_, controller)| controller.clone())
+    }
+
+    /// Stores the record of a completed match.
+    #[tracing::instrument(skip(self, record), fields(id = %record.id))]
+    pub fn finish_match(&mut self, record: MatchRecord) {
+        info!("Recording completed match");
+
+        // The players can no longer rejoin the match, but they may have already started
+        // another match, so only remove the ones that still point to this match.
+        for account in record.players.values() {
+            if let Some((id, _)) = self.active_matches.get(account) {
+                if *id == record.id {
+                    self.active_matches.remove(account);
+                }
+            }
+        }
+
+        // NOTE: There's no one to report the error to at this point, since the match
+        // has already ended, so we log it and move on.
+        if let Err(err) = self.storage.save_match(&record) {
+            error!(%err, "Failed to save match record");
+        }
+    }
+}
+
+/// The state of a connected client's session.
+#[derive(Debug)]
+enum Session {
+    /// The client has connected but hasn't finished the handshake.
+    Connecting,
+
+    /// The client has logged into an account.
+    Active {
+        account: AccountId,
+        controller: ClientControllerProxy,
+    },
+}
+
+static INDEX_HTML: &str = r#"
+<!DOCTYPE html>
+<html>
+    <head>
+        <title>Warp Chat</title>
+    </head>
+    <body>
+        <h1>warp chat</h1>
+        <div id="chat">
+            <p><em>Connecting...</em></p>
+        </div>
+        <input type="text" id="text" />
+        <button type="button" id="send">Send</button>
+        <script type="text/javascript">
+        var uri = 'ws://' + location.host + '/client';
+        var ws = new WebSocket(uri);
+        function message(data) {
+            var line = document.createElement('p');
+            line.innerText = data;
+            chat.appendChild(line);
+        }
+        ws.onopen = function() {
+            chat.innerHTML = "<p><em>Connected!</em></p>";
+        }
+        ws.onmessage = function(msg) {
+            message(msg.data);
+        };
+        send.onclick = function() {
+            var msg = text.value;
+            ws.send(msg);
+            text.value = '';
+            message('<You>: ' + msg);
+        };
+        </script>
+    </body>
+</html>
+"#;
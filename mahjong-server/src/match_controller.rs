@@ -1,10 +1,34 @@
-use crate::client::ClientControllerProxy;
-use mahjong::{anyhow::*, match_state::*, messages::MatchEvent, tile};
-use rand::{seq::SliceRandom, SeedableRng};
+use crate::{
+    client::{ClientControllerProxy, ClientId, InvalidRequest},
+    storage::MatchRecord,
+    GameStateProxy,
+};
+use mahjong::{
+    anyhow::*,
+    bot::{Bot, BotKind, BotView, TurnDecision},
+    match_state::*,
+    messages::{AccountId, MatchEvent, ResumeMatchResponse, ResyncResponse, SpectateResponse},
+    render::{self, Style},
+    spectator::{SpectatorEvent, SpectatorSnapshot, SpectatorView},
+    strum::IntoEnumIterator,
+    sync::MatchSnapshot,
+    tile,
+};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
 use rand_pcg::*;
-use std::collections::HashMap;
-use thespian::Actor;
+use std::{
+    collections::HashMap,
+    sync::mpsc as std_mpsc,
+    thread,
+    time::{Duration, SystemTime},
+};
+use thespian::{Actor, Remote, StageBuilder};
 use tile::{TileId, Wind};
+use tokio::{
+    runtime::Handle,
+    sync::mpsc,
+    time::{self, Instant},
+};
 use tracing::*;
 
 #[derive(Debug, Actor)]
@@ -12,12 +36,86 @@ pub struct MatchController {
     rng: Pcg64Mcg,
     state: MatchState,
 
-    /// Mapping of which client controls which player seat. Key is the index of the
-    clients: HashMap<Wind, ClientControllerProxy>,
+    // TODO: Apply the ruleset to the match logic once we support more than one set
+    // of rules.
+    ruleset: Ruleset,
+
+    /// Mapping of which client controls which player seat, along with the ID of the
+    /// client's session.
+    clients: HashMap<Wind, (ClientId, ClientControllerProxy)>,
+
+    /// The account for each seat controlled by a player.
+    ///
+    /// Seats remain here even if the player's client disconnects, so that the player
+    /// can rejoin the match later.
+    players: HashMap<Wind, AccountId>,
+
+    /// The bots playing each seat that doesn't have a connected client, either
+    /// because no player joined the seat or because the player's client
+    /// disconnected. `None` while every seat has a client, and once the match has
+    /// finished.
+    bots: Option<BotRunner>,
+
+    /// The strategy used for the bots in `bots`.
+    bot_kind: BotKind,
+
+    /// The bots that have declared riichi, along with the index of their riichi tile
+    /// within their discards.
+    ///
+    /// Riichi isn't part of the match state yet, so it's only tracked here to keep
+    /// each bot's view of the match consistent with its own decisions.
+    riichi: HashMap<Wind, usize>,
+
+    /// Every event broadcast so far, in order.
+    events: Vec<MatchEvent>,
+
+    /// When each event in `events` was broadcast.
+    event_times: Vec<Instant>,
+
+    /// The state of the match before the first event, which new spectators start
+    /// from.
+    initial: MatchSnapshot,
+
+    /// The clients watching the match. Unlike the clients in `clients`, spectators
+    /// only see the parts of the match allowed by their `SpectatorView`.
+    spectators: HashMap<ClientId, Spectator>,
+
+    /// How long events are held back from spectators with `SpectatorView::Revealed`.
+    spectator_delay: Duration,
+
+    /// When `start` was called, or `None` if the match hasn't started. Players can
+    /// only take or give up seats before the match starts, and can only act once it
+    /// has started.
+    started_at: Option<Instant>,
+
+    /// The game state actor, which is notified once the match is complete.
+    game: GameStateProxy,
+
+    /// How long players have to take their turn before a discard is made for them.
+    turn_timeout: Duration,
+
+    /// Counter incremented at the start of each turn, used to identify which turn a
+    /// turn timer or bot decision was for.
+    ///
+    /// Also incremented when the current seat changes between a player and a bot, so
+    /// that timers and decisions from before the change are ignored.
+    turn: u64,
+
+    remote: Remote<Self>,
 }
 
 impl MatchController {
-    pub fn new(id: MatchId) -> Self {
+    /// Creates a new match and spawns the actor for it.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn spawn(
+        id: MatchId,
+        ruleset: Ruleset,
+        bot_kind: BotKind,
+        turn_timeout: Duration,
+        spectator_delay: Duration,
+        game: GameStateProxy,
+    ) -> MatchControllerProxy {
         let mut rng = Pcg64Mcg::from_entropy();
 
         // Generate the tileset and shuffle it.
@@ -29,25 +127,286 @@ impl MatchController {
         // For the east player, have them draw the tile for their first turn.
         state.draw_for_player(Wind::East).unwrap();
 
-        Self {
+        let initial = state.snapshot();
+
+        let (builder, remote) = StageBuilder::new();
+        let stage = builder.finish(Self {
             rng,
             state,
+            ruleset,
             clients: Default::default(),
-        }
+            players: Default::default(),
+            bots: None,
+            bot_kind,
+            riichi: Default::default(),
+            events: Default::default(),
+            event_times: Default::default(),
+            initial,
+            spectators: Default::default(),
+            spectator_delay,
+            started_at: None,
+            game,
+            turn_timeout,
+            turn: 0,
+            remote,
+        });
+        let proxy = stage.proxy();
+        tokio::spawn(stage.run());
+
+        proxy
     }
 
+    /// Sends `event` to the players, bots, and spectators of the match.
+    ///
+    /// Players receive the full event, whereas bots and spectators each receive the
+    /// version of the event for their view of the match.
     fn broadcast(&mut self, event: MatchEvent) {
         trace!(
             ?event,
-            "Broadcasting event to {} connected client(s)",
-            self.clients.len()
+            "Broadcasting event to {} connected client(s) and {} spectator(s)",
+            self.clients.len(),
+            self.spectators.len(),
         );
 
-        for client in self.clients.values_mut() {
+        let sequence = self.events.len();
+        let checksum = self.state.checksum();
+        for (_, client) in self.clients.values_mut() {
             client
-                .send_event(event.clone())
+                .send_event(sequence, event.clone(), checksum)
                 .expect("Disconnected from client controller");
         }
+
+        if let Some(bots) = &self.bots {
+            for &seat in &bots.seats {
+                let view = bot_view(&self.state, &self.riichi, seat);
+                let event = view.redact(&event);
+                bots.observe(seat, view, event);
+            }
+        }
+
+        let now = Instant::now();
+        for spectator in self.spectators.values() {
+            spectator.send(sequence, &event, now, self.spectator_delay);
+        }
+
+        self.events.push(event);
+        self.event_times.push(now);
+    }
+
+    /// Discards `tile` for `seat` and then plays out the turns for any
+    /// computer-controlled players until it's a client's turn or the match ends.
+    fn perform_discard(&mut self, seat: Wind, tile: TileId) -> Result<()> {
+        // TODO: Provide more robust state transitions such that it's not possible to get
+        // this far after the match has ended, e.g. a `MatchControllerState` enum that has
+        // different states for whether the match is ongoing or completed.
+        if self.started_at.is_none() {
+            bail!(InvalidRequest::new("Match hasn't started yet"));
+        }
+
+        if self.state.wall.is_empty() {
+            bail!(InvalidRequest::new("Match already finished"));
+        }
+
+        self.state.discard_tile(seat, tile)?;
+
+        trace!("Successfully discarded tile");
+
+        // Broadcast the discard event to all connected clients.
+        self.broadcast(MatchEvent::TileDiscarded { seat, tile });
+
+        self.advance()
+    }
+
+    /// Draws a tile for the next player, or ends the match if the wall is empty.
+    ///
+    /// If the next seat is played by a bot, the bot takes its turn in the background
+    /// and the match advances again once it has decided, see `bot_turn_taken`.
+    fn advance(&mut self) -> Result<()> {
+        // If the match is over, broadcast an event notifying all clients of the outcome.
+        if self.state.wall.is_empty() {
+            self.finish();
+            return Ok(());
+        }
+
+        let player = self.state.current_turn;
+
+        // Draw the tile for the next player.
+        let draw = self.state.draw_for_player(player)?;
+        self.turn += 1;
+        self.broadcast(MatchEvent::TileDrawn {
+            seat: player,
+            tile: draw,
+        });
+
+        if self.clients.contains_key(&player) {
+            trace!(seat = ?player, "Player at current seat, waiting for player action");
+            self.start_turn_timer();
+
+            // A player who makes the last draw doesn't get to discard, whereas a bot
+            // discards before the match ends.
+            if self.state.wall.is_empty() {
+                self.finish();
+            }
+
+            return Ok(());
+        }
+
+        self.request_bot_turn(player)
+    }
+
+    /// Hands `seat` to a new bot, which plays the seat until `remove_bot` is called.
+    ///
+    /// The bot only observes events from this point on, so it relies on its view of
+    /// the match for anything that happened before.
+    fn add_bot(&mut self, seat: Wind) {
+        let bot = self.bot_kind.create(self.rng.gen());
+        if self.bots.is_none() {
+            self.bots = Some(BotRunner::spawn(self.remote.proxy()));
+        }
+
+        if let Some(bots) = &mut self.bots {
+            bots.add(seat, bot);
+        }
+    }
+
+    /// Takes `seat` back from the bot playing it, returning whether there was one.
+    ///
+    /// The bots' thread is shut down once no seats are left for it to play.
+    fn remove_bot(&mut self, seat: Wind) -> bool {
+        let bots = match &mut self.bots {
+            Some(bots) if bots.seats.contains(&seat) => bots,
+            _ => return false,
+        };
+
+        bots.remove(seat);
+        if bots.seats.is_empty() {
+            self.bots = None;
+        }
+
+        true
+    }
+
+    /// Asks the bot in `seat` to take its turn. `bot_turn_taken` is called with the
+    /// bot's decision once it has decided.
+    fn request_bot_turn(&self, seat: Wind) -> Result<()> {
+        let bots = self
+            .bots
+            .as_ref()
+            .filter(|bots| bots.seats.contains(&seat))
+            .ok_or_else(|| anyhow!("No bot is playing seat {:?}", seat))?;
+
+        let view = bot_view(&self.state, &self.riichi, seat);
+        bots.take_turn(seat, view, self.turn);
+        Ok(())
+    }
+
+    /// Performs `decision` for the bot in `seat`, broadcasting the resulting discard.
+    ///
+    /// If the bot tries to discard a tile that isn't in its hand, or to discard
+    /// anything other than its draw after declaring riichi, its current draw is
+    /// discarded instead.
+    fn play_bot_turn(&mut self, seat: Wind, decision: TurnDecision) -> Result<()> {
+        let view = bot_view(&self.state, &self.riichi, seat);
+        let draw = match self.state.player(seat).current_draw() {
+            Some(draw) => draw.id,
+            None => self.state.player(seat).tiles()[0].id,
+        };
+
+        // NOTE: Bots aren't allowed to declare tsumo (see `bot_view`), so a bot that
+        // does anyway discards its draw.
+        let mut discard = match decision {
+            TurnDecision::Discard(tile) => tile,
+
+            TurnDecision::Riichi(tile) => {
+                if view.can_riichi(tile) {
+                    self.riichi.insert(seat, view.hand.discards().len());
+                } else {
+                    warn!(?seat, "Bot declared riichi when it couldn't, ignoring");
+                }
+                tile
+            }
+
+            TurnDecision::Tsumo => {
+                warn!(
+                    ?seat,
+                    "Bot declared tsumo, but the match doesn't support wins"
+                );
+                draw
+            }
+        };
+
+        // Once a bot has declared riichi, it can only discard its draws.
+        let riichi_tile = self.riichi.get(&seat) == Some(&view.hand.discards().len());
+        if self.riichi.contains_key(&seat) && !riichi_tile && discard != draw {
+            warn!(
+                ?seat,
+                "Bot in riichi tried to change its hand, discarding its draw"
+            );
+            discard = draw;
+        }
+
+        info!(
+            ?seat,
+            ?decision,
+            discard = %tile::by_id(discard),
+            hand = %render::hand(self.state.player(seat), seat, Style::Ascii),
+            "Performing action for computer-controlled player",
+        );
+
+        if let Err(err) = self.state.discard_tile(seat, discard) {
+            error!(%err, "Bot made an invalid discard, discarding its draw instead");
+            discard = draw;
+            self.state.discard_tile(seat, discard)?;
+        }
+
+        self.broadcast(MatchEvent::TileDiscarded {
+            seat,
+            tile: discard,
+        });
+
+        Ok(())
+    }
+
+    /// Starts the timer for the current turn. If the player hasn't taken their turn
+    /// once the timer expires, a discard is made for them.
+    fn start_turn_timer(&mut self) {
+        let turn = self.turn;
+        let timeout = self.turn_timeout;
+        let mut proxy = self.remote.proxy();
+
+        tokio::spawn(async move {
+            time::delay_for(timeout).await;
+
+            // NOTE: The match may have already ended and its actor shut down, in which
+            // case there's no turn left to time out.
+            if let Ok(result) = proxy.turn_timed_out(turn) {
+                result.await;
+            }
+        });
+    }
+
+    /// Broadcasts the end of the match and sends the record of the match to the game
+    /// state to be stored.
+    fn finish(&mut self) {
+        self.broadcast(MatchEvent::MatchEnded);
+
+        let record = MatchRecord {
+            id: self.state.id,
+            ruleset: self.ruleset,
+            players: self.players.clone(),
+            events: self.events.clone(),
+            finished_at: SystemTime::now(),
+        };
+
+        // NOTE: We don't need to wait for the record to be stored, the message is sent
+        // as soon as the proxy method is called.
+        let _ = self
+            .game
+            .finish_match(record)
+            .expect("Game state actor has stopped");
+
+        // Let the bots finish observing the end of the match and shut down.
+        self.bots = None;
     }
 }
 
@@ -57,73 +416,531 @@ impl MatchController {
         self.state.id
     }
 
-    pub fn join(&mut self, controller: ClientControllerProxy, seat: Wind) -> Result<MatchState> {
-        if self.clients.contains_key(&seat) {
-            bail!("Seat is already occupied");
+    pub fn ruleset(&self) -> Ruleset {
+        self.ruleset
+    }
+
+    /// Seats a player in the match before it starts, returning the initial state of
+    /// the match.
+    pub fn join(
+        &mut self,
+        client: ClientId,
+        controller: ClientControllerProxy,
+        account: AccountId,
+        seat: Wind,
+    ) -> Result<MatchSnapshot> {
+        if self.started_at.is_some() {
+            bail!(InvalidRequest::new("Match has already started"));
         }
 
-        self.clients.insert(seat, controller);
+        if self.players.contains_key(&seat) {
+            bail!(InvalidRequest::new("Seat is already occupied"));
+        }
 
-        Ok(self.state.clone())
+        self.clients.insert(seat, (client, controller));
+        self.players.insert(seat, account);
+
+        Ok(self.state.snapshot())
     }
 
-    /// Returns the updated match state if the requested discard is valid.
+    /// Removes the player from `seat` before the match starts, leaving the seat free
+    /// for another player to join.
     #[tracing::instrument(skip(self))]
-    pub async fn discard_tile(&mut self, player: Wind, tile: TileId) -> Result<()> {
-        trace!("Attempting to discard tile");
+    pub fn unseat(&mut self, seat: Wind) -> Result<()> {
+        if self.started_at.is_some() {
+            bail!(InvalidRequest::new("Match has already started"));
+        }
 
-        // TODO: Provide more robust state transitions such that it's not possible to get
-        // this far after the match has ended, e.g. a `MatchControllerState` enum that has
-        // different states for whether the match is ongoing or completed.
+        self.clients.remove(&seat);
+        self.players.remove(&seat);
+        Ok(())
+    }
+
+    /// Starts play once every player has joined the match, and registers the match
+    /// as the active match for each player.
+    ///
+    /// Any seats that no player joined are played by a bot. If East is one of them,
+    /// it takes its first turn immediately.
+    pub fn start(&mut self) -> Result<()> {
+        if self.started_at.is_some() {
+            bail!(InvalidRequest::new("Match has already started"));
+        }
+        let now = Instant::now();
+        self.started_at = Some(now);
+
+        for spectator in self.spectators.values() {
+            spectator.reveal(&self.initial, now, self.spectator_delay);
+        }
+
+        // NOTE: We don't need to wait for the match to be registered, the message is
+        // sent as soon as the proxy method is called.
+        let _ = self
+            .game
+            .match_started(
+                self.state.id,
+                self.remote.proxy(),
+                self.players.values().copied().collect(),
+            )
+            .expect("Game state actor has stopped");
+
+        // NOTE: A player whose client disconnected before the match started is
+        // played by a bot until they rejoin, the same as if they'd disconnected
+        // during the match.
+        let seats = Wind::iter()
+            .filter(|seat| !self.clients.contains_key(seat))
+            .collect::<Vec<_>>();
+        info!(bots = seats.len(), kind = ?self.bot_kind, "Starting match");
+        for seat in seats {
+            self.add_bot(seat);
+        }
+
+        let seat = self.state.current_turn;
+        if self.clients.contains_key(&seat) {
+            self.start_turn_timer();
+            return Ok(());
+        }
+
+        self.request_bot_turn(seat)
+    }
+
+    /// Rejoins the match after the player's previous client disconnected, returning
+    /// the current state of the match and the events after the first
+    /// `events_received`.
+    ///
+    /// Takes the seat back from the bot that played it while the player was away. If
+    /// it's the player's turn, they get a full turn regardless of how long the bot
+    /// had been deciding. Replaces any client that is still registered for the
+    /// player's seat.
+    #[tracing::instrument(skip(self, controller))]
+    pub fn rejoin(
+        &mut self,
+        client: ClientId,
+        controller: ClientControllerProxy,
+        account: AccountId,
+        events_received: usize,
+    ) -> Result<ResumeMatchResponse> {
         if self.state.wall.is_empty() {
-            bail!("Match already finished");
+            bail!(InvalidRequest::new("Match already finished"));
         }
 
-        // TODO: Verify that the client submitting the action is actually the one that
-        // controls the player.
+        let seat = self
+            .players
+            .iter()
+            .find(|(_, &player)| player == account)
+            .map(|(&seat, _)| seat)
+            .ok_or_else(|| {
+                InvalidRequest::new(format!("Account {} isn't playing in the match", account))
+            })?;
 
-        self.state.discard_tile(player, tile)?;
+        if events_received > self.events.len() {
+            bail!(InvalidRequest::new(format!(
+                "Client received {} events, but only {} have occurred",
+                events_received,
+                self.events.len(),
+            )));
+        }
 
-        trace!("Successfully discarded tile");
+        info!(?seat, "Client rejoined the match");
 
-        // Broadcast the discard event to all connected clients.
-        self.broadcast(MatchEvent::TileDiscarded { seat: player, tile });
+        self.clients.insert(seat, (client, controller));
+
+        if self.remove_bot(seat) && self.state.current_turn == seat {
+            self.turn += 1;
+            self.start_turn_timer();
+        }
+
+        Ok(ResumeMatchResponse {
+            snapshot: self.state.snapshot(),
+            seat,
+            missed_events: self.events[events_received..].to_vec(),
+        })
+    }
+
+    /// Returns a snapshot of the current state of the match, for a client whose local
+    /// state is out of sync.
+    pub fn resync(&self, id: MatchId) -> Result<ResyncResponse> {
+        if id != self.state.id {
+            bail!(InvalidRequest::new(format!(
+                "Requested resync for match {}, but client is in match {}",
+                id, self.state.id,
+            )));
+        }
+
+        Ok(ResyncResponse {
+            snapshot: self.state.snapshot(),
+            events_applied: self.events.len(),
+        })
+    }
+
+    /// Adds `client` as a spectator, returning the state of the match before its first
+    /// event with every player's hand hidden.
+    ///
+    /// Every event in the match so far is then sent to the client, followed by new
+    /// events as they happen. With `SpectatorView::Revealed`, the starting hands are
+    /// revealed once the spectator delay has passed since the match started, before
+    /// any events. Players can't spectate a match that they're playing in.
+    #[tracing::instrument(skip(self, controller))]
+    pub fn spectate(
+        &mut self,
+        client: ClientId,
+        controller: ClientControllerProxy,
+        account: AccountId,
+        view: SpectatorView,
+    ) -> Result<SpectateResponse> {
+        if self.state.wall.is_empty() {
+            bail!(InvalidRequest::new("Match already finished"));
+        }
 
-        while !self.state.wall.is_empty() {
-            let player = self.state.current_turn;
+        if self.players.values().any(|&player| player == account) {
+            bail!(InvalidRequest::new(
+                "Cannot spectate a match you're playing in"
+            ));
+        }
 
-            // Draw the tile for the next player.
-            let draw = self.state.draw_for_player(player)?;
-            self.broadcast(MatchEvent::TileDrawn {
-                seat: player,
-                tile: draw,
-            });
+        info!(
+            spectators = self.spectators.len() + 1,
+            "Client started spectating"
+        );
 
-            if self.clients.contains_key(&player) {
-                trace!(seat = ?player, "Client at current seat, waiting for player action");
-                break;
+        let spectator = Spectator::new(self.state.id, view, controller);
+        if let Some(started_at) = self.started_at {
+            spectator.reveal(&self.initial, started_at, self.spectator_delay);
+        }
+        for (sequence, (event, &time)) in self.events.iter().zip(&self.event_times).enumerate() {
+            spectator.send(sequence, event, time, self.spectator_delay);
+        }
+        self.spectators.insert(client, spectator);
+
+        Ok(SpectateResponse {
+            snapshot: SpectatorSnapshot::new(&self.initial, SpectatorView::Hidden),
+        })
+    }
+
+    /// Stops sending events to a spectator.
+    ///
+    /// Events that were already queued for the spectator may still be sent.
+    #[tracing::instrument(skip(self))]
+    pub fn stop_spectating(&mut self, client: ClientId) {
+        if self.spectators.remove(&client).is_some() {
+            info!("Client stopped spectating");
+        }
+    }
+
+    /// Removes `client` from the match, e.g. because the client disconnected.
+    ///
+    /// The player keeps their seat. Once the match has started, a bot plays the seat
+    /// until the player rejoins the match, taking over the current turn if it's the
+    /// player's. Does nothing if `client` no longer controls the seat, e.g. because
+    /// the player already rejoined from a new client.
+    #[tracing::instrument(skip(self))]
+    pub fn leave(&mut self, seat: Wind, client: ClientId) {
+        match self.clients.get(&seat) {
+            Some((current, _)) if *current == client => {}
+            _ => {
+                debug!("Client doesn't control the seat, ignoring");
+                return;
             }
+        }
 
-            // Automatically discard the first tile in the player's hand.
-            let auto_discard = self.state.player(player).tiles()[0].id;
-            info!(
-                seat = ?player,
-                discard = ?auto_discard,
-                "Performing action for computer-controlled player",
-            );
+        self.clients.remove(&seat);
+        info!("Client left the match");
 
-            self.state.discard_tile(player, auto_discard)?;
-            self.broadcast(MatchEvent::TileDiscarded {
-                seat: player,
-                tile: auto_discard,
-            });
+        // NOTE: Seats without a client are handed to bots when the match starts, and
+        // there's nothing left to play once the wall is empty.
+        if self.started_at.is_none() || self.state.wall.is_empty() {
+            return;
         }
 
-        // If the match is over, broadcast an event notifying all clients of the outcome.
-        if self.state.wall.is_empty() {
-            self.broadcast(MatchEvent::MatchEnded);
+        self.add_bot(seat);
+        if self.state.current_turn == seat {
+            self.turn += 1;
+            if let Err(err) = self.request_bot_turn(seat) {
+                error!(%err, "Failed to hand the current turn to a bot");
+            }
         }
+    }
 
-        Ok(())
+    /// Discards `tile` for `player` on behalf of `client`, if the discard is valid.
+    ///
+    /// `id` is the match the client requested the discard for, and the request is
+    /// rejected if it's for a different match or if `client` doesn't control the
+    /// player's seat.
+    #[tracing::instrument(skip(self))]
+    pub async fn discard_tile(
+        &mut self,
+        id: MatchId,
+        client: ClientId,
+        player: Wind,
+        tile: TileId,
+    ) -> Result<()> {
+        trace!("Attempting to discard tile");
+
+        if id != self.state.id {
+            bail!(InvalidRequest::new(format!(
+                "Requested discard for match {}, but client is in match {}",
+                id, self.state.id,
+            )));
+        }
+
+        match self.clients.get(&player) {
+            Some((current, _)) if *current == client => {}
+            _ => bail!(InvalidRequest::new(format!(
+                "Client doesn't control the {:?} seat",
+                player,
+            ))),
+        }
+
+        self.perform_discard(player, tile)
+    }
+
+    /// Automatically discards for the current player if they haven't taken their turn
+    /// before the turn timer expired.
+    ///
+    /// `turn` is the turn that the timer was started for. If the player has already
+    /// taken their turn, the timer is stale and is ignored.
+    #[tracing::instrument(skip(self))]
+    pub fn turn_timed_out(&mut self, turn: u64) {
+        if turn != self.turn || self.state.wall.is_empty() {
+            return;
+        }
+
+        let seat = self.state.current_turn;
+        let tile = match self.state.player(seat).current_draw() {
+            Some(draw) => draw.id,
+            None => self.state.player(seat).tiles()[0].id,
+        };
+
+        info!(?seat, discard = %tile::by_id(tile), "Turn timed out, discarding automatically");
+
+        if let Err(err) = self.perform_discard(seat, tile) {
+            error!(%err, "Failed to perform automatic discard");
+        }
+    }
+
+    /// Performs the decision that the bot in `seat` made for `turn`, then continues
+    /// the match.
+    ///
+    /// Ignores the decision if the match has moved on since the bot was asked to
+    /// take its turn.
+    #[tracing::instrument(skip(self))]
+    pub fn bot_turn_taken(&mut self, turn: u64, seat: Wind, decision: TurnDecision) {
+        if turn != self.turn || seat != self.state.current_turn {
+            debug!("Bot decision is for a stale turn, ignoring");
+            return;
+        }
+
+        let result = self
+            .play_bot_turn(seat, decision)
+            .and_then(|_| self.advance());
+        if let Err(err) = result {
+            error!(%err, "Failed to perform bot's turn");
+        }
+    }
+}
+
+/// Creates the view of `state` for the bot in `seat`.
+///
+/// The match logic can't end a match with a win yet, so bots aren't allowed to
+/// declare tsumo.
+fn bot_view(state: &MatchState, riichi: &HashMap<Wind, usize>, seat: Wind) -> BotView {
+    BotView {
+        riichi: riichi.clone(),
+        wins_allowed: false,
+        ..BotView::new(state, seat)
+    }
+}
+
+/// Runs the bots for a match on a dedicated thread, so that slow decisions and
+/// blocking I/O (e.g. `MonteCarloBot`'s search and `MjaiBot`'s engine) don't stall
+/// the async runtime.
+///
+/// The thread lives as long as the match, so it isn't taken from tokio's blocking
+/// pool, which is meant for short-lived work.
+///
+/// Requests are handled in the order they're sent, so each bot observes every event
+/// before it takes its next turn.
+#[derive(Debug)]
+struct BotRunner {
+    /// The seats played by bots.
+    seats: Vec<Wind>,
+
+    requests: std_mpsc::Sender<BotRequest>,
+}
+
+#[derive(Debug)]
+enum BotRequest {
+    Observe {
+        seat: Wind,
+        view: BotView,
+        event: SpectatorEvent,
+    },
+
+    TakeTurn {
+        seat: Wind,
+        view: BotView,
+        turn: u64,
+    },
+
+    Add {
+        seat: Wind,
+        bot: Box<dyn Bot>,
+    },
+
+    Remove {
+        seat: Wind,
+    },
+}
+
+impl BotRunner {
+    /// Starts the thread for the bots, which sends each bot's decisions back to the
+    /// match through `controller`. Bots are added to the thread with `add`.
+    ///
+    /// The thread exits once the runner is dropped and every request has been
+    /// handled.
+    ///
+    /// Must be called from within a tokio runtime, which the thread uses to message
+    /// the match.
+    fn spawn(mut controller: MatchControllerProxy) -> Self {
+        let (requests, receiver) = std_mpsc::channel();
+        let runtime = Handle::current();
+
+        thread::spawn(move || {
+            let mut bots = HashMap::<Wind, Box<dyn Bot>>::new();
+            for request in receiver {
+                match request {
+                    BotRequest::Observe { seat, view, event } => {
+                        if let Some(bot) = bots.get_mut(&seat) {
+                            bot.observe(&view, &event);
+                        }
+                    }
+
+                    BotRequest::TakeTurn { seat, view, turn } => {
+                        let bot = match bots.get_mut(&seat) {
+                            Some(bot) => bot,
+                            None => continue,
+                        };
+                        let decision = bot.take_turn(&view);
+
+                        // NOTE: We don't need to wait for the match to handle the
+                        // decision, the message is sent as soon as the proxy method is
+                        // called. If the match's actor has already shut down, there's
+                        // nothing left for the bots to do.
+                        let sent = runtime
+                            .enter(|| controller.bot_turn_taken(turn, seat, decision).is_ok());
+                        if !sent {
+                            break;
+                        }
+                    }
+
+                    BotRequest::Add { seat, bot } => {
+                        bots.insert(seat, bot);
+                    }
+
+                    BotRequest::Remove { seat } => {
+                        bots.remove(&seat);
+                    }
+                }
+            }
+        });
+
+        Self {
+            seats: Vec::new(),
+            requests,
+        }
+    }
+
+    /// Notifies the bot in `seat` of `event`, with `view` already including the
+    /// event.
+    fn observe(&self, seat: Wind, view: BotView, event: SpectatorEvent) {
+        // NOTE: The thread only exits early if the match's actor has shut down.
+        let _ = self
+            .requests
+            .send(BotRequest::Observe { seat, view, event });
+    }
+
+    /// Asks the bot in `seat` to take its turn for `turn`.
+    fn take_turn(&self, seat: Wind, view: BotView, turn: u64) {
+        let _ = self
+            .requests
+            .send(BotRequest::TakeTurn { seat, view, turn });
+    }
+
+    /// Has `bot` play `seat`.
+    fn add(&mut self, seat: Wind, bot: Box<dyn Bot>) {
+        self.seats.push(seat);
+        let _ = self.requests.send(BotRequest::Add { seat, bot });
+    }
+
+    /// Stops playing `seat`, dropping its bot.
+    fn remove(&mut self, seat: Wind) {
+        self.seats.retain(|&other| other != seat);
+        let _ = self.requests.send(BotRequest::Remove { seat });
+    }
+}
+
+/// A client watching the match.
+#[derive(Debug)]
+struct Spectator {
+    view: SpectatorView,
+
+    /// Queue of events waiting to be sent to the spectator, along with the time to
+    /// send each one.
+    queue: mpsc::UnboundedSender<(Instant, usize, SpectatorEvent)>,
+}
+
+impl Spectator {
+    /// Creates the spectator and spawns the task that sends queued events to the
+    /// spectator's client once they're due.
+    ///
+    /// Events are sent in the order they're queued, so an event is never sent
+    /// before an earlier event even if it's due first.
+    fn new(id: MatchId, view: SpectatorView, mut client: ClientControllerProxy) -> Self {
+        let (queue, mut receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Some((send_at, sequence, event)) = receiver.recv().await {
+                time::delay_until(send_at).await;
+
+                // NOTE: We don't need to wait for the client controller to handle the
+                // event, the message is sent as soon as the proxy method is called.
+                if client.send_spectator_event(id, sequence, event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { view, queue }
+    }
+
+    /// Queues the spectator's version of `event`, which was broadcast at `time`.
+    fn send(&self, sequence: usize, event: &MatchEvent, time: Instant, delay: Duration) {
+        let send_at = match self.view {
+            SpectatorView::Hidden => time,
+            SpectatorView::Revealed => time + delay,
+        };
+
+        // NOTE: The queue is only closed once the client has disconnected, in which
+        // case the spectator will be removed once the client controller shuts down.
+        let _ = self
+            .queue
+            .send((send_at, sequence, SpectatorEvent::new(event, self.view)));
+    }
+
+    /// Queues the reveal of the starting hands in `initial` for a spectator with
+    /// `SpectatorView::Revealed`, once `delay` has passed since the match started at
+    /// `started_at`.
+    ///
+    /// Must be queued before any events, since no events happen before the match
+    /// starts.
+    fn reveal(&self, initial: &MatchSnapshot, started_at: Instant, delay: Duration) {
+        if self.view != SpectatorView::Revealed {
+            return;
+        }
+
+        let _ = self.queue.send((
+            started_at + delay,
+            0,
+            SpectatorEvent::hands_revealed(initial),
+        ));
     }
 }
@@ -0,0 +1,346 @@
+//! Matchmaking for matches between multiple players.
+//!
+//! Clients that send `ClientRequest::JoinQueue` are added to the queue held by the
+//! `Matchmaker` actor. Whenever enough compatible players are queued, they're placed
+//! in a new match with their seats assigned at random. If bot backfill is enabled,
+//! a player who has waited long enough is placed in a match with whichever
+//! compatible players are available, and the remaining seats are played by the
+//! computer.
+
+use crate::{
+    client::{ClientControllerProxy, ClientId, InvalidRequest},
+    config::Config,
+    GameStateProxy,
+};
+use mahjong::{anyhow::*, messages::*, strum::IntoEnumIterator, tile::Wind};
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use std::time::Duration;
+use thespian::{Actor, StageBuilder};
+use tokio::time::{self, Instant};
+use tracing::*;
+
+/// The rating given to new accounts.
+pub const STARTING_RATING: u32 = 1500;
+
+/// How often the queue is checked for players who have waited long enough to be
+/// matched with bots.
+const BACKFILL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The number of seats in a match.
+const SEATS: usize = 4;
+
+/// Actor managing the matchmaking queue.
+#[derive(Debug, Actor)]
+pub struct Matchmaker {
+    /// The queued players, in the order they joined the queue.
+    queue: Vec<QueueEntry>,
+
+    game: GameStateProxy,
+    rng: Pcg64Mcg,
+
+    bot_backfill: Option<Duration>,
+    rating_range: Option<u32>,
+}
+
+impl Matchmaker {
+    /// Spawns the matchmaking actor, returning a proxy to it.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn spawn(config: &Config, game: GameStateProxy) -> MatchmakerProxy {
+        let (builder, _) = StageBuilder::new();
+        let stage = builder.finish(Self {
+            queue: Vec::new(),
+            game,
+            rng: Pcg64Mcg::from_entropy(),
+            bot_backfill: config.bot_backfill,
+            rating_range: config.rating_range,
+        });
+        let proxy = stage.proxy();
+        tokio::spawn(stage.run());
+
+        // Players only need to be matched on a timer if bots can fill in for missing
+        // players, otherwise the queue only changes when players join or leave.
+        if config.bot_backfill.is_some() {
+            let mut proxy = proxy.clone();
+            tokio::spawn(async move {
+                let mut interval = time::interval(BACKFILL_CHECK_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    match proxy.make_matches() {
+                        Ok(result) => result.await,
+                        Err(_) => break,
+                    }
+                }
+            });
+        }
+
+        proxy
+    }
+
+    /// Sends each queued client its current place in the queue.
+    fn send_status(&mut self) {
+        let players_queued = self.queue.len();
+        for (index, entry) in self.queue.iter_mut().enumerate() {
+            let status = QueueStatus {
+                position: index + 1,
+                players_queued,
+            };
+
+            // NOTE: The client may have disconnected, in which case it will leave the
+            // queue once the client controller shuts down.
+            let _ = entry.controller.send_queue_status(status);
+        }
+    }
+
+    /// Creates a match for `players` and seats each of them in it.
+    ///
+    /// Seats are assigned at random, and any seats left over are played by the
+    /// computer.
+    async fn start_match(&mut self, players: &[QueueEntry]) -> Result<()> {
+        let mut seats = Wind::iter().collect::<Vec<_>>();
+        seats.shuffle(&mut self.rng);
+
+        let mut controller = self.game.create_match(None)?.await?;
+
+        for (&seat, entry) in seats.iter().zip(players) {
+            let snapshot = controller
+                .join(entry.client, entry.controller.clone(), entry.account, seat)?
+                .await?;
+
+            // NOTE: We don't wait for the client controller, since the client may be
+            // waiting on the matchmaker to finish joining the queue.
+            let _ = entry
+                .controller
+                .clone()
+                .match_found(controller.clone(), seat, snapshot);
+        }
+
+        controller.start()?.await
+    }
+
+    /// Starts matches for as many of the queued players as possible, returning
+    /// `true` if any matches were started.
+    async fn match_players(&mut self) -> bool {
+        let mut matched = false;
+
+        loop {
+            let now = Instant::now();
+            let candidates = self
+                .queue
+                .iter()
+                .map(|entry| Candidate {
+                    rating: entry.rating,
+                    waited: now - entry.joined_at,
+                })
+                .collect::<Vec<_>>();
+
+            let mut chosen = match find_match(&candidates, self.rating_range, self.bot_backfill) {
+                Some(chosen) => chosen,
+                None => break,
+            };
+
+            // Remove the chosen players from the queue, starting from the back so that
+            // removing one doesn't shift the indices of the others.
+            chosen.sort_unstable_by(|a, b| b.cmp(a));
+            let players = chosen
+                .into_iter()
+                .map(|index| self.queue.remove(index))
+                .collect::<Vec<_>>();
+
+            info!(
+                players = players.len(),
+                "Starting match from matchmaking queue"
+            );
+
+            if let Err(err) = self.start_match(&players).await {
+                error!(%err, "Failed to start match for queued players");
+
+                // Put the players back in the queue so that we can try again later.
+                self.queue.extend(players);
+                self.queue.sort_by_key(|entry| entry.joined_at);
+                break;
+            }
+
+            matched = true;
+        }
+
+        matched
+    }
+}
+
+#[thespian::actor]
+impl Matchmaker {
+    /// Adds a client to the queue, returning its initial place in the queue.
+    ///
+    /// If another client for the same account is already queued, it's replaced by
+    /// `client`.
+    #[tracing::instrument(skip(self, controller))]
+    pub async fn join(
+        &mut self,
+        client: ClientId,
+        controller: ClientControllerProxy,
+        account: AccountId,
+        rating: u32,
+    ) -> Result<QueueStatus> {
+        if self.queue.iter().any(|entry| entry.client == client) {
+            bail!(InvalidRequest::new("Already in the matchmaking queue"));
+        }
+
+        self.queue.retain(|entry| entry.account != account);
+        self.queue.push(QueueEntry {
+            client,
+            controller,
+            account,
+            rating,
+            joined_at: Instant::now(),
+        });
+
+        info!(
+            players_queued = self.queue.len(),
+            "Player joined matchmaking queue"
+        );
+
+        let status = QueueStatus {
+            position: self.queue.len(),
+            players_queued: self.queue.len(),
+        };
+
+        // Notify the other queued players that the queue has grown, even if no match
+        // could be made.
+        self.match_players().await;
+        self.send_status();
+
+        Ok(status)
+    }
+
+    /// Removes a client from the queue.
+    ///
+    /// Does nothing if the client isn't queued, e.g. because it was already placed
+    /// in a match.
+    #[tracing::instrument(skip(self))]
+    pub fn leave(&mut self, client: ClientId) {
+        let len = self.queue.len();
+        self.queue.retain(|entry| entry.client != client);
+
+        if self.queue.len() != len {
+            info!("Player left matchmaking queue");
+            self.send_status();
+        }
+    }
+
+    /// Starts matches for as many of the queued players as possible, notifying the
+    /// remaining players of their new place in the queue.
+    pub async fn make_matches(&mut self) {
+        if self.match_players().await {
+            self.send_status();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct QueueEntry {
+    client: ClientId,
+    controller: ClientControllerProxy,
+    account: AccountId,
+    rating: u32,
+    joined_at: Instant,
+}
+
+/// The information about a queued player that's used to decide who to match.
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    rating: u32,
+
+    /// How long the player has been in the queue.
+    waited: Duration,
+}
+
+/// Chooses the players for the next match from `queue`, which is ordered by when
+/// each player joined.
+///
+/// Returns the indices of the chosen players, or `None` if no match can be made yet.
+/// Players who have waited longer are matched first, and if `rating_range` is set
+/// then the chosen players' ratings must all be within that range of each other. A
+/// match normally needs four players, but once a player has waited for
+/// `bot_backfill` they're matched with as many compatible players as are available.
+fn find_match(
+    queue: &[Candidate],
+    rating_range: Option<u32>,
+    bot_backfill: Option<Duration>,
+) -> Option<Vec<usize>> {
+    for (first, candidate) in queue.iter().enumerate() {
+        let mut chosen = vec![first];
+        let mut min = candidate.rating;
+        let mut max = candidate.rating;
+
+        for (index, other) in queue.iter().enumerate() {
+            if chosen.len() == SEATS {
+                break;
+            }
+
+            if index == first {
+                continue;
+            }
+
+            let new_min = min.min(other.rating);
+            let new_max = max.max(other.rating);
+            if rating_range.map_or(true, |range| new_max - new_min <= range) {
+                chosen.push(index);
+                min = new_min;
+                max = new_max;
+            }
+        }
+
+        let backfill = bot_backfill.map_or(false, |wait| candidate.waited >= wait);
+        if chosen.len() == SEATS || backfill {
+            return Some(chosen);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(ratings: &[u32]) -> Vec<Candidate> {
+        ratings
+            .iter()
+            .map(|&rating| Candidate {
+                rating,
+                waited: Duration::from_secs(0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_in_queue_order() {
+        let queue = queue(&[1500, 1200, 1900, 1500, 1650]);
+        assert_eq!(Some(vec![0, 1, 2, 3]), find_match(&queue, None, None));
+        assert_eq!(None, find_match(&queue[..3], None, None));
+    }
+
+    #[test]
+    fn matches_within_rating_range() {
+        let queue = queue(&[1500, 1200, 1900, 1550, 1400, 1450]);
+        assert_eq!(Some(vec![0, 3, 4, 5]), find_match(&queue, Some(150), None));
+        assert_eq!(None, find_match(&queue, Some(100), None));
+
+        // Players who can't be matched don't hold up the rest of the queue.
+        let queue = self::queue(&[2000, 1500, 1200, 1550, 1450, 1480]);
+        assert_eq!(Some(vec![1, 3, 4, 5]), find_match(&queue, Some(100), None));
+    }
+
+    #[test]
+    fn backfills_after_waiting() {
+        let mut queue = queue(&[1500, 1900, 1520]);
+        let wait = Duration::from_secs(30);
+        assert_eq!(None, find_match(&queue, Some(100), Some(wait)));
+
+        queue[0].waited = wait;
+        assert_eq!(Some(vec![0, 2]), find_match(&queue, Some(100), Some(wait)));
+        assert_eq!(Some(vec![0, 1, 2]), find_match(&queue, None, Some(wait)));
+    }
+}
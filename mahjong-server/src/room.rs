@@ -0,0 +1,273 @@
+//! Private rooms that players join with an invite code.
+//!
+//! Creating a room also creates the match for the room. Players take seats in the
+//! match directly with `MatchController::join` as they join the room, and give
+//! them up with `MatchController::unseat` if they leave before the match starts.
+//! Once the host starts the match the room is closed, and every player in it is
+//! moved into the match.
+
+use crate::{
+    client::{ClientControllerProxy, ClientId, InvalidRequest},
+    match_controller::MatchControllerProxy,
+    GameStateProxy,
+};
+use mahjong::{
+    anyhow::*, match_state::Ruleset, messages::*, strum::IntoEnumIterator, sync::MatchSnapshot,
+    tile::Wind,
+};
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use thespian::{Actor, StageBuilder};
+use tracing::*;
+
+/// The characters used in room codes. Characters that are easily mistaken for each
+/// other, such as `0` and `O`, are left out.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+const CODE_LENGTH: usize = 6;
+
+/// The longest name allowed for a room, in characters.
+pub const MAX_NAME_LENGTH: usize = 32;
+
+/// Generates a random room code.
+///
+/// Codes aren't guaranteed to be unique, so the caller must check that the code
+/// isn't already in use.
+pub fn generate_code() -> RoomCode {
+    let mut rng = rand::thread_rng();
+    let code = (0..CODE_LENGTH)
+        .map(|_| *CODE_ALPHABET.choose(&mut rng).unwrap() as char)
+        .collect::<String>();
+    RoomCode::new(&code)
+}
+
+/// Actor managing the lobby for a private room.
+#[derive(Debug, Actor)]
+pub struct Room {
+    code: RoomCode,
+    name: String,
+    ruleset: Ruleset,
+    host: AccountId,
+
+    /// The match that players in the room are seated in.
+    controller: MatchControllerProxy,
+
+    /// The initial state of the match, sent to each player once the match starts.
+    snapshot: Option<MatchSnapshot>,
+
+    /// The player in each seat that has been taken.
+    members: HashMap<Wind, Member>,
+
+    /// Set once the match has started or the host has left, after which no one can
+    /// join the room.
+    closed: bool,
+
+    game: GameStateProxy,
+}
+
+impl Room {
+    /// Spawns the actor for a new room, returning a proxy to it.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn spawn(
+        code: RoomCode,
+        name: String,
+        ruleset: Ruleset,
+        host: AccountId,
+        controller: MatchControllerProxy,
+        game: GameStateProxy,
+    ) -> RoomProxy {
+        let (builder, _) = StageBuilder::new();
+        let stage = builder.finish(Self {
+            code,
+            name,
+            ruleset,
+            host,
+            controller,
+            snapshot: None,
+            members: Default::default(),
+            closed: false,
+            game,
+        });
+        let proxy = stage.proxy();
+        tokio::spawn(stage.run());
+
+        proxy
+    }
+
+    fn info(&self) -> RoomInfo {
+        RoomInfo {
+            code: self.code.clone(),
+            name: self.name.clone(),
+            ruleset: self.ruleset,
+            host: self.host,
+            seats: Wind::iter()
+                .filter_map(|seat| {
+                    self.members.get(&seat).map(|member| RoomSeat {
+                        seat,
+                        account: member.account,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Sends the current state of the room to every player in it except `except`.
+    fn send_update(&mut self, except: Option<ClientId>) {
+        let info = self.info();
+        for member in self.members.values_mut() {
+            if Some(member.client) != except {
+                let _ = member.controller.room_updated(info.clone());
+            }
+        }
+    }
+
+    /// Stops any more players from joining the room.
+    fn close(&mut self) {
+        self.closed = true;
+
+        // NOTE: We don't need to wait for the room to be removed, the message is sent
+        // as soon as the proxy method is called.
+        let _ = self
+            .game
+            .close_room(self.code.clone())
+            .expect("Game state actor has stopped");
+    }
+}
+
+#[thespian::actor]
+impl Room {
+    /// Seats a player in the room's match, returning the updated state of the room.
+    ///
+    /// Fails if the seat is already taken, or if the player already has a seat.
+    #[tracing::instrument(skip(self, controller), fields(code = %self.code))]
+    pub async fn join(
+        &mut self,
+        client: ClientId,
+        controller: ClientControllerProxy,
+        account: AccountId,
+        seat: Wind,
+    ) -> Result<RoomInfo> {
+        if self.closed {
+            bail!(InvalidRequest::new("Room is closed"));
+        }
+
+        if self
+            .members
+            .values()
+            .any(|member| member.account == account)
+        {
+            bail!(InvalidRequest::new("Already seated in the room"));
+        }
+
+        let snapshot = self
+            .controller
+            .join(client, controller.clone(), account, seat)?
+            .await?;
+        self.snapshot = Some(snapshot);
+
+        info!(?seat, "Player joined room");
+
+        self.members.insert(
+            seat,
+            Member {
+                client,
+                controller,
+                account,
+            },
+        );
+        self.send_update(Some(client));
+
+        Ok(self.info())
+    }
+
+    /// Removes a player from the room, freeing up their seat.
+    ///
+    /// If the host leaves, the room is closed and the remaining players are notified.
+    /// Does nothing if the client isn't in the room, or if the match has already
+    /// started.
+    #[tracing::instrument(skip(self), fields(code = %self.code))]
+    pub async fn leave(&mut self, client: ClientId) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+
+        let seat = match self
+            .members
+            .iter()
+            .find(|(_, member)| member.client == client)
+        {
+            Some((&seat, _)) => seat,
+            None => return Ok(()),
+        };
+
+        self.controller.unseat(seat)?.await?;
+        let member = self.members.remove(&seat).unwrap();
+
+        if member.account == self.host || self.members.is_empty() {
+            info!("Host left the room, closing it");
+
+            self.close();
+            for member in self.members.values_mut() {
+                let _ = member.controller.room_closed();
+            }
+        } else {
+            info!(?seat, "Player left room");
+            self.send_update(None);
+        }
+
+        Ok(())
+    }
+
+    /// Starts the room's match on behalf of the host, moving every player in the
+    /// room into the match.
+    ///
+    /// If `fill_with_bots` is `false`, the match can only start once every seat is
+    /// taken.
+    #[tracing::instrument(skip(self), fields(code = %self.code))]
+    pub async fn start(&mut self, client: ClientId, fill_with_bots: bool) -> Result<()> {
+        if self.closed {
+            bail!(InvalidRequest::new("Room is closed"));
+        }
+
+        let host = self.host;
+        if !self
+            .members
+            .values()
+            .any(|member| member.client == client && member.account == host)
+        {
+            bail!(InvalidRequest::new("Only the host can start the match"));
+        }
+
+        if !fill_with_bots && self.members.len() < Wind::iter().count() {
+            bail!(InvalidRequest::new(
+                "Cannot start the match until every seat is taken"
+            ));
+        }
+
+        info!(players = self.members.len(), "Starting match for room");
+
+        self.close();
+
+        // NOTE: We don't wait for the client controllers, since the host's client is
+        // waiting on the room to start the match.
+        let snapshot = self
+            .snapshot
+            .clone()
+            .expect("Room has players but no snapshot");
+        for (&seat, member) in &mut self.members {
+            let _ = member
+                .controller
+                .match_found(self.controller.clone(), seat, snapshot.clone());
+        }
+
+        self.controller.start()?.await
+    }
+}
+
+#[derive(Debug)]
+struct Member {
+    client: ClientId,
+    controller: ClientControllerProxy,
+    account: AccountId,
+}
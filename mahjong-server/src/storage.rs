@@ -0,0 +1,198 @@
+//! Persistent storage for account data and match history.
+//!
+//! All access to stored data goes through the [`Storage`] trait, which has two
+//! implementations: [`MemoryStorage`], which keeps everything in memory and is
+//! lost when the server shuts down, and [`SqliteStorage`], which stores data in an
+//! embedded SQLite database. The backend is selected by the `persistence_path`
+//! config value.
+//!
+//! [`Storage`]: trait.Storage.html
+//! [`MemoryStorage`]: struct.MemoryStorage.html
+//! [`SqliteStorage`]: sqlite/struct.SqliteStorage.html
+
+use crate::{auth::TokenHash, config::Config};
+use mahjong::{
+    anyhow::{bail, Result},
+    match_state::{MatchId, Ruleset},
+    messages::{AccountId, MatchEvent, PlayerState},
+    tile::Wind,
+};
+use std::{collections::HashMap, fmt::Debug, time::SystemTime};
+
+pub use self::sqlite::SqliteStorage;
+
+mod sqlite;
+
+/// Opens the storage backend specified by `config`.
+pub fn open(config: &Config) -> Result<Box<dyn Storage>> {
+    match &config.persistence_path {
+        Some(path) => Ok(Box::new(SqliteStorage::open(path)?)),
+        None => Ok(Box::new(MemoryStorage::default())),
+    }
+}
+
+/// Interface to the backend used to store game data.
+pub trait Storage: Debug + Send + 'static {
+    /// Generates a new account ID that hasn't been used by any other account.
+    fn next_account_id(&mut self) -> Result<AccountId>;
+
+    /// Generates a new match ID that hasn't been used by any other match.
+    fn next_match_id(&mut self) -> Result<MatchId>;
+
+    /// Loads the account with the specified ID, returning `None` if no such account
+    /// exists.
+    fn load_account(&self, id: AccountId) -> Result<Option<Account>>;
+
+    /// Saves `account`, creating it if it doesn't already exist.
+    fn save_account(&mut self, account: &Account) -> Result<()>;
+
+    /// Saves the record of a completed match.
+    fn save_match(&mut self, record: &MatchRecord) -> Result<()>;
+
+    /// Loads the records for every completed match that `account` played in, ordered
+    /// by match ID.
+    fn match_history(&self, account: AccountId) -> Result<Vec<MatchRecord>>;
+}
+
+/// The stored data for a player account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub id: AccountId,
+    pub token_hash: TokenHash,
+    pub data: PlayerState,
+
+    /// The player's matchmaking rating, used to pair players of similar skill.
+    ///
+    /// New accounts start at `matchmaking::STARTING_RATING`.
+    // TODO: Update ratings based on match results once matches have scoring.
+    pub rating: u32,
+}
+
+/// The record of a completed match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchRecord {
+    pub id: MatchId,
+    pub ruleset: Ruleset,
+
+    /// The account controlling each seat. Seats that were played by the computer
+    /// are not included.
+    pub players: HashMap<Wind, AccountId>,
+
+    /// Every event that occurred during the match, in order.
+    pub events: Vec<MatchEvent>,
+
+    pub finished_at: SystemTime,
+}
+
+/// Storage backend that keeps all data in memory.
+///
+/// Data is lost when the server shuts down, so this is only suitable for
+/// development and testing.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    accounts: HashMap<AccountId, Account>,
+    matches: Vec<MatchRecord>,
+
+    account_id_counter: u64,
+    match_id_counter: u32,
+}
+
+impl Storage for MemoryStorage {
+    fn next_account_id(&mut self) -> Result<AccountId> {
+        self.account_id_counter += 1;
+        Ok(AccountId::new(self.account_id_counter))
+    }
+
+    fn next_match_id(&mut self) -> Result<MatchId> {
+        self.match_id_counter += 1;
+        Ok(MatchId::new(self.match_id_counter))
+    }
+
+    fn load_account(&self, id: AccountId) -> Result<Option<Account>> {
+        Ok(self.accounts.get(&id).cloned())
+    }
+
+    fn save_account(&mut self, account: &Account) -> Result<()> {
+        self.accounts.insert(account.id, account.clone());
+        Ok(())
+    }
+
+    fn save_match(&mut self, record: &MatchRecord) -> Result<()> {
+        if self.matches.iter().any(|existing| existing.id == record.id) {
+            bail!("Match {} has already been recorded", record.id);
+        }
+
+        self.matches.push(record.clone());
+        Ok(())
+    }
+
+    fn match_history(&self, account: AccountId) -> Result<Vec<MatchRecord>> {
+        let mut history = self
+            .matches
+            .iter()
+            .filter(|record| record.players.values().any(|&id| id == account))
+            .cloned()
+            .collect::<Vec<_>>();
+        history.sort_by_key(|record| record.id.raw());
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth;
+
+    /// Exercises the common behavior that every storage backend must support.
+    pub fn check_storage(storage: &mut dyn Storage) {
+        let first = storage.next_account_id().unwrap();
+        let second = storage.next_account_id().unwrap();
+        assert_ne!(first, second, "Account IDs must be unique");
+
+        let first_match = storage.next_match_id().unwrap();
+        let second_match = storage.next_match_id().unwrap();
+        assert_ne!(first_match, second_match, "Match IDs must be unique");
+
+        assert_eq!(None, storage.load_account(first).unwrap());
+
+        let mut account = Account {
+            id: first,
+            token_hash: TokenHash::new(&auth::generate_token()),
+            data: PlayerState { points: 10_000 },
+            rating: 1500,
+        };
+        storage.save_account(&account).unwrap();
+        assert_eq!(
+            Some(&account),
+            storage.load_account(first).unwrap().as_ref()
+        );
+
+        // Saving an existing account overwrites the stored data.
+        account.token_hash = TokenHash::new(&auth::generate_token());
+        account.data.points = 9_000;
+        account.rating = 1620;
+        storage.save_account(&account).unwrap();
+        assert_eq!(
+            Some(&account),
+            storage.load_account(first).unwrap().as_ref()
+        );
+
+        let record = MatchRecord {
+            id: first_match,
+            ruleset: Ruleset::Riichi,
+            players: vec![(Wind::East, first)].into_iter().collect(),
+            events: vec![MatchEvent::MatchEnded],
+            finished_at: SystemTime::UNIX_EPOCH,
+        };
+        storage.save_match(&record).unwrap();
+        assert!(storage.save_match(&record).is_err(), "Recorded match twice");
+
+        assert_eq!(vec![record], storage.match_history(first).unwrap());
+        assert!(storage.match_history(second).unwrap().is_empty());
+    }
+
+    #[test]
+    fn memory_storage() {
+        check_storage(&mut MemoryStorage::default());
+    }
+}
@@ -0,0 +1,346 @@
+use super::{Account, MatchRecord, Storage};
+use crate::auth::TokenHash;
+use mahjong::{
+    anyhow::{anyhow, bail, Context, Result},
+    match_state::{MatchId, Ruleset},
+    messages::{AccountId, PlayerState},
+    tile::Wind,
+};
+use rusqlite::{params, Connection, OptionalExtension, Transaction, NO_PARAMS};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+use tracing::*;
+
+/// The schema migrations for the database, in order.
+///
+/// The database tracks how many migrations have been applied using SQLite's
+/// `user_version` pragma. When opening a database, any migrations that haven't yet
+/// been applied are run in order. Existing migrations must never be modified once
+/// they have been released, since databases in the wild may have already applied
+/// them. Make schema changes by appending a new migration instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: Initial schema.
+    r#"
+    CREATE TABLE counters (
+        name TEXT PRIMARY KEY NOT NULL,
+        value INTEGER NOT NULL
+    );
+
+    INSERT INTO counters (name, value) VALUES ('account_id', 0), ('match_id', 0);
+
+    CREATE TABLE accounts (
+        id INTEGER PRIMARY KEY NOT NULL,
+        token_hash BLOB NOT NULL,
+        points INTEGER NOT NULL
+    );
+
+    CREATE TABLE matches (
+        id INTEGER PRIMARY KEY NOT NULL,
+        ruleset TEXT NOT NULL,
+        events TEXT NOT NULL,
+        finished_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE match_players (
+        match_id INTEGER NOT NULL REFERENCES matches(id),
+        seat TEXT NOT NULL,
+        account_id INTEGER NOT NULL REFERENCES accounts(id),
+        PRIMARY KEY (match_id, seat)
+    );
+
+    CREATE INDEX match_players_by_account ON match_players(account_id);
+    "#,
+    // 2: Matchmaking rating for accounts.
+    r#"
+    ALTER TABLE accounts ADD COLUMN rating INTEGER NOT NULL DEFAULT 1500;
+    "#,
+];
+
+/// Storage backend using an embedded SQLite database.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, creating it if it doesn't exist and applying
+    /// any pending schema migrations.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let connection = Connection::open(path)
+            .with_context(|| format!("Failed to open database at {}", path.display()))?;
+        Self::with_connection(connection)
+    }
+
+    /// Creates a new database that only exists in memory.
+    pub fn open_in_memory() -> Result<Self> {
+        let connection = Connection::open_in_memory().context("Failed to create database")?;
+        Self::with_connection(connection)
+    }
+
+    fn with_connection(mut connection: Connection) -> Result<Self> {
+        connection
+            .execute_batch("PRAGMA foreign_keys = ON;")
+            .context("Failed to enable foreign key constraints")?;
+        migrate(&mut connection)?;
+        Ok(Self { connection })
+    }
+
+    fn next_counter(&mut self, name: &str) -> Result<i64> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "UPDATE counters SET value = value + 1 WHERE name = ?1",
+            params![name],
+        )?;
+        let value = transaction.query_row(
+            "SELECT value FROM counters WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )?;
+        transaction.commit()?;
+        Ok(value)
+    }
+
+    fn load_players(&self, id: MatchId) -> Result<HashMap<Wind, AccountId>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT seat, account_id FROM match_players WHERE match_id = ?1")?;
+        let rows = statement.query_map(params![id.raw()], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+
+        let mut players = HashMap::new();
+        for row in rows {
+            let (seat, account) = row?;
+            let seat = seat
+                .parse::<Wind>()
+                .map_err(|_| anyhow!("Invalid seat {:?} stored for match {}", seat, id))?;
+            players.insert(seat, AccountId::new(account as u64));
+        }
+
+        Ok(players)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn next_account_id(&mut self) -> Result<AccountId> {
+        let id = self.next_counter("account_id")?;
+        Ok(AccountId::new(id as u64))
+    }
+
+    fn next_match_id(&mut self) -> Result<MatchId> {
+        let id = self.next_counter("match_id")?;
+        let id = id.try_into().context("Exhausted available match IDs")?;
+        Ok(MatchId::new(id))
+    }
+
+    fn load_account(&self, id: AccountId) -> Result<Option<Account>> {
+        let row = self
+            .connection
+            .query_row(
+                "SELECT token_hash, points, rating FROM accounts WHERE id = ?1",
+                params![id.raw() as i64],
+                |row| {
+                    Ok((
+                        row.get::<_, Vec<u8>>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, u32>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        let (token_hash, points, rating) = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let token_hash = token_hash
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow!("Invalid token hash stored for account {}", id))?;
+
+        Ok(Some(Account {
+            id,
+            token_hash: TokenHash::from_bytes(token_hash),
+            data: PlayerState {
+                points: points as u64,
+            },
+            rating,
+        }))
+    }
+
+    fn save_account(&mut self, account: &Account) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO accounts (id, token_hash, points, rating) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (id) DO UPDATE SET
+                token_hash = excluded.token_hash,
+                points = excluded.points,
+                rating = excluded.rating",
+            params![
+                account.id.raw() as i64,
+                &account.token_hash.as_bytes()[..],
+                account.data.points as i64,
+                account.rating,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn save_match(&mut self, record: &MatchRecord) -> Result<()> {
+        let events = serde_json::to_string(&record.events)?;
+        let finished_at = record
+            .finished_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .context("Match finished before the unix epoch")?
+            .as_millis() as i64;
+
+        let transaction = self.connection.transaction()?;
+        transaction
+            .execute(
+                "INSERT INTO matches (id, ruleset, events, finished_at) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    record.id.raw(),
+                    format!("{:?}", record.ruleset),
+                    events,
+                    finished_at,
+                ],
+            )
+            .with_context(|| format!("Failed to record match {}", record.id))?;
+
+        for (seat, account) in &record.players {
+            transaction.execute(
+                "INSERT INTO match_players (match_id, seat, account_id) VALUES (?1, ?2, ?3)",
+                params![record.id.raw(), format!("{:?}", seat), account.raw() as i64],
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn match_history(&self, account: AccountId) -> Result<Vec<MatchRecord>> {
+        let mut statement = self.connection.prepare(
+            "SELECT matches.id, matches.ruleset, matches.events, matches.finished_at
+            FROM matches
+            INNER JOIN match_players ON match_players.match_id = matches.id
+            WHERE match_players.account_id = ?1
+            ORDER BY matches.id",
+        )?;
+        let rows = statement.query_map(params![account.raw() as i64], |row| {
+            Ok((
+                row.get::<_, u32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?;
+
+        let mut history = Vec::new();
+        for row in rows {
+            let (id, ruleset, events, finished_at) = row?;
+            let id = MatchId::new(id);
+
+            let ruleset = ruleset
+                .parse::<Ruleset>()
+                .map_err(|_| anyhow!("Invalid ruleset {:?} stored for match {}", ruleset, id))?;
+            let events = serde_json::from_str(&events)
+                .with_context(|| format!("Invalid events stored for match {}", id))?;
+
+            history.push(MatchRecord {
+                id,
+                ruleset,
+                players: self.load_players(id)?,
+                events,
+                finished_at: SystemTime::UNIX_EPOCH + Duration::from_millis(finished_at as u64),
+            });
+        }
+
+        Ok(history)
+    }
+}
+
+/// Applies any pending migrations to the database.
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let version: i64 = connection.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+    let version = version as usize;
+
+    if version > MIGRATIONS.len() {
+        bail!(
+            "Database schema version {} is newer than the latest supported version {}",
+            version,
+            MIGRATIONS.len(),
+        );
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        apply_migration(&transaction, index + 1, migration)?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+fn apply_migration(transaction: &Transaction<'_>, version: usize, migration: &str) -> Result<()> {
+    info!(version, "Applying database migration");
+
+    transaction
+        .execute_batch(migration)
+        .with_context(|| format!("Failed to apply migration {}", version))?;
+
+    // NOTE: Pragmas don't support bound parameters, so the version has to be
+    // formatted into the statement directly.
+    transaction.execute_batch(&format!("PRAGMA user_version = {};", version))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{auth, storage::tests::check_storage};
+
+    #[test]
+    fn sqlite_storage() {
+        check_storage(&mut SqliteStorage::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn data_survives_reopening() {
+        let path = std::env::temp_dir().join(format!(
+            "mahjong-storage-test-{}.sqlite",
+            auth::generate_token(),
+        ));
+
+        let account = {
+            let mut storage = SqliteStorage::open(&path).unwrap();
+            let account = Account {
+                id: storage.next_account_id().unwrap(),
+                token_hash: TokenHash::new(&auth::generate_token()),
+                data: PlayerState { points: 10_000 },
+                rating: 1500,
+            };
+            storage.save_account(&account).unwrap();
+            account
+        };
+
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(
+            Some(&account),
+            storage.load_account(account.id).unwrap().as_ref()
+        );
+        assert_ne!(
+            account.id,
+            storage.next_account_id().unwrap(),
+            "Account ID counter was reset",
+        );
+
+        drop(storage);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+//! End-to-end tests for account creation and login.
+
+use harness::*;
+use mahjong::messages::{AccountId, Credentials, HandshakeError, Version};
+use mahjong_headless::Client;
+use mahjong_server::config::Config;
+use std::time::Duration;
+use tokio::time;
+
+mod harness;
+
+// Test that a client can log back into its account, and that the server issues a
+// new token each time it does.
+#[tokio::test]
+async fn log_in_rotates_token() {
+    let server = TestServer::start();
+
+    let client = server.connect().await;
+    let first = client.credentials().clone();
+    let account = client.account().clone();
+    client.close().await.expect("Failed to close connection");
+
+    let client = Client::connect(&server.url(), Some(first.clone()))
+        .await
+        .expect("Failed to log into existing account");
+    let second = client.credentials().clone();
+
+    assert_eq!(first.id, second.id, "Logged into a different account");
+    assert_ne!(first.token, second.token, "Token was not rotated on login");
+    assert_eq!(&account, client.account());
+
+    // The previous token is no longer valid once a new one has been issued.
+    let result = Client::connect(&server.url(), Some(first)).await;
+    assert!(result.is_err(), "Logged in using a stale token");
+}
+
+// Test that the server rejects credentials it didn't issue.
+#[tokio::test]
+async fn invalid_credentials_rejected() {
+    let server = TestServer::start();
+    let client = server.connect().await;
+
+    let wrong_token = Credentials {
+        id: client.credentials().id,
+        token: String::from("DUMMY"),
+    };
+    let result = Client::connect(&server.url(), Some(wrong_token)).await;
+    assert!(result.is_err(), "Logged in with an invalid token");
+
+    let unknown_account = Credentials {
+        id: AccountId::new(u64::MAX),
+        token: client.credentials().token.clone(),
+    };
+    let result = Client::connect(&server.url(), Some(unknown_account)).await;
+    assert!(result.is_err(), "Logged into an account that doesn't exist");
+}
+
+// Test that accounts are still available after restarting the server when using
+// persistent storage.
+#[tokio::test]
+async fn accounts_persist_across_restart() {
+    let path = std::env::temp_dir().join(format!(
+        "mahjong-accounts-test-{}.sqlite",
+        std::process::id(),
+    ));
+    let config = Config {
+        persistence_path: Some(path.clone()),
+        ..Default::default()
+    };
+
+    let credentials = {
+        let server = TestServer::with_config(config.clone());
+        let client = server.connect().await;
+        let credentials = client.credentials().clone();
+        client.close().await.expect("Failed to close connection");
+        credentials
+    };
+
+    // Start a second server using the same database, simulating a restart.
+    let server = TestServer::with_config(config);
+    let client = Client::connect(&server.url(), Some(credentials.clone()))
+        .await
+        .expect("Failed to log in after restart");
+    assert_eq!(credentials.id, client.credentials().id);
+
+    // New accounts must not reuse the IDs of accounts created before the restart.
+    let other = server.connect().await;
+    assert_ne!(credentials.id, other.credentials().id);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+// Test that clients older than the configured minimum version are told to update.
+#[tokio::test]
+async fn outdated_client_rejected() {
+    let server = TestServer::with_config(Config {
+        min_client_version: Version::new(0, 1, 1),
+        ..Default::default()
+    });
+
+    let err = Client::connect(&server.url(), None)
+        .await
+        .expect_err("Outdated client was allowed to connect");
+    assert!(
+        matches!(
+            err.downcast_ref::<HandshakeError>(),
+            Some(HandshakeError::UpdateRequired { .. })
+        ),
+        "Unexpected error: {:?}",
+        err,
+    );
+}
+
+// Test that logging into an account from a second client disconnects the first,
+// and that the first client's match keeps going without it.
+#[tokio::test]
+async fn second_login_disconnects_first_client() {
+    let server = TestServer::start();
+
+    let mut first = server.connect().await;
+    first.start_match().await.expect("Failed to start match");
+
+    let mut second = Client::connect(&server.url(), Some(first.credentials().clone()))
+        .await
+        .expect("Failed to log in from second client");
+
+    // The first client may still receive some events before the server closes the
+    // connection, but the connection must eventually be closed.
+    let closed = time::timeout(Duration::from_secs(5), async {
+        while first.next_event().await.is_ok() {}
+    })
+    .await;
+    assert!(closed.is_ok(), "First client was never disconnected");
+
+    // The second client can keep using the account as normal.
+    second
+        .start_match()
+        .await
+        .expect("Failed to start match from second client");
+}
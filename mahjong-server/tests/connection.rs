@@ -0,0 +1,140 @@
+//! End-to-end tests for the connection lifecycle, using raw websocket connections
+//! so that the tests can misbehave in ways the headless client won't.
+
+use futures::prelude::*;
+use harness::*;
+use mahjong::messages::{
+    DisconnectReason, ErrorKind, ErrorResponse, HandshakeRequest, HandshakeResult, RequestId,
+    ServerMessage,
+};
+use mahjong_headless::Client;
+use mahjong_server::config::Config;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+
+mod harness;
+
+// Test that the server disconnects clients that don't send a handshake in time,
+// and tells them why.
+#[tokio::test]
+async fn handshake_timeout() {
+    let server = TestServer::with_config(Config {
+        handshake_timeout: Duration::from_secs(1),
+        ..Default::default()
+    });
+
+    let stream = TcpStream::connect(server.addr())
+        .await
+        .expect("Failed to connect to server");
+    let (mut socket, _) = tokio_tungstenite::client_async(server.url(), stream)
+        .await
+        .expect("Failed to establish websocket connection");
+
+    // The server sends a heartbeat as soon as the connection is established.
+    match socket.next().await {
+        Some(Ok(Message::Text(text))) => assert!(
+            ServerMessage::is_heartbeat(&text),
+            "Expected initial heartbeat, got {:?}",
+            text,
+        ),
+        other => panic!("Expected initial heartbeat, got {:?}", other),
+    }
+
+    // Never send the handshake, and wait for the server to give up on us.
+    match socket.next().await {
+        Some(Ok(Message::Close(Some(frame)))) => assert_eq!(
+            Some(DisconnectReason::HandshakeTimeout),
+            DisconnectReason::from_close_code(frame.code.into()),
+        ),
+        other => panic!("Expected close message, got {:?}", other),
+    }
+}
+
+// Test that the server reports malformed requests to the client rather than
+// silently dropping them, and includes the request ID when it can find one.
+#[tokio::test]
+async fn malformed_request_rejected() {
+    let server = TestServer::start();
+
+    let stream = TcpStream::connect(server.addr())
+        .await
+        .expect("Failed to connect to server");
+    let (mut socket, _) = tokio_tungstenite::client_async(server.url(), stream)
+        .await
+        .expect("Failed to establish websocket connection");
+
+    let handshake = serde_json::to_string(&HandshakeRequest::new(None)).unwrap();
+    socket.send(Message::Text(handshake)).await.unwrap();
+
+    let response = recv_text(&mut socket).await;
+    serde_json::from_str::<HandshakeResult>(&response)
+        .unwrap()
+        .expect("Handshake was rejected");
+
+    let unknown_request = r#"{"request_id":7,"request":"Bogus"}"#;
+    socket
+        .send(Message::Text(unknown_request.into()))
+        .await
+        .unwrap();
+    let error = recv_error(&mut socket).await;
+    assert_eq!(Some(RequestId::new(7)), error.request_id);
+    assert_eq!(ErrorKind::InvalidMessage, error.kind);
+
+    socket
+        .send(Message::Text("not even json".into()))
+        .await
+        .unwrap();
+    let error = recv_error(&mut socket).await;
+    assert_eq!(None, error.request_id);
+    assert_eq!(ErrorKind::InvalidMessage, error.kind);
+}
+
+// Test that failing to bind the server is reported as an error, rather than
+// panicking, when the port is already in use.
+#[tokio::test]
+async fn bind_to_used_port_fails() {
+    let server = TestServer::start();
+
+    let result = mahjong_server::bind(Config {
+        bind_address: server.addr(),
+        ..Default::default()
+    });
+    assert!(result.is_err(), "Bound a second server to {}", server.addr());
+}
+
+// Test that the headless client refuses to connect to a `wss` URL, rather than
+// connecting to the server without TLS.
+#[tokio::test]
+async fn tls_url_rejected() {
+    let server = TestServer::start();
+
+    let url = format!("wss://{}/client", server.addr());
+    let result = Client::connect(&url, None).await;
+    assert!(result.is_err(), "Connected to {} without TLS", url);
+}
+
+/// Waits for the next text message from the server, skipping heartbeats.
+async fn recv_text<S>(socket: &mut S) -> String
+where
+    S: Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    loop {
+        match socket.next().await {
+            Some(Ok(Message::Text(text))) if ServerMessage::is_heartbeat(&text) => {}
+            Some(Ok(Message::Text(text))) => return text,
+            other => panic!("Expected text message, got {:?}", other),
+        }
+    }
+}
+
+async fn recv_error<S>(socket: &mut S) -> ErrorResponse
+where
+    S: Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    let text = recv_text(socket).await;
+    match serde_json::from_str(&text) {
+        Ok(ServerMessage::Error(error)) => error,
+        _ => panic!("Expected error message, got {:?}", text),
+    }
+}
@@ -0,0 +1,144 @@
+//! Test harness for running the server in-process and connecting scripted clients
+//! to it.
+
+// Each test file compiles its own copy of the harness, and not every file uses
+// every helper.
+#![allow(dead_code)]
+
+use mahjong::{
+    anyhow::{ensure, Context, Result},
+    match_state::MatchState,
+    messages::{ErrorKind, ErrorResponse, MatchEvent},
+    tile::Wind,
+};
+use mahjong_headless::Client;
+use mahjong_server::config::Config;
+use std::{fmt::Debug, net::SocketAddr};
+
+/// An instance of the server running in the background on an ephemeral port.
+///
+/// The server runs until the test's runtime is shut down.
+#[derive(Debug)]
+pub struct TestServer {
+    addr: SocketAddr,
+}
+
+impl TestServer {
+    /// Starts a new server instance.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn start() -> Self {
+        Self::with_config(Default::default())
+    }
+
+    /// Starts a new server instance using `config`.
+    ///
+    /// The bind address in `config` is ignored, and the server is bound to an
+    /// unused local port instead.
+    pub fn with_config(config: Config) -> Self {
+        let config = Config {
+            bind_address: ([127, 0, 0, 1], 0).into(),
+            ..config
+        };
+        let (addr, server) = mahjong_server::bind(config).expect("Failed to start test server");
+        tokio::spawn(server);
+        Self { addr }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}/client", self.addr)
+    }
+
+    /// Connects a new client to the server, creating a new account for it.
+    pub async fn connect(&self) -> Client {
+        Client::connect(&self.url(), None)
+            .await
+            .expect("Failed to connect to test server")
+    }
+}
+
+/// The result of a completed match, as seen by a single client.
+#[derive(Debug)]
+pub struct MatchRecord {
+    /// The initial state of the match, as sent by the server.
+    pub initial: MatchState,
+
+    /// The local state after applying every event received from the server.
+    pub last: MatchState,
+
+    /// Every event received from the server, in order.
+    pub events: Vec<MatchEvent>,
+}
+
+/// Starts a match and plays through it as `seat`, always discarding the tile that
+/// was just drawn.
+///
+/// Every event is applied to the client's local copy of the match state, so this
+/// fails if the client ever gets out of sync with the server, either because an
+/// event can't be applied or because the checksum sent with the event doesn't match
+/// the local state.
+pub async fn play_tsumogiri_match(client: &mut Client, seat: Wind) -> Result<MatchRecord> {
+    let initial = client.start_match().await?;
+    play_tsumogiri(client, initial, seat).await
+}
+
+/// Plays through a match that the client has already joined as `seat`, starting
+/// from `initial`. See `play_tsumogiri_match`.
+pub async fn play_tsumogiri(
+    client: &mut Client,
+    initial: MatchState,
+    seat: Wind,
+) -> Result<MatchRecord> {
+    let mut state = initial.clone();
+    let mut events = Vec::new();
+
+    loop {
+        if state.current_turn == seat {
+            if let Some(draw) = state.player(seat).current_draw() {
+                client.discard_tile(state.id(), seat, draw.id).await?;
+            }
+        }
+
+        let event = client.next_event().await?;
+        state
+            .apply_event(&event)
+            .with_context(|| format!("Failed to apply event #{}", events.len()))?;
+        ensure!(
+            client.checksum() == Some(state.checksum()),
+            "Local state out of sync after event #{}",
+            events.len(),
+        );
+        events.push(event.clone());
+
+        if let MatchEvent::MatchEnded = event {
+            break;
+        }
+    }
+
+    Ok(MatchRecord {
+        initial,
+        last: state,
+        events,
+    })
+}
+
+/// Asserts that the server rejected a request with an error of the given kind,
+/// returning the error response.
+pub fn assert_error<T: Debug>(result: Result<T>, kind: ErrorKind) -> ErrorResponse {
+    let err = result
+        .expect_err("Server accepted invalid request")
+        .downcast::<ErrorResponse>()
+        .unwrap_or_else(|err| panic!("Unexpected error: {:?}", err));
+    assert_eq!(kind, err.kind);
+    err
+}
+
+/// Asserts that the server rejected a request as invalid in the current state of
+/// the session or match.
+pub fn assert_invalid_request<T: Debug>(result: Result<T>) -> ErrorResponse {
+    assert_error(result, ErrorKind::InvalidRequest)
+}
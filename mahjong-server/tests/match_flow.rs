@@ -0,0 +1,397 @@
+//! End-to-end tests that run the full server in-process and play matches over real
+//! websocket connections.
+
+use futures::future;
+use harness::*;
+use mahjong::{
+    codec::Encoding,
+    match_state::{MatchId, MatchState},
+    messages::{ErrorKind, MatchEvent},
+    tile::Wind,
+};
+use mahjong_headless::Client;
+use mahjong_server::config::Config;
+use std::time::Duration;
+use tokio::time;
+
+mod harness;
+
+// Test that a client can play a complete match against the computer-controlled
+// players, and that the server sends the expected sequence of events.
+#[tokio::test]
+async fn full_match_event_sequence() {
+    let server = TestServer::start();
+    let mut client = server.connect().await;
+
+    let record = play_tsumogiri_match(&mut client, Wind::East)
+        .await
+        .expect("Failed to play match");
+
+    let (last, turns) = record.events.split_last().unwrap();
+    assert!(
+        matches!(last, MatchEvent::MatchEnded),
+        "Last event wasn't `MatchEnded`: {:?}",
+        last,
+    );
+
+    // The match starts with the East player having already drawn, so after that
+    // every seat discards in turn and the next seat draws from the wall. If a
+    // computer-controlled player makes the last draw, they also discard before the
+    // match ends.
+    let mut seat = Wind::East;
+    let mut draws = 0;
+    for (index, event) in turns.iter().enumerate() {
+        match (index % 2, event) {
+            (
+                0,
+                &MatchEvent::TileDiscarded {
+                    seat: discarder, ..
+                },
+            ) => {
+                assert_eq!(seat, discarder, "Wrong seat discarded, event #{}", index);
+            }
+
+            (1, &MatchEvent::TileDrawn { seat: drawer, .. }) => {
+                seat = seat.next();
+                draws += 1;
+                assert_eq!(seat, drawer, "Wrong seat drew, event #{}", index);
+            }
+
+            _ => panic!("Unexpected event #{}: {:?}", index, event),
+        }
+    }
+
+    assert_eq!(
+        record.initial.wall.len(),
+        draws,
+        "Every tile in the wall should have been drawn",
+    );
+    assert!(
+        record.last.wall.is_empty(),
+        "Match ended with tiles in wall"
+    );
+}
+
+// Test that clients only get a binary encoding if they ask for one, and that
+// clients which only support JSON can still play a full match.
+#[tokio::test]
+async fn negotiated_encoding() {
+    let server = TestServer::start();
+
+    let client = server.connect().await;
+    assert_eq!(Encoding::MessagePack, client.encoding());
+
+    let mut client = Client::connect_with_encodings(&server.url(), None, &[Encoding::Json])
+        .await
+        .expect("Failed to connect to test server");
+    assert_eq!(Encoding::Json, client.encoding());
+
+    let record = play_tsumogiri_match(&mut client, Wind::East)
+        .await
+        .expect("Failed to play match");
+    assert!(
+        matches!(record.events.last(), Some(MatchEvent::MatchEnded)),
+        "Match did not end: {:?}",
+        record.events.last(),
+    );
+}
+
+// Test that the client returns to idle once its match has ended, so that it can play
+// another match on the same connection.
+#[tokio::test]
+async fn back_to_back_matches() {
+    let server = TestServer::start();
+    let mut client = server.connect().await;
+
+    let first = play_tsumogiri_match(&mut client, Wind::East)
+        .await
+        .expect("Failed to play first match");
+    let second = play_tsumogiri_match(&mut client, Wind::East)
+        .await
+        .expect("Failed to play second match");
+
+    assert_ne!(first.initial.id(), second.initial.id());
+    assert!(
+        matches!(second.events.last(), Some(MatchEvent::MatchEnded)),
+        "Match did not end: {:?}",
+        second.events.last(),
+    );
+}
+
+// Test that multiple clients can play matches at the same time without interfering
+// with each other.
+#[tokio::test]
+async fn concurrent_matches() {
+    const CLIENTS: usize = 4;
+
+    let server = TestServer::start();
+
+    let matches = future::join_all((0..CLIENTS).map(|_| async {
+        let mut client = server.connect().await;
+        let record = play_tsumogiri_match(&mut client, Wind::East)
+            .await
+            .expect("Failed to play match");
+        (client.credentials().id, record.initial.id())
+    }))
+    .await;
+
+    let mut accounts = matches
+        .iter()
+        .map(|(account, _)| *account)
+        .collect::<Vec<_>>();
+    accounts.sort();
+    accounts.dedup();
+    assert_eq!(CLIENTS, accounts.len(), "Clients shared an account");
+
+    let mut ids = matches.iter().map(|(_, id)| id.raw()).collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(CLIENTS, ids.len(), "Clients shared a match");
+}
+
+// Test that the server discards for a player who doesn't take their turn before the
+// turn timer expires.
+#[tokio::test]
+async fn turn_timeout_discards_for_player() {
+    let server = TestServer::with_config(Config {
+        turn_timeout: Duration::from_secs(1),
+        ..Default::default()
+    });
+    let mut client = server.connect().await;
+
+    let mut state = client.start_match().await.expect("Failed to start match");
+    let draw = state
+        .player(Wind::East)
+        .current_draw()
+        .expect("East should start with a draw")
+        .id;
+
+    // Don't send a discard, instead wait for the server to discard on our behalf.
+    let event = client.next_event().await.expect("Failed to receive event");
+    assert_eq!(
+        MatchEvent::TileDiscarded {
+            seat: Wind::East,
+            tile: draw,
+        },
+        event,
+    );
+
+    state
+        .apply_event(&event)
+        .expect("Automatic discard was not valid");
+}
+
+// Test that a bot plays a disconnected player's seat without waiting for the turn
+// timer, and that the player can rejoin the match and take their seat back. Applying
+// the events they missed brings them back in sync with the server.
+#[tokio::test]
+async fn resume_match_after_disconnect() {
+    let server = TestServer::with_config(Config {
+        turn_timeout: Duration::from_secs(60),
+        ..Default::default()
+    });
+    let ((east, mut state), (mut south, south_state)) = start_two_player_match(&server).await;
+
+    // Disconnect on our first turn. South stays connected and doesn't take its turn,
+    // which holds the match at South's turn until we're back.
+    let credentials = east.credentials().clone();
+    let events_received = east.events_received();
+    east.close().await.expect("Failed to close connection");
+
+    let south_draw = time::timeout(Duration::from_secs(5), async {
+        loop {
+            let event = south.next_event().await.expect("Failed to receive event");
+            if let MatchEvent::TileDrawn {
+                seat: Wind::South,
+                tile,
+            } = event
+            {
+                break tile;
+            }
+        }
+    })
+    .await
+    .expect("Bot didn't take the disconnected player's turn");
+
+    let mut east = Client::connect(&server.url(), Some(credentials))
+        .await
+        .expect("Failed to reconnect");
+    assert_eq!(Some(state.id()), east.active_match());
+
+    let response = east
+        .resume_match(state.id(), events_received)
+        .await
+        .expect("Failed to resume match");
+    assert_eq!(Wind::East, response.seat);
+    assert!(
+        !response.missed_events.is_empty(),
+        "Match didn't continue while disconnected",
+    );
+
+    for event in &response.missed_events {
+        state
+            .apply_event(event)
+            .expect("Failed to apply missed event");
+    }
+    assert_eq!(
+        MatchState::from_snapshot(&response.snapshot).expect("Invalid snapshot"),
+        state,
+        "Client is out of sync after resuming"
+    );
+    assert_eq!(Some(state.checksum()), east.checksum());
+
+    // Once South has taken its turn, East's next turn should be left to us rather
+    // than the bot.
+    south
+        .discard_tile(south_state.id(), Wind::South, south_draw)
+        .await
+        .expect("Failed to discard tile");
+
+    loop {
+        let event = east.next_event().await.expect("Failed to receive event");
+        state.apply_event(&event).expect("Failed to apply event");
+
+        if let MatchEvent::TileDrawn {
+            seat: Wind::East, ..
+        } = event
+        {
+            break;
+        }
+    }
+
+    let tile = state.player(Wind::East).tiles()[0].id;
+    east.discard_tile(state.id(), Wind::East, tile)
+        .await
+        .expect("Failed to discard tile");
+    assert_eq!(
+        MatchEvent::TileDiscarded {
+            seat: Wind::East,
+            tile,
+        },
+        east.next_event()
+            .await
+            .expect("Failed to discard after resuming"),
+    );
+}
+
+// Test that a client can use the checksums sent with each event to detect that its
+// local state has diverged from the server, and can recover by requesting a fresh
+// snapshot of the match.
+#[tokio::test]
+async fn resync_after_desync() {
+    let server = TestServer::start();
+    let mut client = server.connect().await;
+
+    // Corrupt the local state in a way that doesn't prevent events from applying.
+    let mut state = client.start_match().await.expect("Failed to start match");
+    state.wall.remove(0);
+
+    let draw = state.player(Wind::East).current_draw().unwrap().id;
+    client
+        .discard_tile(state.id(), Wind::East, draw)
+        .await
+        .expect("Failed to discard tile");
+    let event = client.next_event().await.expect("Failed to receive event");
+    state.apply_event(&event).expect("Failed to apply event");
+    assert_ne!(
+        Some(state.checksum()),
+        client.checksum(),
+        "Checksum didn't detect corrupted state",
+    );
+
+    // NOTE: The server has already sent the events up to our next draw, so some of
+    // them are included in the snapshot and should be skipped.
+    let mut state = client.resync(state.id()).await.expect("Failed to resync");
+    assert_eq!(Some(state.checksum()), client.checksum());
+
+    while state.current_turn != Wind::East || state.player(Wind::East).current_draw().is_none() {
+        let event = client.next_event().await.expect("Failed to receive event");
+        state
+            .apply_event(&event)
+            .expect("Failed to apply event after resync");
+        assert_eq!(
+            Some(state.checksum()),
+            client.checksum(),
+            "Out of sync after resync",
+        );
+    }
+}
+
+// Test that the server tells the client why a discard was rejected, rather than
+// dropping the request.
+#[tokio::test]
+async fn invalid_discard_reported() {
+    let server = TestServer::start();
+    let ((_east, _), (mut south, state)) = start_two_player_match(&server).await;
+    let tile = state.player(Wind::South).tiles()[0].id;
+
+    // It's East's turn at the start of the match, so South can't discard yet.
+    let request_id = south
+        .discard_tile(state.id(), Wind::South, tile)
+        .await
+        .expect("Failed to send discard request");
+
+    let err = assert_error(south.next_event().await, ErrorKind::IncorrectTurn);
+    assert_eq!(Some(request_id), err.request_id);
+}
+
+// Test that a client can only discard for its own seat in its own match, and that
+// rejected discards don't affect the match.
+#[tokio::test]
+async fn discard_for_other_player_rejected() {
+    let server = TestServer::start();
+    let ((mut east, east_state), (mut south, south_state)) = start_two_player_match(&server).await;
+    let tile = south_state.player(Wind::East).tiles()[0].id;
+
+    south
+        .discard_tile(south_state.id(), Wind::East, tile)
+        .await
+        .expect("Failed to send discard request");
+    assert_invalid_request(south.next_event().await);
+
+    let tile = south_state.player(Wind::South).tiles()[0].id;
+    south
+        .discard_tile(MatchId::new(1234), Wind::South, tile)
+        .await
+        .expect("Failed to send discard request");
+    assert_invalid_request(south.next_event().await);
+
+    let (east_record, south_record) = future::join(
+        play_tsumogiri(&mut east, east_state, Wind::East),
+        play_tsumogiri(&mut south, south_state, Wind::South),
+    )
+    .await;
+    let east_record = east_record.expect("East failed to play match");
+    let south_record = south_record.expect("South failed to play match");
+    assert_eq!(east_record.events, south_record.events);
+}
+
+/// Starts a match through a private room with human players in the East and South
+/// seats and bots in the others, returning each player's client and initial state.
+async fn start_two_player_match(
+    server: &TestServer,
+) -> ((Client, MatchState), (Client, MatchState)) {
+    let mut east = server.connect().await;
+    let mut south = server.connect().await;
+
+    let info = east
+        .create_room("Two players", None, Wind::East)
+        .await
+        .expect("Failed to create room");
+    south
+        .join_room(info.code.as_str(), Wind::South)
+        .await
+        .expect("Failed to join room");
+    east.next_room_update()
+        .await
+        .expect("Failed to get room update")
+        .expect("Room was closed");
+    east.start_room(true).await.expect("Failed to start room");
+
+    let (east_found, south_found) =
+        future::join(east.wait_for_match(), south.wait_for_match()).await;
+    let (_, east_state) = east_found.expect("East failed to find match");
+    let (_, south_state) = south_found.expect("South failed to find match");
+
+    ((east, east_state), (south, south_state))
+}
@@ -0,0 +1,135 @@
+//! Tests for matching multiple players together through the matchmaking queue.
+
+use futures::future;
+use harness::*;
+use mahjong::{
+    messages::{MatchEvent, QueueStatus},
+    tile::Wind,
+};
+use mahjong_server::config::Config;
+use std::{collections::HashSet, time::Duration};
+
+mod harness;
+
+// Test that four queued players are placed in the same match with different seats,
+// and that they can play through the match together.
+#[tokio::test]
+async fn four_players_matched() {
+    let server = TestServer::with_config(Config {
+        bot_backfill: None,
+        ..Default::default()
+    });
+
+    let mut clients = Vec::new();
+    for index in 0..4 {
+        let mut client = server.connect().await;
+        let status = client.join_queue().await.expect("Failed to join queue");
+        assert_eq!(
+            QueueStatus {
+                position: index + 1,
+                players_queued: index + 1,
+            },
+            status,
+        );
+        clients.push(client);
+    }
+
+    let found = future::join_all(clients.iter_mut().map(|client| client.wait_for_match())).await;
+    let found = found
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("Failed to find match");
+
+    let seats = found.iter().map(|(seat, _)| *seat).collect::<HashSet<_>>();
+    assert_eq!(
+        4,
+        seats.len(),
+        "Players were given the same seat: {:?}",
+        found
+    );
+
+    let (_, initial) = &found[0];
+    for (_, state) in &found {
+        assert_eq!(initial, state, "Players received different initial states");
+    }
+
+    let records = future::join_all(
+        clients
+            .iter_mut()
+            .zip(found)
+            .map(|(client, (seat, state))| play_tsumogiri(client, state, seat)),
+    )
+    .await;
+    let records = records
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("Failed to play match");
+
+    for record in &records {
+        assert_eq!(records[0].events, record.events);
+        assert_eq!(records[0].last, record.last);
+    }
+    assert!(
+        matches!(records[0].events.last(), Some(MatchEvent::MatchEnded)),
+        "Match did not end: {:?}",
+        records[0].events.last(),
+    );
+}
+
+// Test that a player who waits long enough is placed in a match with bots in the
+// seats that couldn't be filled, including when a bot is East and has to take the
+// first turn.
+#[tokio::test]
+async fn bots_backfill_empty_seats() {
+    let server = TestServer::with_config(Config {
+        bot_backfill: Some(Duration::from_secs(1)),
+        ..Default::default()
+    });
+    let mut client = server.connect().await;
+
+    let status = client.join_queue().await.expect("Failed to join queue");
+    assert_eq!(
+        QueueStatus {
+            position: 1,
+            players_queued: 1,
+        },
+        status,
+    );
+
+    let (seat, state) = client.wait_for_match().await.expect("Failed to find match");
+    let record = play_tsumogiri(&mut client, state, seat)
+        .await
+        .expect("Failed to play match");
+    assert!(
+        matches!(record.events.last(), Some(MatchEvent::MatchEnded)),
+        "Match did not end: {:?}",
+        record.events.last(),
+    );
+}
+
+// Test that a player who leaves the queue isn't matched, and can start a match on
+// their own afterwards.
+#[tokio::test]
+async fn leave_queue() {
+    let server = TestServer::with_config(Config {
+        bot_backfill: None,
+        ..Default::default()
+    });
+
+    let mut client = server.connect().await;
+    client.join_queue().await.expect("Failed to join queue");
+    client.leave_queue().await.expect("Failed to leave queue");
+
+    let mut other = server.connect().await;
+    let status = other.join_queue().await.expect("Failed to join queue");
+    assert_eq!(1, status.players_queued, "Player is still in the queue");
+
+    let record = play_tsumogiri_match(&mut client, Wind::East)
+        .await
+        .expect("Failed to play match after leaving queue");
+    assert!(
+        matches!(record.events.last(), Some(MatchEvent::MatchEnded)),
+        "Match did not end: {:?}",
+        record.events.last(),
+    );
+}
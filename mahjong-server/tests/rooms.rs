@@ -0,0 +1,121 @@
+//! Tests for private rooms that players join with an invite code.
+
+use futures::future;
+use harness::*;
+use mahjong::{
+    match_state::Ruleset,
+    messages::{MatchEvent, RoomSeat},
+    tile::Wind,
+};
+
+mod harness;
+
+// Test that a friend can join a room using its code, that only the host can start
+// the match, and that the players in the room can play the match together with bots
+// in the remaining seats.
+#[tokio::test]
+async fn friends_join_room_and_play() {
+    let server = TestServer::start();
+    let mut host = server.connect().await;
+    let mut friend = server.connect().await;
+    let host_id = host.credentials().id;
+    let friend_id = friend.credentials().id;
+
+    let info = host
+        .create_room("Friday game", Some(Ruleset::Riichi), Wind::South)
+        .await
+        .expect("Failed to create room");
+    assert_eq!("Friday game", info.name);
+    assert_eq!(host_id, info.host);
+
+    assert_invalid_request(friend.join_room(info.code.as_str(), Wind::South).await);
+
+    // Codes are case-insensitive, so that players can type them however they like.
+    let code = info.code.as_str().to_lowercase();
+    let info = friend
+        .join_room(&code, Wind::North)
+        .await
+        .expect("Failed to join room");
+    let expected = vec![
+        RoomSeat {
+            seat: Wind::South,
+            account: host_id,
+        },
+        RoomSeat {
+            seat: Wind::North,
+            account: friend_id,
+        },
+    ];
+    assert_eq!(expected, info.seats);
+
+    let update = host
+        .next_room_update()
+        .await
+        .expect("Failed to get room update")
+        .expect("Room was closed");
+    assert_eq!(info, update);
+
+    assert_invalid_request(friend.start_room(true).await);
+    assert_invalid_request(host.start_room(false).await);
+    host.start_room(true).await.expect("Failed to start room");
+
+    let (host_found, friend_found) =
+        future::join(host.wait_for_match(), friend.wait_for_match()).await;
+    let (host_seat, host_state) = host_found.expect("Host failed to find match");
+    let (friend_seat, friend_state) = friend_found.expect("Friend failed to find match");
+    assert_eq!(Wind::South, host_seat);
+    assert_eq!(Wind::North, friend_seat);
+    assert_eq!(host_state, friend_state);
+
+    let (host_record, friend_record) = future::join(
+        play_tsumogiri(&mut host, host_state, host_seat),
+        play_tsumogiri(&mut friend, friend_state, friend_seat),
+    )
+    .await;
+    let host_record = host_record.expect("Host failed to play match");
+    let friend_record = friend_record.expect("Friend failed to play match");
+
+    assert_eq!(host_record.events, friend_record.events);
+    assert!(
+        matches!(host_record.events.last(), Some(MatchEvent::MatchEnded)),
+        "Match did not end: {:?}",
+        host_record.events.last(),
+    );
+}
+
+// Test that the room is closed when the host leaves, and that the other players can
+// go on to start a match of their own.
+#[tokio::test]
+async fn host_leaving_closes_room() {
+    let server = TestServer::start();
+    let mut host = server.connect().await;
+    let mut friend = server.connect().await;
+
+    let info = host
+        .create_room("Short-lived", None, Wind::East)
+        .await
+        .expect("Failed to create room");
+    friend
+        .join_room(info.code.as_str(), Wind::West)
+        .await
+        .expect("Failed to join room");
+
+    host.leave_room().await.expect("Failed to leave room");
+    let update = friend
+        .next_room_update()
+        .await
+        .expect("Failed to get room update");
+    assert_eq!(None, update, "Room wasn't closed");
+
+    let mut other = server.connect().await;
+    assert_invalid_request(other.join_room(info.code.as_str(), Wind::South).await);
+
+    let record = play_tsumogiri_match(&mut friend, Wind::East)
+        .await
+        .expect("Failed to play match after room closed");
+    assert!(
+        matches!(record.events.last(), Some(MatchEvent::MatchEnded)),
+        "Match did not end: {:?}",
+        record.events.last(),
+    );
+}
@@ -0,0 +1,163 @@
+//! Tests for spectating matches that are in progress.
+
+use futures::future;
+use harness::*;
+use mahjong::{
+    match_state::MatchId,
+    spectator::{SpectatorEvent, SpectatorSnapshot, SpectatorView},
+    tile::Wind,
+};
+use mahjong_headless::Client;
+use mahjong_server::config::Config;
+use std::time::{Duration, Instant};
+
+mod harness;
+
+/// Receives spectator events until the match ends, applying each one to `view`.
+async fn watch_match(
+    client: &mut Client,
+    mut view: SpectatorSnapshot,
+) -> (SpectatorSnapshot, Vec<SpectatorEvent>) {
+    let mut events = Vec::new();
+    loop {
+        let event = client
+            .next_spectator_event()
+            .await
+            .expect("Failed to receive spectator event");
+        view.apply_event(&event)
+            .unwrap_or_else(|err| panic!("Failed to apply event #{}: {}", events.len(), err));
+        events.push(event.clone());
+
+        if let SpectatorEvent::MatchEnded = event {
+            return (view, events);
+        }
+    }
+}
+
+// Test that a spectator with hidden hands can follow the whole match without ever
+// seeing a player's concealed tiles.
+#[tokio::test]
+async fn hidden_spectator_follows_match() {
+    let server = TestServer::start();
+    let mut player = server.connect().await;
+    let mut spectator = server.connect().await;
+
+    assert_invalid_request(
+        spectator
+            .spectate(MatchId::new(1234), SpectatorView::Hidden)
+            .await,
+    );
+
+    let initial = player.start_match().await.expect("Failed to start match");
+    let view = spectator
+        .spectate(initial.id, SpectatorView::Hidden)
+        .await
+        .expect("Failed to spectate match");
+    assert_eq!(
+        SpectatorSnapshot::new(&initial.snapshot(), SpectatorView::Hidden),
+        view,
+    );
+
+    let (record, (view, events)) = future::join(
+        async {
+            play_tsumogiri(&mut player, initial, Wind::East)
+                .await
+                .expect("Failed to play match")
+        },
+        watch_match(&mut spectator, view),
+    )
+    .await;
+
+    assert_eq!(record.events.len(), events.len());
+    for event in &events {
+        if let SpectatorEvent::TileDrawn { tile, .. } = event {
+            assert_eq!(&None, tile, "Spectator saw a drawn tile");
+        }
+    }
+    assert_eq!(
+        SpectatorSnapshot::new(&record.last.snapshot(), SpectatorView::Hidden),
+        view,
+    );
+}
+
+// Test that a spectator who can see every hand only sees the starting hands and
+// receives events once the spectator delay has passed.
+#[tokio::test]
+async fn revealed_spectator_is_delayed() {
+    let delay = Duration::from_secs(1);
+    let server = TestServer::with_config(Config {
+        spectator_delay: delay,
+        ..Default::default()
+    });
+    let mut player = server.connect().await;
+    let mut spectator = server.connect().await;
+
+    let state = player.start_match().await.expect("Failed to start match");
+    let started_at = Instant::now();
+    let mut view = spectator
+        .spectate(state.id, SpectatorView::Revealed)
+        .await
+        .expect("Failed to spectate match");
+    assert_eq!(
+        SpectatorSnapshot::new(&state.snapshot(), SpectatorView::Hidden),
+        view,
+    );
+
+    let event = spectator
+        .next_spectator_event()
+        .await
+        .expect("Failed to receive spectator event");
+    assert!(
+        started_at.elapsed() >= delay - Duration::from_millis(100),
+        "Hands were revealed after {:?}, expected a delay of {:?}",
+        started_at.elapsed(),
+        delay,
+    );
+    view.apply_event(&event)
+        .expect("Failed to reveal starting hands");
+    assert_eq!(
+        SpectatorSnapshot::new(&state.snapshot(), SpectatorView::Revealed),
+        view,
+    );
+
+    let tile = state.player(Wind::East).current_draw().unwrap().id;
+    player
+        .discard_tile(state.id, Wind::East, tile)
+        .await
+        .expect("Failed to discard tile");
+    let discarded_at = Instant::now();
+
+    let event = spectator
+        .next_spectator_event()
+        .await
+        .expect("Failed to receive spectator event");
+    assert!(
+        discarded_at.elapsed() >= delay - Duration::from_millis(100),
+        "Event arrived after {:?}, expected a delay of {:?}",
+        discarded_at.elapsed(),
+        delay,
+    );
+    assert_eq!(
+        SpectatorEvent::TileDiscarded {
+            seat: Wind::East,
+            tile,
+        },
+        event,
+    );
+
+    let event = spectator
+        .next_spectator_event()
+        .await
+        .expect("Failed to receive spectator event");
+    assert!(
+        matches!(
+            event,
+            SpectatorEvent::TileDrawn {
+                seat: Wind::South,
+                tile: Some(_),
+            }
+        ),
+        "Unexpected event: {:?}",
+        event,
+    );
+}
@@ -0,0 +1,138 @@
+//! Compares the size and throughput of the supported message encodings.
+//!
+//! Run with `cargo bench -p mahjong`. The encoded size of each message set is
+//! printed before the benchmarks run, and criterion reports throughput in bytes
+//! per second of encoded data.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mahjong::{
+    codec::Encoding,
+    match_state::{MatchId, MatchState},
+    messages::*,
+    tile::{self, Wind},
+};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+/// Creates the response sent when a match starts, using a fixed shuffle so that
+/// results are comparable between runs.
+fn start_match_response() -> ServerMessage {
+    let mut tiles = tile::TILE_SET.clone();
+    tiles.shuffle(&mut StdRng::seed_from_u64(0));
+
+    let mut state = MatchState::new(MatchId::new(1), tiles);
+    state.draw_for_player(Wind::East).unwrap();
+
+    ServerMessage::Response {
+        request_id: RequestId::new(0),
+        response: Response::StartMatch(StartMatchResponse {
+            snapshot: state.snapshot(),
+        }),
+    }
+}
+
+/// Plays out a full match where every player discards the tile they drew,
+/// returning every event that the server would send.
+fn event_stream() -> Vec<ServerMessage> {
+    let mut state = match start_match_response() {
+        ServerMessage::Response {
+            response: Response::StartMatch(response),
+            ..
+        } => MatchState::from_snapshot(&response.snapshot).unwrap(),
+        _ => unreachable!(),
+    };
+
+    let mut events = Vec::new();
+    let mut push = |state: &MatchState, event| {
+        events.push(ServerMessage::Event {
+            sequence: events.len(),
+            event,
+            checksum: state.checksum(),
+        });
+    };
+
+    while !state.wall.is_empty() {
+        let seat = state.current_turn;
+        let tile = state.player(seat).current_draw().unwrap().id;
+        state.discard_tile(seat, tile).unwrap();
+        push(&state, MatchEvent::TileDiscarded { seat, tile });
+
+        let seat = state.current_turn;
+        let tile = state.draw_for_player(seat).unwrap();
+        push(&state, MatchEvent::TileDrawn { seat, tile });
+    }
+    push(&state, MatchEvent::MatchEnded);
+
+    events
+}
+
+fn encoded_size(encoding: Encoding, messages: &[ServerMessage]) -> usize {
+    messages
+        .iter()
+        .map(|message| encoding.encode(message).unwrap().len())
+        .sum()
+}
+
+fn bench_messages(c: &mut Criterion, name: &str, messages: &[ServerMessage]) {
+    let mut group = c.benchmark_group(name);
+
+    for &encoding in Encoding::SUPPORTED {
+        let encoded = messages
+            .iter()
+            .map(|message| encoding.encode(message).unwrap())
+            .collect::<Vec<_>>();
+        let size = encoded.iter().map(Vec::len).sum::<usize>();
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("encode", format!("{:?}", encoding)),
+            messages,
+            |b, messages| {
+                b.iter(|| {
+                    for message in messages {
+                        encoding.encode(message).unwrap();
+                    }
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("decode", format!("{:?}", encoding)),
+            &encoded,
+            |b, encoded| {
+                b.iter(|| {
+                    for bytes in encoded {
+                        encoding.decode::<ServerMessage>(bytes).unwrap();
+                    }
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn codec(c: &mut Criterion) {
+    let start_match = vec![start_match_response()];
+    let events = event_stream();
+
+    for (name, messages) in &[
+        ("start_match_response", &start_match),
+        ("event_stream", &events),
+    ] {
+        for &encoding in Encoding::SUPPORTED {
+            println!(
+                "{} ({} messages) as {:?}: {} bytes",
+                name,
+                messages.len(),
+                encoding,
+                encoded_size(encoding, messages),
+            );
+        }
+    }
+
+    bench_messages(c, "start_match_response", &start_match);
+    bench_messages(c, "event_stream", &events);
+}
+
+criterion_group!(benches, codec);
+criterion_main!(benches);
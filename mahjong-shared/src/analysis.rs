@@ -0,0 +1,681 @@
+//! Hand analysis for computer-controlled players and other tools.
+//!
+//! The functions here work on `TileCounts`, which describes a set of tiles by how
+//! many copies of each kind of tile it contains. This ignores which specific tile
+//! instances are in the set, which doesn't matter when evaluating a hand.
+//!
+//! The main measures of a hand are its shanten, the number of tiles it needs to
+//! exchange before it's tenpai, and its ukeire, the number of tiles that would
+//! improve its shanten if drawn.
+
+use crate::{
+    hand::Hand,
+    tile::{self, SimpleTile, Suit, Tile, Wind},
+};
+use std::{cell::RefCell, collections::HashMap, ops::Index, rc::Rc};
+
+/// The number of distinct kinds of tile in the tile set.
+pub const KINDS: usize = 34;
+
+/// The shanten of a complete hand.
+pub const COMPLETE: i8 = -1;
+
+/// Returns the index for `tile` within a `TileCounts`.
+///
+/// Simple tiles come first, ordered by suit and then by number, followed by the
+/// dragons and then the winds. This is the same order as `tile::TILE_SET`.
+pub fn kind_index(tile: Tile) -> usize {
+    match tile {
+        Tile::Simple(simple) => {
+            let suit = match simple.suit {
+                Suit::Coins => 0,
+                Suit::Bamboo => 1,
+                Suit::Characters => 2,
+            };
+            suit * 9 + simple.number as usize - 1
+        }
+
+        Tile::Dragon(dragon) => 27 + dragon as usize,
+        Tile::Wind(wind) => 30 + wind as usize,
+    }
+}
+
+/// Returns the tile with the specified kind index, the inverse of `kind_index`.
+///
+/// # Panics
+///
+/// Panics if `index` isn't less than `KINDS`.
+pub fn kind(index: usize) -> Tile {
+    tile::TILE_SET[index * 4].tile
+}
+
+/// The number of copies of each kind of tile in a set of tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileCounts([u8; KINDS]);
+
+impl TileCounts {
+    pub fn new() -> Self {
+        Self([0; KINDS])
+    }
+
+    pub fn from_tiles<I>(tiles: I) -> Self
+    where
+        I: IntoIterator<Item = Tile>,
+    {
+        let mut counts = Self::new();
+        for tile in tiles {
+            counts.add(tile);
+        }
+        counts
+    }
+
+    /// Counts the concealed tiles in `hand`, including the current draw.
+    pub fn from_hand(hand: &Hand) -> Self {
+        Self::from_tiles(
+            hand.tiles()
+                .iter()
+                .chain(hand.current_draw())
+                .map(|instance| instance.tile),
+        )
+    }
+
+    pub fn add(&mut self, tile: Tile) {
+        self.0[kind_index(tile)] += 1;
+    }
+
+    /// Removes one copy of `tile`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no copies of `tile` in the set.
+    pub fn remove(&mut self, tile: Tile) {
+        let count = &mut self.0[kind_index(tile)];
+        assert!(*count > 0, "No copies of {} to remove", tile);
+        *count -= 1;
+    }
+
+    pub fn get(&self, tile: Tile) -> u8 {
+        self.0[kind_index(tile)]
+    }
+
+    /// Returns the total number of tiles in the set.
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|&count| count as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&count| count == 0)
+    }
+
+    /// Iterates over the kinds of tile in the set, along with the number of copies of
+    /// each.
+    pub fn iter(&self) -> impl Iterator<Item = (Tile, u8)> + '_ {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(index, &count)| (kind(index), count))
+    }
+}
+
+impl Default for TileCounts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<usize> for TileCounts {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &u8 {
+        &self.0[index]
+    }
+}
+
+/// Returns the number of tiles `hand` needs to exchange before it's tenpai.
+///
+/// `hand` contains the concealed tiles in the hand, and `melds` is the number of
+/// melds the hand has already made, including closed kongs. Tenpai hands have a
+/// shanten of 0, and complete hands have a shanten of `COMPLETE`.
+///
+/// Seven pairs and thirteen orphans are taken into account for hands without any
+/// melds.
+pub fn shanten(hand: &TileCounts, melds: usize) -> i8 {
+    let mut best = regular_shanten(hand, melds);
+    if melds == 0 {
+        best = best.min(seven_pairs_shanten(hand));
+        best = best.min(thirteen_orphans_shanten(hand));
+    }
+    best
+}
+
+/// Returns the kinds of tile that would lower the shanten of `hand` if drawn.
+///
+/// `hand` should be a hand waiting for a draw, i.e. one that has just discarded. For
+/// a tenpai hand, these are the tiles the hand is waiting on. Kinds that the hand
+/// already holds all four copies of are never included.
+pub fn improving_tiles(hand: &TileCounts, melds: usize) -> Vec<Tile> {
+    let current = shanten(hand, melds);
+    let mut hand = *hand;
+
+    let mut tiles = Vec::new();
+    for index in 0..KINDS {
+        if hand[index] >= 4 {
+            continue;
+        }
+
+        let tile = kind(index);
+        hand.add(tile);
+        if shanten(&hand, melds) < current {
+            tiles.push(tile);
+        }
+        hand.remove(tile);
+    }
+
+    tiles
+}
+
+/// The tiles that would improve a hand, as returned by `ukeire`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ukeire {
+    /// The kinds of tile that improve the hand.
+    pub tiles: Vec<Tile>,
+
+    /// The number of copies of those tiles that haven't been seen yet.
+    pub count: u32,
+}
+
+/// Returns the tiles that would lower the shanten of `hand` if drawn, counting only
+/// the copies of each that aren't in `visible`.
+///
+/// `visible` should include every tile the player can see, including the tiles in
+/// `hand` itself.
+pub fn ukeire(hand: &TileCounts, melds: usize, visible: &TileCounts) -> Ukeire {
+    let tiles = improving_tiles(hand, melds);
+    let count = tiles
+        .iter()
+        .map(|&tile| 4u32.saturating_sub(visible.get(tile) as u32))
+        .sum();
+
+    Ukeire { tiles, count }
+}
+
+/// Determines if `tile` is a value tile (yakuhai) for the player in `seat`.
+///
+/// Dragons are always value tiles, as are the player's seat wind and the round wind.
+/// Matches currently consist of a single hand in the East round.
+pub fn is_yakuhai(tile: Tile, seat: Wind) -> bool {
+    match tile {
+        Tile::Dragon(..) => true,
+        Tile::Wind(wind) => wind == seat || wind == Wind::East,
+        Tile::Simple(..) => false,
+    }
+}
+
+/// Returns the han that a triplet of `tile` is worth for the player in `seat`.
+///
+/// East is worth two han to the dealer, since it's both their seat wind and the
+/// round wind.
+fn yakuhai_han(tile: Tile, seat: Wind) -> u8 {
+    match tile {
+        Tile::Dragon(..) => 1,
+        Tile::Wind(wind) => (wind == seat) as u8 + (wind == Wind::East) as u8,
+        Tile::Simple(..) => 0,
+    }
+}
+
+/// Determines if `tile` is a simple tile other than a 1 or 9.
+pub fn is_tanyao_tile(tile: Tile) -> bool {
+    match tile {
+        Tile::Simple(SimpleTile { number, .. }) => number > 1 && number < 9,
+        _ => false,
+    }
+}
+
+/// Determines if the player in `seat` could legally win with `hand`.
+///
+/// `ron` is the discard being claimed, or `None` if the player is winning on their
+/// own draw. `riichi` is whether the player has declared riichi.
+///
+/// The hand must be complete and have at least one yaku, and can't win on a discard
+/// if it's furiten. Only the yaku counted by `han` are checked. This is enough for
+/// the hands that the bots aim for, but may reject hands that a player could legally
+/// win with.
+pub fn can_win(hand: &Hand, ron: Option<Tile>, seat: Wind, riichi: bool) -> bool {
+    han(hand, ron, seat, riichi).is_some()
+}
+
+/// Counts the han that the player in `seat` would score by winning with `hand`, or
+/// returns `None` if they can't legally win with it.
+///
+/// Takes the same arguments as `can_win`. Only the yaku that can be identified
+/// without fully scoring the hand are counted: riichi, menzen tsumo, tanyao,
+/// yakuhai, seven pairs, and thirteen orphans, which counts as 13 han. Dora aren't
+/// counted, since the match doesn't have a dead wall yet.
+pub fn han(hand: &Hand, ron: Option<Tile>, seat: Wind, riichi: bool) -> Option<u8> {
+    let mut concealed = TileCounts::from_hand(hand);
+    if let Some(tile) = ron {
+        concealed.add(tile);
+    }
+
+    let melds = meld_count(hand);
+    if shanten(&concealed, melds) != COMPLETE || (ron.is_some() && is_furiten(hand)) {
+        return None;
+    }
+
+    let closed = is_closed(hand);
+    if closed && thirteen_orphans_shanten(&concealed) == COMPLETE {
+        return Some(13);
+    }
+
+    let mut han = 0;
+    if riichi {
+        han += 1;
+    }
+
+    if closed && ron.is_none() {
+        han += 1;
+    }
+
+    if closed && seven_pairs_shanten(&concealed) == COMPLETE {
+        han += 2;
+    }
+
+    let meld_tiles = hand
+        .open_chows()
+        .iter()
+        .flat_map(|meld| meld.tiles.iter())
+        .chain(hand.open_pongs().iter().flat_map(|meld| meld.tiles.iter()))
+        .chain(hand.open_kongs().iter().flat_map(|meld| meld.tiles.iter()))
+        .chain(hand.closed_kongs().iter().flatten())
+        .map(|instance| instance.tile);
+    let mut all_tiles = concealed;
+    for tile in meld_tiles {
+        all_tiles.add(tile);
+    }
+    if all_tiles.iter().all(|(tile, _)| is_tanyao_tile(tile)) {
+        han += 1;
+    }
+
+    // A concealed triplet of a value tile is always part of the hand, since the hand
+    // can't use three copies of an honor any other way.
+    han += concealed
+        .iter()
+        .filter(|&(_, count)| count >= 3)
+        .map(|(tile, _)| tile)
+        .chain(hand.open_pongs().iter().map(|meld| meld.tiles[0].tile))
+        .chain(hand.open_kongs().iter().map(|meld| meld.tiles[0].tile))
+        .chain(hand.closed_kongs().iter().map(|kong| kong[0].tile))
+        .map(|tile| yakuhai_han(tile, seat))
+        .sum::<u8>();
+
+    if han > 0 {
+        Some(han)
+    } else {
+        None
+    }
+}
+
+/// Returns the points that a hand worth `han` scores when it wins.
+///
+/// Hands below mangan are scored as if they had 30 fu, since the hand isn't broken
+/// down far enough to count fu. The dealer scores half again as much as other
+/// players.
+pub fn points(han: u8, dealer: bool) -> u32 {
+    let base: u32 = match han {
+        0 => 0,
+        1..=4 => (30 << (han + 2)).min(2000),
+        5 => 2000,
+        6..=7 => 3000,
+        8..=10 => 4000,
+        11..=12 => 6000,
+        _ => 8000,
+    };
+
+    let multiplier = if dealer { 6 } else { 4 };
+    (base * multiplier).div_ceil(100) * 100
+}
+
+/// Determines if `hand` is furiten, i.e. is tenpai and waiting on a tile that the
+/// player has already discarded. Furiten hands can't win on another player's
+/// discard.
+///
+/// `hand` should not have a current draw. Only the player's own discards are
+/// checked, so passing up a winning discard doesn't make the hand furiten.
+pub fn is_furiten(hand: &Hand) -> bool {
+    let concealed = TileCounts::from_hand(hand);
+    let melds = meld_count(hand);
+    if shanten(&concealed, melds) != 0 {
+        return false;
+    }
+
+    let waits = improving_tiles(&concealed, melds);
+    hand.discards()
+        .iter()
+        .any(|discard| waits.contains(&discard.tile))
+}
+
+/// Returns the number of melds in `hand`, including closed kongs.
+pub fn meld_count(hand: &Hand) -> usize {
+    hand.open_chows().len()
+        + hand.open_pongs().len()
+        + hand.open_kongs().len()
+        + hand.closed_kongs().len()
+}
+
+/// Determines if `hand` is closed, i.e. hasn't called any other player's discards.
+pub fn is_closed(hand: &Hand) -> bool {
+    hand.open_chows().is_empty() && hand.open_pongs().is_empty() && hand.open_kongs().is_empty()
+}
+
+/// Shanten for a hand made of four melds and a pair.
+///
+/// Tiles in different suits can't form sets together, so each suit is split up
+/// separately and then the best combination of splits is chosen.
+fn regular_shanten(hand: &TileCounts, melds: usize) -> i8 {
+    let mut counts = hand.0;
+    let (simples, honors) = counts.split_at_mut(27);
+
+    // Each state is the number of sets and partial sets in the suits so far, and
+    // whether one of those suits provided the hand's pair.
+    let mut states = vec![(melds as u8, 0u8, false)];
+    for (tiles, sequences) in simples
+        .chunks_mut(9)
+        .map(|suit| (suit, true))
+        .chain(Some((honors, false)))
+    {
+        let splits = cached_suit_splits(tiles, sequences);
+
+        let mut next = Vec::new();
+        for &(sets, partials, pair) in &states {
+            for &(suit_sets, suit_partials) in &splits[0] {
+                next.push((sets + suit_sets, partials + suit_partials, pair));
+            }
+
+            if !pair {
+                for &(suit_sets, suit_partials) in &splits[1] {
+                    next.push((sets + suit_sets, partials + suit_partials, true));
+                }
+            }
+        }
+
+        next.sort_unstable();
+        next.dedup();
+        states = next;
+    }
+
+    states
+        .into_iter()
+        .map(|(sets, partials, pair)| {
+            let partials = partials.min(4u8.saturating_sub(sets));
+            8 - 2 * sets as i8 - partials as i8 - pair as i8
+        })
+        .min()
+        .unwrap()
+}
+
+/// Returns `suit_splits` for the tiles in a suit, caching the result.
+///
+/// Hands only ever have a few tiles in each suit, so the same suits come up again
+/// and again when evaluating hands, e.g. when checking each possible discard.
+fn cached_suit_splits(counts: &mut [u8], sequences: bool) -> Rc<[Vec<(u8, u8)>; 2]> {
+    type Cache = HashMap<(u32, bool), Rc<[Vec<(u8, u8)>; 2]>>;
+
+    thread_local! {
+        static CACHE: RefCell<Cache> = RefCell::new(HashMap::new());
+    }
+
+    let key = counts.iter().fold(0, |key, &count| key * 5 + count as u32);
+    if let Some(splits) = CACHE.with(|cache| cache.borrow().get(&(key, sequences)).cloned()) {
+        return splits;
+    }
+
+    let splits = Rc::new(suit_splits(counts, sequences));
+    CACHE.with(|cache| cache.borrow_mut().insert((key, sequences), splits.clone()));
+    splits
+}
+
+/// Finds the ways to split the tiles in a single suit into complete sets and
+/// partial sets, returning the number of each as `(sets, partials)`.
+///
+/// Splits are returned separately for those without and with a pair taken out for
+/// the hand's pair. Splits with fewer sets and fewer partial sets than another split
+/// can never be better, so they're left out.
+fn suit_splits(counts: &mut [u8], sequences: bool) -> [Vec<(u8, u8)>; 2] {
+    let mut splits = [Vec::new(), Vec::new()];
+    split_suit(counts, 0, sequences, (0, 0, false), &mut splits);
+
+    for splits in &mut splits {
+        let all = splits.clone();
+        splits.retain(|&(sets, partials)| {
+            !all.iter()
+                .any(|&other| other != (sets, partials) && other.0 >= sets && other.1 >= partials)
+        });
+        splits.sort_unstable();
+        splits.dedup();
+    }
+
+    splits
+}
+
+/// Recursively splits the tiles from `start` onwards, adding each complete split to
+/// `splits`.
+///
+/// Each set needs no more tiles and each partial set needs one more tile, so a hand
+/// with four sets and a pair is complete.
+fn split_suit(
+    counts: &mut [u8],
+    start: usize,
+    sequences: bool,
+    (sets, partials, pair): (u8, u8, bool),
+    splits: &mut [Vec<(u8, u8)>; 2],
+) {
+    let index = match (start..counts.len()).find(|&index| counts[index] > 0) {
+        Some(index) => index,
+        None => {
+            splits[pair as usize].push((sets, partials));
+            return;
+        }
+    };
+
+    // Try leaving one copy of the tile out of the suit's sets entirely.
+    counts[index] -= 1;
+    split_suit(counts, index, sequences, (sets, partials, pair), splits);
+    counts[index] += 1;
+
+    if counts[index] >= 3 {
+        counts[index] -= 3;
+        split_suit(counts, index, sequences, (sets + 1, partials, pair), splits);
+        counts[index] += 3;
+    }
+
+    if counts[index] >= 2 {
+        counts[index] -= 2;
+        split_suit(counts, index, sequences, (sets, partials + 1, pair), splits);
+        if !pair {
+            split_suit(counts, index, sequences, (sets, partials, true), splits);
+        }
+        counts[index] += 2;
+    }
+
+    if !sequences {
+        return;
+    }
+
+    if index + 2 < counts.len() && counts[index + 1] > 0 && counts[index + 2] > 0 {
+        counts[index] -= 1;
+        counts[index + 1] -= 1;
+        counts[index + 2] -= 1;
+        split_suit(counts, index, sequences, (sets + 1, partials, pair), splits);
+        counts[index] += 1;
+        counts[index + 1] += 1;
+        counts[index + 2] += 1;
+    }
+
+    for offset in 1..=2 {
+        if index + offset < counts.len() && counts[index + offset] > 0 {
+            counts[index] -= 1;
+            counts[index + offset] -= 1;
+            split_suit(counts, index, sequences, (sets, partials + 1, pair), splits);
+            counts[index] += 1;
+            counts[index + offset] += 1;
+        }
+    }
+}
+
+/// Shanten for a hand made of seven distinct pairs.
+fn seven_pairs_shanten(hand: &TileCounts) -> i8 {
+    let pairs = hand.0.iter().filter(|&&count| count >= 2).count() as i8;
+    let kinds = hand.0.iter().filter(|&&count| count > 0).count() as i8;
+    6 - pairs + (7 - kinds).max(0)
+}
+
+/// Shanten for a hand with one of each terminal and honor, plus a pair of any of
+/// them.
+fn thirteen_orphans_shanten(hand: &TileCounts) -> i8 {
+    let orphans = (0..KINDS)
+        .filter(|&index| index >= 27 || index % 9 == 0 || index % 9 == 8)
+        .map(|index| hand[index]);
+    let kinds = orphans.clone().filter(|&count| count > 0).count() as i8;
+    let pair = orphans.clone().any(|count| count >= 2);
+    13 - kinds - pair as i8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render, tile::TileInstance};
+
+    fn parse(mpsz: &str) -> Vec<Tile> {
+        render::parse_ascii(mpsz).unwrap()
+    }
+
+    fn counts(mpsz: &str) -> TileCounts {
+        TileCounts::from_tiles(parse(mpsz))
+    }
+
+    /// Creates a closed hand from the tiles in `mpsz`, giving it the first tile of
+    /// each kind from the tile set.
+    fn hand(mpsz: &str) -> Hand {
+        let mut used = TileCounts::new();
+        let mut tiles = parse(mpsz)
+            .into_iter()
+            .map(|tile| {
+                let index = kind_index(tile);
+                let instance: TileInstance = tile::TILE_SET[index * 4 + used[index] as usize];
+                used.add(tile);
+                instance
+            })
+            .collect::<Vec<_>>();
+        let draw = tiles.split_off(13);
+
+        let mut hand = Hand::new(&mut tiles);
+        for tile in draw {
+            hand.draw_tile(tile).unwrap();
+        }
+        hand
+    }
+
+    #[test]
+    fn kind_index_round_trips() {
+        for index in 0..KINDS {
+            assert_eq!(index, kind_index(kind(index)));
+        }
+
+        for instance in tile::TILE_SET.iter() {
+            assert_eq!(instance.id.raw() as usize / 4, kind_index(instance.tile));
+        }
+    }
+
+    #[test]
+    fn regular_hands() {
+        assert_eq!(COMPLETE, shanten(&counts("123m456p789s11122z"), 0));
+        assert_eq!(0, shanten(&counts("123m456p789s1112z"), 0));
+        assert_eq!(0, shanten(&counts("123m456p78s11122z"), 0));
+        assert_eq!(2, shanten(&counts("123m456p79s11234z"), 0));
+
+        // Melds that have already been made reduce the tiles needed.
+        assert_eq!(COMPLETE, shanten(&counts("456p11z"), 3));
+        assert_eq!(0, shanten(&counts("45p11z"), 3));
+
+        // The worst possible hand.
+        assert_eq!(6, shanten(&counts("147m258p369s1234z"), 0));
+    }
+
+    #[test]
+    fn special_hands() {
+        assert_eq!(COMPLETE, shanten(&counts("1188m2299p55s1133z"), 0));
+        assert_eq!(0, shanten(&counts("1188m2299p55s113z"), 0));
+        assert_eq!(COMPLETE, shanten(&counts("19m19p19s12345677z"), 0));
+        assert_eq!(0, shanten(&counts("19m19p19s1234567z"), 0));
+
+        // Special hands can't be made once the hand has melded.
+        assert_eq!(2, shanten(&counts("1188m2299s33z"), 1));
+    }
+
+    #[test]
+    fn improving_tiles_and_ukeire() {
+        let hand = counts("123m456p789s1112z");
+        assert_eq!(parse("2z"), improving_tiles(&hand, 0));
+
+        // Two-sided wait on 6s and 9s, with one 9s already visible.
+        let hand = counts("123m456p78s11122z");
+        let visible = TileCounts::from_tiles(parse("123m456p789s11122z"));
+        let ukeire = ukeire(&hand, 0, &visible);
+        assert_eq!(parse("69s"), ukeire.tiles);
+        assert_eq!(7, ukeire.count);
+    }
+
+    #[test]
+    fn winning_requires_yaku() {
+        // Closed hands can always win on their own draw.
+        let closed = hand("123m456p789s11122z");
+        assert!(can_win(&closed, None, Wind::South, false));
+
+        // Winning on a discard needs a yaku other than menzen tsumo.
+        let no_yaku = hand("123m456p789s1199m");
+        let ron = Some(parse("9m")[0]);
+        assert!(!can_win(&no_yaku, ron, Wind::West, false));
+        assert!(can_win(&no_yaku, ron, Wind::West, true));
+
+        let yakuhai = hand("123m456p789s5522z");
+        assert!(can_win(&yakuhai, Some(parse("5z")[0]), Wind::West, false));
+        assert!(!can_win(&yakuhai, Some(parse("2z")[0]), Wind::West, false));
+        assert!(can_win(&yakuhai, Some(parse("2z")[0]), Wind::South, false));
+
+        let tanyao = hand("234m456p678s2255p");
+        assert!(can_win(&tanyao, Some(parse("5p")[0]), Wind::North, false));
+        assert!(!can_win(&tanyao, Some(parse("1p")[0]), Wind::North, false));
+
+        // Discarding one of the hand's waits makes it furiten.
+        let mut furiten = hand("234m456p678s2255p2p");
+        let draw = furiten.current_draw().unwrap().id;
+        furiten.discard_tile(draw).unwrap();
+        assert!(is_furiten(&furiten));
+        assert!(!can_win(&furiten, Some(parse("5p")[0]), Wind::North, false));
+    }
+
+    #[test]
+    fn counts_han() {
+        let tsumo = hand("234m456p678s2255p5p");
+        assert_eq!(Some(2), han(&tsumo, None, Wind::North, false));
+        assert_eq!(Some(3), han(&tsumo, None, Wind::North, true));
+
+        // East is worth two han to the dealer.
+        let east = hand("123m456p789s11z99m");
+        let ron = Some(parse("1z")[0]);
+        assert_eq!(Some(1), han(&east, ron, Wind::South, false));
+        assert_eq!(Some(2), han(&east, ron, Wind::East, false));
+
+        let seven_pairs = hand("1155m2288p3399s66z");
+        assert_eq!(Some(3), han(&seven_pairs, None, Wind::West, false));
+        let kokushi = hand("19m19p19s1234567z1m");
+        assert_eq!(Some(13), han(&kokushi, None, Wind::West, false));
+
+        assert_eq!(1000, points(1, false));
+        assert_eq!(3900, points(3, false));
+        assert_eq!(11600, points(4, true));
+        assert_eq!(8000, points(5, false));
+        assert_eq!(32000, points(13, false));
+    }
+}
@@ -0,0 +1,345 @@
+//! Computer-controlled players.
+//!
+//! A `Bot` makes the decisions for a seat that no player controls. Bots only get to
+//! see what a player in the same seat would see, namely their own hand and the public
+//! information about the rest of the table, which is provided as a `BotView`.
+//!
+//! Bots are created from a `BotKind`, which lets the server and other tools choose a
+//! strategy without depending on the concrete bot types.
+
+use crate::{
+    analysis::{self, TileCounts},
+    hand::{Call, Hand},
+    match_state::MatchState,
+    messages::MatchEvent,
+    spectator::{SpectatorEvent, SpectatorSnapshot, SpectatorView},
+    tile::{self, TileId, TileInstance, Wind},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt::Debug};
+use strum::EnumString;
+
+pub use self::{
+    defensive::DefensiveBot,
+    efficiency::EfficiencyBot,
+    mjai::MjaiBot,
+    monte_carlo::{MonteCarloBot, SearchBudget},
+};
+
+pub mod defensive;
+pub mod efficiency;
+pub mod mjai;
+pub mod monte_carlo;
+
+/// The fewest tiles that must be left in the wall for a player to declare riichi.
+const RIICHI_MIN_WALL: usize = 4;
+
+/// Decision-making logic for a computer-controlled seat.
+///
+/// The match calls into the bot whenever the seat needs to make a decision, passing
+/// in the bot's current view of the match. Bots may keep their own state between
+/// decisions, e.g. to track reads on other players, using `observe` to follow along
+/// with the match.
+///
+/// Not every decision is supported everywhere a bot can play. The server's match
+/// logic can't end a match with a win or make calls yet, so live matches set
+/// `BotView::wins_allowed` to `false` and never call `respond_to_discard`. Riichi
+/// is tracked by whatever runs the match, and `simulation` supports riichi and wins
+/// but not calls.
+pub trait Bot: Debug + Send {
+    /// Notifies the bot of an event in the match.
+    ///
+    /// `view` already includes the event. The event is redacted the same way as
+    /// `view`, so the tile drawn is only visible for the bot's own draws.
+    fn observe(&mut self, view: &BotView, event: &SpectatorEvent) {
+        let _ = (view, event);
+    }
+
+    /// Chooses what to do on the bot's turn, once it has drawn a tile.
+    fn take_turn(&mut self, view: &BotView) -> TurnDecision;
+
+    /// Chooses whether to claim `discard`, which another player just discarded.
+    ///
+    /// `calls` lists the calls that the bot's hand can make with the discard. The
+    /// default implementation always passes.
+    fn respond_to_discard(
+        &mut self,
+        view: &BotView,
+        discard: TileInstance,
+        calls: &[Call],
+    ) -> CallDecision {
+        let _ = (view, discard, calls);
+        CallDecision::Pass
+    }
+}
+
+/// A bot's decision on its own turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TurnDecision {
+    Discard(TileId),
+
+    /// Declares riichi, discarding the specified tile.
+    Riichi(TileId),
+
+    /// Wins with the tile the bot just drew.
+    Tsumo,
+}
+
+/// A bot's decision when another player discards a tile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallDecision {
+    Pass,
+    Call(Call),
+
+    /// Wins with the discarded tile.
+    Ron,
+}
+
+/// The information about a match that's available to the player in `seat`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotView {
+    pub seat: Wind,
+
+    /// The bot's own hand, including its current draw.
+    pub hand: Hand,
+
+    /// The public information about every seat, i.e. what a spectator would see with
+    /// `SpectatorView::Hidden`.
+    pub table: SpectatorSnapshot,
+
+    /// The players that have declared riichi, along with the index within their
+    /// discards of the tile they declared riichi with.
+    ///
+    /// The match logic doesn't support riichi yet, so this is only populated by
+    /// whatever runs the match, e.g. the server or `simulation`, if it tracks riichi
+    /// declarations itself.
+    pub riichi: HashMap<Wind, usize>,
+
+    /// Whether the bot may declare tsumo. When `false`, bots must keep discarding
+    /// even if their hand is complete.
+    pub wins_allowed: bool,
+}
+
+impl BotView {
+    /// Creates the view of `state` for the player in `seat`.
+    pub fn new(state: &MatchState, seat: Wind) -> Self {
+        Self {
+            seat,
+            hand: state.player(seat).clone(),
+            table: SpectatorSnapshot::new(&state.snapshot(), SpectatorView::Hidden),
+            riichi: HashMap::new(),
+            wins_allowed: true,
+        }
+    }
+
+    /// Determines if the bot can declare riichi by discarding `tile`.
+    ///
+    /// The bot must not have declared riichi already, its hand must be closed and
+    /// tenpai after the discard, and there must be enough tiles left in the wall.
+    pub fn can_riichi(&self, tile: TileId) -> bool {
+        let hand = &self.hand;
+        let discard = match hand
+            .tiles()
+            .iter()
+            .chain(hand.current_draw())
+            .find(|instance| instance.id == tile)
+        {
+            Some(instance) => instance.tile,
+            None => return false,
+        };
+
+        let mut concealed = TileCounts::from_hand(hand);
+        concealed.remove(discard);
+
+        !self.riichi.contains_key(&self.seat)
+            && analysis::is_closed(hand)
+            && self.table.tiles_remaining >= RIICHI_MIN_WALL
+            && analysis::shanten(&concealed, analysis::meld_count(hand)) == 0
+    }
+
+    /// Redacts `event` for the bot, hiding the tiles drawn by other players.
+    pub fn redact(&self, event: &MatchEvent) -> SpectatorEvent {
+        match *event {
+            MatchEvent::TileDrawn { seat, tile } if seat == self.seat => {
+                SpectatorEvent::TileDrawn {
+                    seat,
+                    tile: Some(tile),
+                }
+            }
+
+            _ => SpectatorEvent::new(event, SpectatorView::Hidden),
+        }
+    }
+
+    /// Counts every tile the bot can see: the tiles in its own hand, plus the
+    /// discards and melds of every player.
+    pub fn visible_tiles(&self) -> TileCounts {
+        let mut visible = TileCounts::from_hand(&self.hand);
+        for hand in &self.table.hands {
+            let tiles = hand
+                .discards
+                .iter()
+                .chain(hand.open_chows.iter().flat_map(|meld| &meld.tiles))
+                .chain(hand.open_pongs.iter().flat_map(|meld| &meld.tiles))
+                .chain(hand.open_kongs.iter().flat_map(|meld| &meld.tiles))
+                .chain(hand.closed_kongs.iter().flatten());
+            for &id in tiles {
+                visible.add(tile::by_id(id));
+            }
+        }
+        visible
+    }
+}
+
+/// The strategies available for computer-controlled seats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumString)]
+pub enum BotKind {
+    /// Always discards the first tile in its hand. Only useful for testing.
+    FirstTile,
+
+    /// Plays for the fastest hand it can make, without any regard for defense. Used
+    /// for the "normal" difficulty.
+    Efficiency,
+
+    /// Plays for its hand like `Efficiency`, but folds when another player is likely
+    /// to win and its own hand isn't worth the risk. Used for the "hard" difficulty.
+    Defensive,
+
+    /// Simulates the rest of the match to choose each discard, using the default
+    /// `SearchBudget`. Stronger than `Defensive`, but much slower.
+    MonteCarlo,
+
+    /// Delegates every decision to an external MJAI engine, see `MjaiBot`. The
+    /// command that starts the engine has to be provided separately, e.g. with
+    /// `Simulation::mjai_engine`.
+    Mjai,
+}
+
+impl BotKind {
+    /// Creates a new bot using this strategy.
+    ///
+    /// `seed` seeds any randomness used by the bot, so that a bot's decisions can be
+    /// reproduced.
+    ///
+    /// `Mjai` bots are created without an engine, so they always discard their draw.
+    /// Use `MjaiBot::spawn` to start a bot with an engine.
+    pub fn create(self, seed: u64) -> Box<dyn Bot> {
+        match self {
+            BotKind::FirstTile => Box::new(FirstTileBot),
+            BotKind::Efficiency => Box::new(EfficiencyBot::new()),
+            BotKind::Defensive => Box::new(DefensiveBot::new()),
+            BotKind::MonteCarlo => Box::new(MonteCarloBot::new(seed)),
+            BotKind::Mjai => Box::new(MjaiBot::without_engine()),
+        }
+    }
+}
+
+/// Bot that always discards the first tile in its hand.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FirstTileBot;
+
+impl Bot for FirstTileBot {
+    fn take_turn(&mut self, view: &BotView) -> TurnDecision {
+        TurnDecision::Discard(view.hand.tiles()[0].id)
+    }
+}
+
+/// Helpers for setting up bots in tests.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use crate::{analysis, match_state::MatchId, render};
+
+    /// Creates the view for a bot in the South seat holding `mpsz`, where any tiles
+    /// after the first 13 are drawn.
+    pub fn bot_view(mpsz: &str) -> BotView {
+        let mut used = TileCounts::new();
+        let mut tiles = render::parse_ascii(mpsz)
+            .unwrap()
+            .into_iter()
+            .map(|tile| {
+                let index = analysis::kind_index(tile);
+                let instance = tile::TILE_SET[index * 4 + used[index] as usize];
+                used.add(tile);
+                instance
+            })
+            .collect::<Vec<_>>();
+        let draws = tiles.split_off(13);
+
+        let mut hand = Hand::new(&mut tiles);
+        for draw in draws {
+            hand.draw_tile(draw).unwrap();
+        }
+
+        let state = MatchState::new(MatchId::new(1), tile::TILE_SET.clone());
+        BotView {
+            hand,
+            ..BotView::new(&state, Wind::South)
+        }
+    }
+
+    /// Returns a copy of the tile in `mpsz` that isn't visible to the bot.
+    pub fn unseen(view: &BotView, mpsz: &str) -> TileInstance {
+        let tile = render::parse_ascii(mpsz).unwrap()[0];
+        let index = analysis::kind_index(tile);
+        tile::TILE_SET[index * 4 + view.visible_tiles()[index] as usize]
+    }
+
+    /// Adds the tiles in `mpsz` to the discards of the player in `seat`.
+    pub fn discard(view: &mut BotView, seat: Wind, mpsz: &str) {
+        for tile in render::parse_ascii(mpsz).unwrap() {
+            let id = unseen(view, &tile.to_string()).id;
+            view.table.hands[seat as usize].discards.push(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{match_state::MatchId, tile};
+
+    #[test]
+    fn view_hides_other_hands() {
+        let mut state = MatchState::new(MatchId::new(1), tile::TILE_SET.clone());
+        let draw = state.draw_for_player(Wind::East).unwrap();
+
+        let view = BotView::new(&state, Wind::South);
+        assert_eq!(state.player(Wind::South), &view.hand);
+        assert!(view.table.hand(Wind::East).has_draw);
+        assert_eq!(None, view.table.hand(Wind::East).current_draw);
+
+        let event = MatchEvent::TileDrawn {
+            seat: Wind::East,
+            tile: draw,
+        };
+        let expected = SpectatorEvent::TileDrawn {
+            seat: Wind::East,
+            tile: None,
+        };
+        assert_eq!(expected, view.redact(&event));
+
+        let view = BotView::new(&state, Wind::East);
+        let expected = SpectatorEvent::TileDrawn {
+            seat: Wind::East,
+            tile: Some(draw),
+        };
+        assert_eq!(expected, view.redact(&event));
+    }
+
+    #[test]
+    fn riichi_requires_tenpai() {
+        let mut view = testing::bot_view("123m456p789s56s11z4z");
+        let draw = view.hand.current_draw().unwrap().id;
+        let first = view.hand.tiles()[0].id;
+        assert!(view.can_riichi(draw));
+        assert!(!view.can_riichi(first));
+
+        view.table.tiles_remaining = RIICHI_MIN_WALL - 1;
+        assert!(!view.can_riichi(draw));
+
+        view.table.tiles_remaining = RIICHI_MIN_WALL;
+        view.riichi.insert(view.seat, 0);
+        assert!(!view.can_riichi(draw));
+    }
+}
@@ -0,0 +1,195 @@
+//! Bot that weighs building its hand against the risk of dealing in.
+
+use super::{
+    efficiency::{self, DiscardOption},
+    Bot, BotView, CallDecision, TurnDecision,
+};
+use crate::{
+    analysis::{self, TileCounts},
+    danger::DangerModel,
+    hand::Call,
+    tile::{TileInstance, TILE_SET},
+};
+
+/// The estimated value of an opponent's hand, used to weigh the cost of dealing in.
+pub(super) const OPPONENT_HAND_VALUE: f32 = 5000.0;
+
+/// The most that the bot's own hand can be worth, i.e. a mangan.
+const MAX_HAND_VALUE: f32 = 8000.0;
+
+/// How much less likely the hand is to win for each step it is from tenpai.
+const SHANTEN_PENALTY: f32 = 0.35;
+
+/// The highest threat from an opponent at which the bot still calls pon, since
+/// calling leaves fewer tiles in its hand to defend with.
+const MAX_CALL_THREAT: f32 = 0.3;
+
+/// Bot that plays for its hand while it's safe to do so, and folds when an opponent
+/// is threatening to win.
+///
+/// Each possible discard is scored by how likely it is to lead to a win, weighted by
+/// an estimate of the hand's value, minus how likely it is to deal in, weighted by
+/// the value of a typical hand. Against an opponent in riichi, a weak hand folds by
+/// discarding its safest tiles, while a strong hand keeps pushing. Otherwise the bot
+/// plays much like `EfficiencyBot`.
+#[derive(Debug, Clone, Default)]
+pub struct DefensiveBot {
+    /// Whether the bot has declared riichi, after which it only discards its draws.
+    riichi: bool,
+}
+
+impl DefensiveBot {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Bot for DefensiveBot {
+    fn take_turn(&mut self, view: &BotView) -> TurnDecision {
+        if view.wins_allowed && analysis::can_win(&view.hand, None, view.seat, self.riichi) {
+            return TurnDecision::Tsumo;
+        }
+
+        if self.riichi {
+            if let Some(draw) = view.hand.current_draw() {
+                return TurnDecision::Discard(draw.id);
+            }
+        }
+
+        let danger = DangerModel::new(view);
+        let value = hand_value(view);
+        let unseen = TILE_SET.len() - view.visible_tiles().len();
+
+        // NOTE: Options are ordered from the most efficient, and only a strictly better
+        // score replaces the current best, so ties go to the more efficient discard.
+        let options = efficiency::discard_options(view);
+        let mut best: Option<(&DiscardOption, f32)> = None;
+        for option in &options {
+            let win = win_chance(option, view.table.tiles_remaining, unseen) * value;
+            let loss = danger.deal_in_chance(option.tile.tile) * OPPONENT_HAND_VALUE;
+            let score = win - loss;
+
+            match best {
+                Some((_, best_score)) if best_score >= score => {}
+                _ => best = Some((option, score)),
+            }
+        }
+        let (best, _) = best.expect("Hand has no tiles to discard");
+
+        if efficiency::should_riichi(view, best) {
+            self.riichi = true;
+            return TurnDecision::Riichi(best.tile.id);
+        }
+
+        TurnDecision::Discard(best.tile.id)
+    }
+
+    fn respond_to_discard(
+        &mut self,
+        view: &BotView,
+        discard: TileInstance,
+        calls: &[Call],
+    ) -> CallDecision {
+        if analysis::can_win(&view.hand, Some(discard.tile), view.seat, self.riichi) {
+            return CallDecision::Ron;
+        }
+
+        if !self.riichi
+            && analysis::is_yakuhai(discard.tile, view.seat)
+            && calls.contains(&Call::Pon)
+            && DangerModel::new(view).max_threat() <= MAX_CALL_THREAT
+        {
+            return CallDecision::Call(Call::Pon);
+        }
+
+        CallDecision::Pass
+    }
+}
+
+/// Estimates the chance that the hand goes on to win after making `option`.
+///
+/// This is the chance of drawing one of the hand's improving tiles before the wall
+/// runs out, discounted for each further step the hand needs to take.
+fn win_chance(option: &DiscardOption, tiles_remaining: usize, unseen: usize) -> f32 {
+    if unseen == 0 {
+        return 0.0;
+    }
+
+    let draws = (tiles_remaining / 4) as i32;
+    let per_draw = option.ukeire.count as f32 / unseen as f32;
+    let improve = 1.0 - (1.0 - per_draw.min(1.0)).powi(draws);
+    improve * SHANTEN_PENALTY.powi(option.shanten.max(0) as i32)
+}
+
+/// Estimates how many points the bot's hand would be worth if it won.
+///
+/// Counts the han the hand is likely to have: one for the yaku it needs to win at
+/// all, one for riichi if the hand is closed, and one for each value tile it has a
+/// pair or triplet of, plus tanyao if the hand only has simples.
+pub(super) fn hand_value(view: &BotView) -> f32 {
+    let concealed = TileCounts::from_hand(&view.hand);
+
+    let mut han = 1.0;
+    if analysis::is_closed(&view.hand) {
+        han += 1.0;
+    }
+
+    han += concealed
+        .iter()
+        .filter(|&(tile, count)| count >= 2 && analysis::is_yakuhai(tile, view.seat))
+        .count() as f32;
+    han += view
+        .hand
+        .open_pongs()
+        .iter()
+        .filter(|meld| analysis::is_yakuhai(meld.tiles[0].tile, view.seat))
+        .count() as f32;
+
+    if concealed
+        .iter()
+        .all(|(tile, _)| analysis::is_tanyao_tile(tile))
+    {
+        han += 1.0;
+    }
+
+    (1000.0 * 2f32.powf(han - 1.0)).min(MAX_HAND_VALUE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bot::testing::*,
+        tile::{self, Wind},
+    };
+
+    // Test that a weak hand folds against riichi by discarding a safe tile.
+    #[test]
+    fn folds_against_riichi() {
+        let mut view = bot_view("147m258p3699s134z5m");
+        discard(&mut view, Wind::East, "6m1p7s1z");
+        view.riichi.insert(Wind::East, 3);
+        view.table.tiles_remaining = 40;
+
+        let mut bot = DefensiveBot::new();
+        let decision = bot.take_turn(&view);
+        let tile = match decision {
+            TurnDecision::Discard(tile) => tile::by_id(tile),
+            _ => panic!("Unexpected decision {:?}", decision),
+        };
+
+        let danger = DangerModel::new(&view);
+        assert_eq!(0.0, danger.deal_in_chance(tile), "Discarded {}", tile);
+    }
+
+    // Test that without any threat the bot plays the same as the efficiency bot.
+    #[test]
+    fn pushes_without_threat() {
+        let view = bot_view("123m456p789s56s11z4z");
+        let options = efficiency::discard_options(&view);
+        assert_eq!(
+            TurnDecision::Riichi(options[0].tile.id),
+            DefensiveBot::new().take_turn(&view),
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Bot that plays for the fastest possible hand.
+
+use super::{Bot, BotView, CallDecision, TurnDecision};
+use crate::{
+    analysis::{self, TileCounts, Ukeire},
+    hand::Call,
+    tile::{Tile, TileInstance, Wind},
+};
+use std::cmp::Reverse;
+
+/// The fewest unseen tiles that a tenpai hand can be waiting on for the bot to
+/// declare riichi.
+const GOOD_WAIT: u32 = 4;
+
+/// Bot that discards whichever tile leaves its hand closest to winning.
+///
+/// Discards are chosen to minimize the hand's shanten, breaking ties by the number
+/// of unseen tiles that would improve it. The bot declares riichi once it's tenpai
+/// with a good wait, calls pon on value tiles, and always wins when it can.
+#[derive(Debug, Clone, Default)]
+pub struct EfficiencyBot {
+    /// Whether the bot has declared riichi, after which it only discards its draws.
+    riichi: bool,
+}
+
+impl EfficiencyBot {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Bot for EfficiencyBot {
+    fn take_turn(&mut self, view: &BotView) -> TurnDecision {
+        if view.wins_allowed && analysis::can_win(&view.hand, None, view.seat, self.riichi) {
+            return TurnDecision::Tsumo;
+        }
+
+        if self.riichi {
+            if let Some(draw) = view.hand.current_draw() {
+                return TurnDecision::Discard(draw.id);
+            }
+        }
+
+        let best = &discard_options(view)[0];
+        if should_riichi(view, best) {
+            self.riichi = true;
+            return TurnDecision::Riichi(best.tile.id);
+        }
+
+        TurnDecision::Discard(best.tile.id)
+    }
+
+    fn respond_to_discard(
+        &mut self,
+        view: &BotView,
+        discard: TileInstance,
+        calls: &[Call],
+    ) -> CallDecision {
+        if analysis::can_win(&view.hand, Some(discard.tile), view.seat, self.riichi) {
+            return CallDecision::Ron;
+        }
+
+        if !self.riichi
+            && analysis::is_yakuhai(discard.tile, view.seat)
+            && calls.contains(&Call::Pon)
+        {
+            return CallDecision::Call(Call::Pon);
+        }
+
+        CallDecision::Pass
+    }
+}
+
+/// A tile that a bot could discard, along with how efficient its hand would be
+/// afterwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscardOption {
+    pub tile: TileInstance,
+
+    /// The shanten of the hand after the discard.
+    pub shanten: i8,
+
+    /// The tiles that would improve the hand after the discard.
+    pub ukeire: Ukeire,
+}
+
+/// Evaluates each tile that the bot in `view` could discard, ordered from the most
+/// to the least efficient.
+///
+/// Only one copy of each kind of tile is included. Where discards are otherwise
+/// equal, the current draw comes first.
+pub fn discard_options(view: &BotView) -> Vec<DiscardOption> {
+    let hand = &view.hand;
+    let melds = analysis::meld_count(hand);
+    let visible = view.visible_tiles();
+    let mut counts = TileCounts::from_hand(hand);
+
+    let mut options = Vec::<DiscardOption>::new();
+    for &tile in hand.current_draw().into_iter().chain(hand.tiles()) {
+        if options.iter().any(|option| option.tile.tile == tile.tile) {
+            continue;
+        }
+
+        counts.remove(tile.tile);
+        options.push(DiscardOption {
+            tile,
+            shanten: analysis::shanten(&counts, melds),
+            ukeire: analysis::ukeire(&counts, melds, &visible),
+        });
+        counts.add(tile.tile);
+    }
+
+    // NOTE: The sort is stable, so the current draw stays first among discards that
+    // are equally good.
+    options.sort_by_key(|option| {
+        (
+            option.shanten,
+            Reverse(option.ukeire.count),
+            keep_value(option.tile.tile, view.seat),
+        )
+    });
+    options
+}
+
+/// Determines if the bot should declare riichi when making `discard`.
+///
+/// The bot must be able to declare riichi with the discard (see
+/// `BotView::can_riichi`), and the hand must be waiting on at least two kinds of
+/// tile without being furiten.
+pub(super) fn should_riichi(view: &BotView, discard: &DiscardOption) -> bool {
+    let waits = &discard.ukeire.tiles;
+    let furiten = view
+        .hand
+        .discards()
+        .iter()
+        .chain(Some(&discard.tile))
+        .any(|tile| waits.contains(&tile.tile));
+
+    view.can_riichi(discard.tile.id)
+        && waits.len() >= 2
+        && discard.ukeire.count >= GOOD_WAIT
+        && !furiten
+}
+
+/// Returns how much the bot wants to keep `tile` when discards are otherwise equal,
+/// where lower values are discarded first.
+///
+/// Honors that aren't value tiles are the least useful, since they can only ever
+/// form pairs and triplets. Simple tiles closer to the middle of their suit are the
+/// most useful, since they can form the most sequences.
+fn keep_value(tile: Tile, seat: Wind) -> u8 {
+    match tile {
+        Tile::Simple(simple) => 2 + (simple.number - 1).min(9 - simple.number),
+        _ if analysis::is_yakuhai(tile, seat) => 1,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::testing::*;
+
+    #[test]
+    fn discards_least_useful_tile() {
+        let view = bot_view("123m456p79s114z7z5m8m");
+        let options = discard_options(&view);
+
+        // Discarding either lone honor leaves the hand 2-shanten, but the red dragon is
+        // a value tile.
+        assert_eq!(2, options[0].shanten);
+        assert_eq!("4z", options[0].tile.tile.to_string());
+        assert_eq!(
+            TurnDecision::Discard(options[0].tile.id),
+            EfficiencyBot::new().take_turn(&view),
+        );
+    }
+
+    #[test]
+    fn riichi_then_win() {
+        let mut bot = EfficiencyBot::new();
+
+        // Tenpai on 4s and 7s once the north wind is discarded.
+        let mut view = bot_view("123m456p789s56s11z4z");
+        let north = view.hand.current_draw().unwrap().id;
+        assert_eq!(TurnDecision::Riichi(north), bot.take_turn(&view));
+        view.hand.discard_tile(north).unwrap();
+
+        // After riichi the bot only discards its draws, until it can win.
+        let draw = unseen(&view, "5z");
+        view.hand.draw_tile(draw).unwrap();
+        assert_eq!(TurnDecision::Discard(draw.id), bot.take_turn(&view));
+        view.hand.discard_tile(draw.id).unwrap();
+
+        let discard = unseen(&view, "7s");
+        assert_eq!(
+            CallDecision::Ron,
+            bot.respond_to_discard(&view, discard, &[])
+        );
+
+        view.hand.draw_tile(unseen(&view, "4s")).unwrap();
+        assert_eq!(TurnDecision::Tsumo, bot.take_turn(&view));
+    }
+
+    #[test]
+    fn calls_value_tiles() {
+        let mut bot = EfficiencyBot::new();
+
+        let view = bot_view("1239m456p789s55z1p");
+        let white = unseen(&view, "5z");
+        let calls = view.hand.find_possible_calls(&white, false);
+        assert_eq!(
+            CallDecision::Call(Call::Pon),
+            bot.respond_to_discard(&view, white, &calls),
+        );
+
+        // The bot could win on 8s, but the hand has no yaku.
+        let view = bot_view("123m456p79s11z789m");
+        let eight = unseen(&view, "8s");
+        let calls = view.hand.find_possible_calls(&eight, true);
+        assert_eq!(
+            CallDecision::Pass,
+            bot.respond_to_discard(&view, eight, &calls),
+        );
+    }
+}
@@ -0,0 +1,355 @@
+//! Bot that delegates its decisions to an external AI speaking MJAI.
+
+use super::{Bot, BotView, CallDecision, TurnDecision};
+use crate::{
+    hand::Call,
+    messages::ClientRequest,
+    mjai::{self, Message, Translator},
+    spectator::SpectatorEvent,
+    tile::{TileId, TileInstance},
+};
+use anyhow::{bail, Context, Result};
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+use tracing::*;
+
+/// Bot that runs an MJAI engine as a local subprocess, speaking to it over the
+/// engine's stdin and stdout.
+///
+/// Every event in the match is sent to the engine from the perspective of the bot's
+/// seat. The engine's response to its own draws decides the bot's turn, and its
+/// response to other players' discards decides whether the bot calls.
+///
+/// If the engine responds with an action that isn't legal, the bot discards its draw
+/// or passes instead. If the engine exits or sends something that isn't valid MJAI,
+/// the error is logged and the bot keeps doing so for the rest of the match.
+///
+/// Every call into the bot blocks until the engine has responded, so async code must
+/// run the bot on a blocking thread, e.g. with `tokio::task::spawn_blocking`.
+#[derive(Debug)]
+pub struct MjaiBot {
+    engine: Option<Engine>,
+
+    /// The translator for the bot's seat, created once the bot sees its first event.
+    translator: Option<Translator>,
+
+    /// The engine's decision for the bot's current turn.
+    turn: Option<TurnDecision>,
+
+    /// The engine's response to the last discard by another player.
+    call: Option<Message>,
+
+    /// The actor whose riichi needs to be accepted before the next event.
+    reach: Option<u8>,
+}
+
+impl MjaiBot {
+    /// Starts the engine by running `command`.
+    ///
+    /// The engine's stdin and stdout are used to speak MJAI, while its stderr is
+    /// inherited, so that any logs from the engine are still visible.
+    pub fn spawn(mut command: Command) -> Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to start MJAI engine {:?}", command))?;
+        let stdin = child.stdin.take().expect("Engine stdin wasn't piped");
+        let stdout = child.stdout.take().expect("Engine stdout wasn't piped");
+
+        Ok(Self {
+            engine: Some(Engine {
+                child,
+                stdin,
+                stdout: BufReader::new(stdout),
+            }),
+            ..Self::without_engine()
+        })
+    }
+
+    /// Creates a bot without an engine, which behaves the same as a bot whose engine
+    /// has failed.
+    pub fn without_engine() -> Self {
+        Self {
+            engine: None,
+            translator: None,
+            turn: None,
+            call: None,
+            reach: None,
+        }
+    }
+
+    /// Sends `message` to the engine and returns its response, or `None` if the
+    /// engine has failed.
+    fn send(&mut self, message: &Message) -> Option<Message> {
+        let engine = self.engine.as_mut()?;
+        match engine.send(message) {
+            Ok(Message::Error { message }) => {
+                warn!(?message, "MJAI engine reported an error");
+                Some(Message::None)
+            }
+
+            Ok(response) => Some(response),
+
+            Err(err) => {
+                error!(
+                    "MJAI engine failed, falling back to discarding draws: {:?}",
+                    err
+                );
+                self.engine = None;
+                None
+            }
+        }
+    }
+
+    /// Converts the engine's response to the bot's draw into the decision for its
+    /// turn.
+    fn turn_decision(&mut self, view: &BotView, response: Message) -> Result<TurnDecision> {
+        let decision = match response {
+            Message::Hora { .. } if view.wins_allowed => TurnDecision::Tsumo,
+            Message::Dahai { .. } => TurnDecision::Discard(self.discard(view, &response)?),
+
+            // The engine declares riichi and then waits for the riichi to be echoed back
+            // before it chooses the tile to discard.
+            Message::Reach { actor } => match self.send(&Message::Reach { actor }) {
+                Some(discard) => TurnDecision::Riichi(self.discard(view, &discard)?),
+                None => bail!("MJAI engine didn't discard after declaring riichi"),
+            },
+
+            _ => bail!("Unsupported MJAI action for turn: {:?}", response),
+        };
+
+        Ok(decision)
+    }
+
+    /// Returns the tile discarded by `message`.
+    fn discard(&self, view: &BotView, message: &Message) -> Result<TileId> {
+        let translator = self.translator.as_ref().expect("Game wasn't started");
+        match translator.request(view.table.id, &view.hand, message)? {
+            ClientRequest::DiscardTile(request) => Ok(request.tile),
+            request => bail!("Unexpected request {:?}", request),
+        }
+    }
+}
+
+impl Bot for MjaiBot {
+    fn observe(&mut self, view: &BotView, event: &SpectatorEvent) {
+        self.turn = None;
+        self.call = None;
+
+        if self.translator.is_none() {
+            let translator = Translator::new(view.seat);
+            for message in translator.start(view.hand.tiles()) {
+                self.send(&message);
+            }
+            self.translator = Some(translator);
+        }
+
+        if let Some(actor) = self.reach.take() {
+            self.send(&Message::ReachAccepted { actor });
+        }
+
+        // The match doesn't announce riichi, so it's sent to the engine along with the
+        // riichi tile instead. The bot's own riichi was sent when the engine declared
+        // it.
+        if let SpectatorEvent::TileDiscarded { seat, .. } = *event {
+            let index = view.table.hand(seat).discards.len().checked_sub(1);
+            if index.is_some() && view.riichi.get(&seat) == index.as_ref() {
+                let actor = mjai::actor(seat);
+                if seat != view.seat {
+                    self.send(&Message::Reach { actor });
+                }
+                self.reach = Some(actor);
+            }
+        }
+
+        let own_actor = mjai::actor(view.seat);
+        let messages = self.translator.as_mut().unwrap().event(event);
+        for message in messages {
+            if message == Message::EndGame {
+                if let Some(mut engine) = self.engine.take() {
+                    let _ = engine.notify(&message);
+                }
+                continue;
+            }
+
+            let response = match self.send(&message) {
+                Some(response) => response,
+                None => continue,
+            };
+
+            match message {
+                Message::Tsumo { actor, .. } if actor == own_actor => {
+                    match self.turn_decision(view, response) {
+                        Ok(decision) => self.turn = Some(decision),
+                        Err(err) => warn!("Invalid turn from MJAI engine: {:?}", err),
+                    }
+                }
+
+                Message::Dahai { actor, .. } if actor != own_actor => self.call = Some(response),
+
+                _ => {}
+            }
+        }
+    }
+
+    fn take_turn(&mut self, view: &BotView) -> TurnDecision {
+        if let Some(decision) = self.turn.take() {
+            return decision;
+        }
+
+        let tile = view
+            .hand
+            .current_draw()
+            .unwrap_or_else(|| &view.hand.tiles()[0]);
+        TurnDecision::Discard(tile.id)
+    }
+
+    fn respond_to_discard(
+        &mut self,
+        view: &BotView,
+        discard: TileInstance,
+        calls: &[Call],
+    ) -> CallDecision {
+        let response = match self.call.take() {
+            Some(response) => response,
+            None => return CallDecision::Pass,
+        };
+
+        if let Message::Hora { .. } = response {
+            return CallDecision::Ron;
+        }
+
+        let translator = self.translator.as_ref().expect("Game wasn't started");
+        match translator.call(&view.hand, discard, &response) {
+            Ok(Some(call)) if calls.contains(&call) => CallDecision::Call(call),
+            Ok(_) => CallDecision::Pass,
+            Err(err) => {
+                warn!("Invalid call from MJAI engine: {:?}", err);
+                CallDecision::Pass
+            }
+        }
+    }
+}
+
+/// A running MJAI engine.
+#[derive(Debug)]
+struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Engine {
+    /// Sends `message` to the engine and waits for its response.
+    fn send(&mut self, message: &Message) -> Result<Message> {
+        self.notify(message)?;
+
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            bail!("MJAI engine closed its output");
+        }
+
+        serde_json::from_str(&line)
+            .with_context(|| format!("Invalid response from MJAI engine: {:?}", line.trim()))
+    }
+
+    /// Sends `message` to the engine without waiting for a response.
+    fn notify(&mut self, message: &Message) -> Result<()> {
+        let json = serde_json::to_string(message)?;
+        writeln!(self.stdin, "{}", json)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{
+        bot::testing::*,
+        tile::{self, Wind},
+    };
+
+    /// Starts an engine that discards every tile that the South player draws.
+    fn tsumogiri_engine() -> MjaiBot {
+        let mut command = Command::new("sed");
+        command.args([
+            "-u",
+            "-E",
+            "-e",
+            concat!(
+                r#"s/^\{"type":"tsumo","actor":1,"pai":"([^"]+)"\}$/"#,
+                r#"{"type":"dahai","actor":1,"pai":"\1","tsumogiri":true}/"#,
+            ),
+            "-e",
+            "t",
+            "-e",
+            r#"s/.*/{"type":"none"}/"#,
+        ]);
+        MjaiBot::spawn(command).unwrap()
+    }
+
+    #[test]
+    fn follows_engine() {
+        let mut view = bot_view("123m456p789s56s11z4z");
+        let draw = view.hand.current_draw().unwrap().id;
+        let mut bot = tsumogiri_engine();
+
+        bot.observe(
+            &view,
+            &SpectatorEvent::TileDrawn {
+                seat: Wind::South,
+                tile: Some(draw),
+            },
+        );
+        assert_eq!(TurnDecision::Discard(draw), bot.take_turn(&view));
+        assert!(bot.engine.is_some());
+
+        view.hand.discard_tile(draw).unwrap();
+        discard(&mut view, Wind::West, "1z");
+        let id = *view.table.hand(Wind::West).discards.last().unwrap();
+        let east = TileInstance::new(tile::by_id(id), id);
+        bot.observe(
+            &view,
+            &SpectatorEvent::TileDiscarded {
+                seat: Wind::West,
+                tile: id,
+            },
+        );
+
+        // The engine never calls, even though the bot could pon.
+        let calls = view.hand.find_possible_calls(&east, false);
+        assert!(calls.contains(&Call::Pon));
+        assert_eq!(
+            CallDecision::Pass,
+            bot.respond_to_discard(&view, east, &calls)
+        );
+    }
+
+    #[test]
+    fn falls_back_when_engine_exits() {
+        let view = bot_view("123m456p789s56s11z4z");
+        let draw = view.hand.current_draw().unwrap().id;
+        let mut bot = MjaiBot::spawn(Command::new("true")).unwrap();
+
+        bot.observe(
+            &view,
+            &SpectatorEvent::TileDrawn {
+                seat: Wind::South,
+                tile: Some(draw),
+            },
+        );
+        assert!(bot.engine.is_none());
+        assert_eq!(TurnDecision::Discard(draw), bot.take_turn(&view));
+    }
+}
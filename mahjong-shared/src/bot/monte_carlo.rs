@@ -0,0 +1,342 @@
+//! Bot that chooses its discards by simulating the rest of the match.
+
+use super::{
+    defensive::{self, OPPONENT_HAND_VALUE},
+    efficiency::{self, DiscardOption},
+    Bot, BotView, CallDecision, TurnDecision,
+};
+use crate::{
+    analysis::{self, TileCounts},
+    hand::Call,
+    tile::{Tile, TileInstance, Wind},
+};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
+
+/// The number of the most efficient discards that the bot compares, since simulating
+/// every possible discard would take too long.
+const CANDIDATES: usize = 5;
+
+/// Limits on how much work the bot does for each decision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchBudget {
+    /// The most samples to play out for each decision.
+    pub samples: usize,
+
+    /// The most time to spend on each decision, or `None` for no limit. At least one
+    /// sample is always played out, even if it takes longer than this.
+    pub time: Option<Duration>,
+}
+
+impl SearchBudget {
+    /// A budget limited only by the number of samples, which makes the bot's
+    /// decisions reproducible for a given seed. Useful for offline analysis.
+    pub fn samples(samples: usize) -> Self {
+        Self {
+            samples,
+            time: None,
+        }
+    }
+}
+
+/// The default budget is small enough to use for live matches.
+impl Default for SearchBudget {
+    fn default() -> Self {
+        Self {
+            samples: 200,
+            time: Some(Duration::from_millis(200)),
+        }
+    }
+}
+
+/// Bot that picks the discard with the best expected score over many simulations of
+/// the rest of the match.
+///
+/// Each sample deals the tiles the bot can't see into plausible hands for its
+/// opponents and a plausible order for the wall, and then plays out the match from
+/// each of the candidate discards, with every player following a fast greedy policy.
+/// Winning scores the bot's estimated hand value, while dealing in or another player
+/// winning on their own draw costs the value of a typical hand.
+///
+/// The playouts ignore yaku and furiten, treating any complete hand as a win.
+#[derive(Debug, Clone)]
+pub struct MonteCarloBot {
+    rng: StdRng,
+    budget: SearchBudget,
+
+    /// Whether the bot has declared riichi, after which it only discards its draws.
+    riichi: bool,
+}
+
+impl MonteCarloBot {
+    pub fn new(seed: u64) -> Self {
+        Self::with_budget(seed, Default::default())
+    }
+
+    pub fn with_budget(seed: u64, budget: SearchBudget) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            budget,
+            riichi: false,
+        }
+    }
+
+    /// Plays out samples for each of `candidates` until the budget runs out,
+    /// returning the average score for each candidate.
+    fn search(&mut self, view: &BotView, candidates: &[DiscardOption]) -> Vec<f32> {
+        let start = Instant::now();
+        let value = defensive::hand_value(view);
+
+        let visible = view.visible_tiles();
+        let mut unseen = (0..analysis::KINDS)
+            .flat_map(|index| {
+                let count = 4u8.saturating_sub(visible[index]);
+                (0..count).map(move |_| analysis::kind(index))
+            })
+            .collect::<Vec<_>>();
+
+        let mut totals = vec![0.0; candidates.len()];
+        let mut samples = 0;
+        while samples < self.budget.samples.max(1) {
+            let out_of_time = match self.budget.time {
+                Some(time) => start.elapsed() >= time,
+                None => false,
+            };
+            if samples > 0 && out_of_time {
+                break;
+            }
+
+            unseen.shuffle(&mut self.rng);
+            let sample = Sample::deal(view, &unseen);
+            for (total, candidate) in totals.iter_mut().zip(candidates) {
+                *total += sample.play_out(view.seat, candidate.tile.tile, value);
+            }
+
+            samples += 1;
+        }
+
+        totals
+            .into_iter()
+            .map(|total| total / samples as f32)
+            .collect()
+    }
+}
+
+impl Bot for MonteCarloBot {
+    fn take_turn(&mut self, view: &BotView) -> TurnDecision {
+        if view.wins_allowed && analysis::can_win(&view.hand, None, view.seat, self.riichi) {
+            return TurnDecision::Tsumo;
+        }
+
+        if self.riichi {
+            if let Some(draw) = view.hand.current_draw() {
+                return TurnDecision::Discard(draw.id);
+            }
+        }
+
+        let mut options = efficiency::discard_options(view);
+        options.truncate(CANDIDATES);
+
+        let best = if options.len() > 1 {
+            let scores = self.search(view, &options);
+            let mut best = 0;
+            for (index, &score) in scores.iter().enumerate() {
+                if score > scores[best] {
+                    best = index;
+                }
+            }
+            &options[best]
+        } else {
+            &options[0]
+        };
+
+        if efficiency::should_riichi(view, best) {
+            self.riichi = true;
+            return TurnDecision::Riichi(best.tile.id);
+        }
+
+        TurnDecision::Discard(best.tile.id)
+    }
+
+    fn respond_to_discard(
+        &mut self,
+        view: &BotView,
+        discard: TileInstance,
+        calls: &[Call],
+    ) -> CallDecision {
+        if analysis::can_win(&view.hand, Some(discard.tile), view.seat, self.riichi) {
+            return CallDecision::Ron;
+        }
+
+        if !self.riichi
+            && analysis::is_yakuhai(discard.tile, view.seat)
+            && calls.contains(&Call::Pon)
+        {
+            return CallDecision::Call(Call::Pon);
+        }
+
+        CallDecision::Pass
+    }
+}
+
+/// One possible arrangement of the tiles that the bot can't see.
+struct Sample {
+    /// The concealed tiles for each seat, in wind order starting from East. The bot's
+    /// own hand is the real one.
+    hands: Vec<TileCounts>,
+
+    /// The number of melds each seat has made.
+    melds: Vec<usize>,
+
+    /// The remaining tiles in the wall, in the order they'll be drawn.
+    wall: Vec<Tile>,
+}
+
+impl Sample {
+    /// Deals the tiles in `unseen` into the hands of the bot's opponents and then the
+    /// wall.
+    fn deal(view: &BotView, unseen: &[Tile]) -> Self {
+        let mut unseen = unseen.iter().copied();
+
+        let mut hands = Vec::new();
+        let mut melds = Vec::new();
+        for seat in Wind::iter() {
+            let hand = view.table.hand(seat);
+            melds.push(
+                hand.open_chows.len()
+                    + hand.open_pongs.len()
+                    + hand.open_kongs.len()
+                    + hand.closed_kongs.len(),
+            );
+
+            if seat == view.seat {
+                hands.push(TileCounts::from_hand(&view.hand));
+            } else {
+                let count = hand.tiles.len() + hand.has_draw as usize;
+                hands.push(TileCounts::from_tiles(unseen.by_ref().take(count)));
+            }
+        }
+
+        Self {
+            hands,
+            melds,
+            wall: unseen.take(view.table.tiles_remaining).collect(),
+        }
+    }
+
+    /// Plays out the rest of the match after `seat` discards `discard`, returning the
+    /// score for `seat`.
+    ///
+    /// Every player discards whichever tile leaves them with the lowest shanten, and
+    /// wins as soon as their hand is complete.
+    fn play_out(&self, seat: Wind, discard: Tile, value: f32) -> f32 {
+        let mut hands = self.hands.clone();
+        let mut wall = self.wall.iter();
+
+        let mut discarder = seat;
+        let mut discard = discard;
+        hands[seat as usize].remove(discard);
+
+        loop {
+            // Check if another player can win on the discard, in turn order.
+            let mut other = discarder.next();
+            while other != discarder {
+                let hand = &mut hands[other as usize];
+                hand.add(discard);
+                let complete =
+                    analysis::shanten(hand, self.melds[other as usize]) == analysis::COMPLETE;
+                hand.remove(discard);
+
+                if complete {
+                    return if discarder == seat {
+                        -OPPONENT_HAND_VALUE
+                    } else if other == seat {
+                        value
+                    } else {
+                        0.0
+                    };
+                }
+
+                other = other.next();
+            }
+
+            let current = discarder.next();
+            let draw = match wall.next() {
+                Some(&draw) => draw,
+                None => return 0.0,
+            };
+
+            let hand = &mut hands[current as usize];
+            let melds = self.melds[current as usize];
+            hand.add(draw);
+            if analysis::shanten(hand, melds) == analysis::COMPLETE {
+                return if current == seat {
+                    value
+                } else {
+                    -OPPONENT_HAND_VALUE / 3.0
+                };
+            }
+
+            discard = greedy_discard(hand, melds);
+            hand.remove(discard);
+            discarder = current;
+        }
+    }
+}
+
+/// Chooses the discard that leaves `hand` with the lowest shanten.
+///
+/// Honors are checked first, so that they're discarded when discards are otherwise
+/// equal.
+fn greedy_discard(hand: &TileCounts, melds: usize) -> Tile {
+    let mut hand = *hand;
+    let mut best = None;
+    for index in (0..analysis::KINDS).rev() {
+        if hand[index] == 0 {
+            continue;
+        }
+
+        let tile = analysis::kind(index);
+        hand.remove(tile);
+        let shanten = analysis::shanten(&hand, melds);
+        hand.add(tile);
+
+        match best {
+            Some((_, best_shanten)) if best_shanten <= shanten => {}
+            _ => best = Some((tile, shanten)),
+        }
+    }
+
+    best.expect("Hand has no tiles to discard").0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::testing::*;
+
+    #[test]
+    fn finds_tenpai_discard() {
+        let view = bot_view("123m456p789s56s11z4z");
+        let mut bot = MonteCarloBot::with_budget(0, SearchBudget::samples(20));
+        let north = view.hand.current_draw().unwrap().id;
+        assert_eq!(TurnDecision::Riichi(north), bot.take_turn(&view));
+    }
+
+    #[test]
+    fn sample_budget_is_reproducible() {
+        let view = bot_view("147m258p3699s134z5m");
+        let decisions = (0..2)
+            .map(|_| MonteCarloBot::with_budget(7, SearchBudget::samples(10)).take_turn(&view))
+            .collect::<Vec<_>>();
+        assert_eq!(decisions[0], decisions[1]);
+
+        let candidates = efficiency::discard_options(&view)
+            .into_iter()
+            .take(CANDIDATES)
+            .map(|option| TurnDecision::Discard(option.tile.id))
+            .collect::<Vec<_>>();
+        assert!(candidates.contains(&decisions[0]));
+    }
+}
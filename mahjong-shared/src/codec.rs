@@ -0,0 +1,155 @@
+//! Encodings for the messages sent between the client and the server.
+//!
+//! The handshake is always sent as JSON so that any client can connect. The client
+//! lists the encodings it supports in its handshake request, and the server picks
+//! the encoding used for every message after the handshake. The server sends JSON
+//! messages as text frames and binary encodings as binary frames, and accepts
+//! messages from the client in either kind of frame.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+/// A format used to encode messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Encoding {
+    Json,
+
+    /// [MessagePack](https://msgpack.org), with structs encoded as arrays rather
+    /// than maps in order to keep messages compact.
+    MessagePack,
+}
+
+impl Encoding {
+    /// Every encoding supported by this version of the crate, in order of preference.
+    pub const SUPPORTED: &'static [Encoding] = &[Encoding::MessagePack, Encoding::Json];
+
+    /// Selects the encoding to use for a session, given the encodings supported by
+    /// the client in order of preference.
+    ///
+    /// Falls back to JSON if the client doesn't support any other encoding, since
+    /// every client supports JSON.
+    pub fn negotiate(client: &[Encoding]) -> Encoding {
+        client
+            .iter()
+            .copied()
+            .find(|encoding| Self::SUPPORTED.contains(encoding))
+            .unwrap_or(Encoding::Json)
+    }
+
+    /// Returns `true` if messages should be sent as binary frames rather than text
+    /// frames.
+    pub fn is_binary(self) -> bool {
+        match self {
+            Encoding::Json => false,
+            Encoding::MessagePack => true,
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, CodecError> {
+        match self {
+            Encoding::Json => Ok(serde_json::to_vec(value)?),
+            Encoding::MessagePack => Ok(rmp_serde::to_vec(value)?),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, CodecError> {
+        match self {
+            Encoding::Json => Ok(serde_json::from_slice(bytes)?),
+            Encoding::MessagePack => Ok(rmp_serde::from_slice(bytes)?),
+        }
+    }
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Json
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CodecError {
+    #[error("Invalid JSON message: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Failed to encode MessagePack message: {0}")]
+    MessagePackEncode(#[from] rmp_serde::encode::Error),
+
+    #[error("Invalid MessagePack message: {0}")]
+    MessagePackDecode(#[from] rmp_serde::decode::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        match_state::{MatchId, MatchState},
+        messages::*,
+        tile::{self, Wind},
+    };
+
+    fn messages() -> Vec<ServerMessage> {
+        let state = MatchState::new(MatchId::new(7), tile::TILE_SET.clone());
+        let tile = state.player(Wind::East).tiles()[0].id;
+
+        vec![
+            ServerMessage::Heartbeat,
+            ServerMessage::Response {
+                request_id: RequestId::new(3),
+                response: Response::StartMatch(StartMatchResponse {
+                    snapshot: state.snapshot(),
+                }),
+            },
+            ServerMessage::Event {
+                sequence: 12,
+                event: MatchEvent::TileDiscarded {
+                    seat: Wind::East,
+                    tile,
+                },
+                checksum: state.checksum(),
+            },
+            ServerMessage::Error(ErrorResponse {
+                request_id: None,
+                kind: ErrorKind::InvalidMessage,
+                message: String::from("Invalid message"),
+            }),
+        ]
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        for &encoding in Encoding::SUPPORTED {
+            for message in messages() {
+                let bytes = encoding.encode(&message).unwrap();
+                let decoded = encoding.decode::<ServerMessage>(&bytes).unwrap();
+                assert_eq!(message, decoded, "Round trip failed for {:?}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn message_pack_is_smaller() {
+        for message in messages() {
+            let json = Encoding::Json.encode(&message).unwrap();
+            let message_pack = Encoding::MessagePack.encode(&message).unwrap();
+            assert!(
+                message_pack.len() <= json.len(),
+                "MessagePack was larger than JSON for {:?}",
+                message,
+            );
+        }
+    }
+
+    #[test]
+    fn negotiate_encoding() {
+        assert_eq!(Encoding::Json, Encoding::negotiate(&[]));
+        assert_eq!(Encoding::Json, Encoding::negotiate(&[Encoding::Json]));
+        assert_eq!(
+            Encoding::MessagePack,
+            Encoding::negotiate(&[Encoding::MessagePack, Encoding::Json]),
+        );
+        assert_eq!(
+            Encoding::Json,
+            Encoding::negotiate(&[Encoding::Json, Encoding::MessagePack]),
+        );
+    }
+}
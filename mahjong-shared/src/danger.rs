@@ -0,0 +1,340 @@
+//! Estimates of how likely a discard is to deal into another player's hand.
+//!
+//! The danger of a tile depends on how likely each opponent is to be tenpai, and how
+//! likely the tile is to be one of their waits if they are. Both are estimated from
+//! the information visible to the player, using the same reads that human players
+//! rely on:
+//!
+//! * Genbutsu: a player can't win on a tile they've already discarded, and a player
+//!   in riichi can't win on a tile that anyone has discarded since their riichi.
+//! * Suji: if a player has discarded a 4, they can't be waiting on 1 or 7 with a
+//!   two-sided wait, since a two-sided wait on either would also be waiting on 4.
+//! * Kabe: if every copy of a tile is visible, no player can be using it in their
+//!   wait. If only one copy is unseen (one-chance), such waits are unlikely.
+//! * Riichi timing: players in riichi are certainly tenpai, and tiles close to the
+//!   tile they declared riichi with are more likely to be part of their wait.
+//!
+//! The estimates are rough heuristics rather than exact probabilities, but they're
+//! good enough to rank discards from safest to most dangerous.
+
+use crate::{
+    analysis::TileCounts,
+    bot::BotView,
+    tile::{self, SimpleTile, Tile, TileInstance, Wind},
+};
+use std::cmp::Ordering;
+
+/// The chance that a tenpai player is waiting on a tile with each two-sided wait
+/// that could include it.
+const RYANMEN: f32 = 0.06;
+
+/// The chance that a tenpai player is waiting on a tile with a closed wait.
+const KANCHAN: f32 = 0.02;
+
+/// The chance that a tenpai player is waiting on a tile with an edge wait.
+const PENCHAN: f32 = 0.02;
+
+/// The chance that a tenpai player is waiting on a simple tile with a single or
+/// double pair wait, for each copy of the tile that they could be holding.
+const SIMPLE_PAIR: f32 = 0.015;
+
+/// The same as `SIMPLE_PAIR`, but for honors. Honors are more often left as pairs,
+/// since they can't be used in sequences.
+const HONOR_PAIR: f32 = 0.025;
+
+/// How much less likely a wait is when only one copy of one of the tiles it needs is
+/// unseen.
+const ONE_CHANCE: f32 = 0.5;
+
+/// How much more likely a tile close to a player's riichi tile is to be in their
+/// wait.
+const RIICHI_NEIGHBOR: f32 = 1.5;
+
+/// The number of discards after which a player who hasn't called or declared riichi
+/// is estimated to be tenpai.
+const TENPAI_DISCARDS: f32 = 18.0;
+
+/// How much more likely a player is to be tenpai for each open meld they've made.
+const TENPAI_PER_MELD: f32 = 0.1;
+
+/// The highest chance that a player who hasn't declared riichi is estimated to be
+/// tenpai.
+const MAX_HIDDEN_TENPAI: f32 = 0.85;
+
+/// The danger of discarding tiles for the player in a `BotView`.
+#[derive(Debug, Clone)]
+pub struct DangerModel {
+    opponents: Vec<Opponent>,
+
+    /// Every tile visible to the player, including their own hand.
+    visible: TileCounts,
+}
+
+/// What the player can tell about one of their opponents.
+#[derive(Debug, Clone)]
+pub struct Opponent {
+    pub seat: Wind,
+
+    /// The estimated chance that the opponent is tenpai.
+    pub tenpai: f32,
+
+    /// The tiles that the opponent can't win on, i.e. their genbutsu.
+    safe: TileCounts,
+
+    /// The tiles that the opponent has discarded themselves.
+    discarded: TileCounts,
+
+    /// The tile the opponent declared riichi with, if they're in riichi.
+    riichi_tile: Option<Tile>,
+}
+
+impl DangerModel {
+    pub fn new(view: &BotView) -> Self {
+        let opponents = view
+            .table
+            .hands
+            .iter()
+            .enumerate()
+            .map(|(index, _)| seat_at(index))
+            .filter(|&seat| seat != view.seat)
+            .map(|seat| Opponent::new(view, seat))
+            .collect();
+
+        Self {
+            opponents,
+            visible: view.visible_tiles(),
+        }
+    }
+
+    pub fn opponents(&self) -> &[Opponent] {
+        &self.opponents
+    }
+
+    /// Returns the highest chance that any opponent is tenpai.
+    pub fn max_threat(&self) -> f32 {
+        self.opponents
+            .iter()
+            .map(|opponent| opponent.tenpai)
+            .fold(0.0, f32::max)
+    }
+
+    /// Estimates the chance that `tile` is one of `opponent`'s waits, assuming that
+    /// they're tenpai.
+    pub fn wait_chance(&self, opponent: &Opponent, tile: Tile) -> f32 {
+        if opponent.is_safe(tile) {
+            return 0.0;
+        }
+
+        let chance = match tile {
+            Tile::Simple(simple) => self.simple_wait_chance(opponent, simple),
+            _ => HONOR_PAIR * self.unseen(tile) as f32,
+        };
+
+        // Players often declare riichi by discarding a tile that was next to their
+        // wait, e.g. discarding 2 from 2-4-5 to wait on 3 and 6.
+        let near_riichi_tile = match (tile, opponent.riichi_tile) {
+            (Tile::Simple(simple), Some(Tile::Simple(riichi))) => {
+                simple.suit == riichi.suit
+                    && simple.number != riichi.number
+                    && (simple.number as i8 - riichi.number as i8).abs() <= 2
+            }
+            _ => false,
+        };
+
+        if near_riichi_tile {
+            (chance * RIICHI_NEIGHBOR).min(1.0)
+        } else {
+            chance.min(1.0)
+        }
+    }
+
+    /// Estimates the chance that discarding `tile` deals into any opponent's hand.
+    pub fn deal_in_chance(&self, tile: Tile) -> f32 {
+        let safe_chance = self
+            .opponents
+            .iter()
+            .map(|opponent| 1.0 - opponent.tenpai * self.wait_chance(opponent, tile))
+            .product::<f32>();
+        1.0 - safe_chance
+    }
+
+    /// Ranks `tiles` from the safest to the most dangerous discard, along with the
+    /// chance of each one dealing in.
+    pub fn rank<I>(&self, tiles: I) -> Vec<(TileInstance, f32)>
+    where
+        I: IntoIterator<Item = TileInstance>,
+    {
+        let mut ranked = tiles
+            .into_iter()
+            .map(|instance| (instance, self.deal_in_chance(instance.tile)))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|(_, left), (_, right)| left.partial_cmp(right).unwrap_or(Ordering::Equal));
+        ranked
+    }
+
+    fn simple_wait_chance(&self, opponent: &Opponent, simple: SimpleTile) -> f32 {
+        let number = simple.number as i8;
+        let offset = |offset: i8| -> Option<Tile> {
+            let number = number + offset;
+            if (1..=9).contains(&number) {
+                Some(SimpleTile::new(simple.suit, number as u8).into())
+            } else {
+                None
+            }
+        };
+
+        // How much each tile that the wait needs limits the chance of the wait, based
+        // on how many copies of it are unseen.
+        let needs = |tile: Tile| match self.unseen(tile) {
+            0 => 0.0,
+            1 => ONE_CHANCE,
+            _ => 1.0,
+        };
+
+        let mut chance = 0.0;
+
+        // Two-sided and edge waits, made from the two tiles either above or below the
+        // tile. A two-sided wait also waits on the tile at the other end, so the
+        // opponent can't be using one if they've discarded that tile.
+        for &(first, second, other_end) in &[(1, 2, 3), (-1, -2, -3)] {
+            if let (Some(first), Some(second)) = (offset(first), offset(second)) {
+                let shape = needs(first) * needs(second);
+                match offset(other_end) {
+                    Some(other_end) if opponent.discarded.get(other_end) > 0 => {}
+                    Some(_) => chance += RYANMEN * shape,
+                    None => chance += PENCHAN * shape,
+                }
+            }
+        }
+
+        if let (Some(below), Some(above)) = (offset(-1), offset(1)) {
+            chance += KANCHAN * needs(below) * needs(above);
+        }
+
+        chance + SIMPLE_PAIR * self.unseen(simple.into()) as f32
+    }
+
+    /// Returns the number of copies of `tile` that the player can't see.
+    fn unseen(&self, tile: Tile) -> u8 {
+        4u8.saturating_sub(self.visible.get(tile))
+    }
+}
+
+impl Opponent {
+    fn new(view: &BotView, seat: Wind) -> Self {
+        let hand = view.table.hand(seat);
+        let discarded = TileCounts::from_tiles(hand.discards.iter().map(|&id| tile::by_id(id)));
+        let riichi = view.riichi.get(&seat).copied();
+
+        // Once a player has declared riichi, they can't win on any tile that another
+        // player has discarded since, since they would have had to pass on it.
+        let mut safe = discarded;
+        if let Some(index) = riichi {
+            let riichi_turn = turn(seat, index);
+            for (other_index, other) in view.table.hands.iter().enumerate() {
+                let other_seat = seat_at(other_index);
+                for (discard_index, &id) in other.discards.iter().enumerate() {
+                    if turn(other_seat, discard_index) > riichi_turn {
+                        safe.add(tile::by_id(id));
+                    }
+                }
+            }
+        }
+
+        let melds = hand.open_chows.len() + hand.open_pongs.len() + hand.open_kongs.len();
+        let tenpai = if riichi.is_some() {
+            1.0
+        } else {
+            let turns = hand.discards.len() as f32 / TENPAI_DISCARDS;
+            (turns * turns + melds as f32 * TENPAI_PER_MELD).min(MAX_HIDDEN_TENPAI)
+        };
+
+        Self {
+            seat,
+            tenpai,
+            safe,
+            discarded,
+            riichi_tile: riichi
+                .and_then(|index| hand.discards.get(index))
+                .map(|&id| tile::by_id(id)),
+        }
+    }
+
+    /// Determines if `tile` is genbutsu against the opponent, i.e. a tile they can't
+    /// win on.
+    pub fn is_safe(&self, tile: Tile) -> bool {
+        self.safe.get(tile) > 0
+    }
+}
+
+/// Returns the seat whose hand is at `index` in a `SpectatorSnapshot`.
+fn seat_at(index: usize) -> Wind {
+    (0..index).fold(Wind::East, |seat, _| seat.next())
+}
+
+/// Returns the turn in which `seat` made the discard at `index` in their discards,
+/// counting turns from the start of the match.
+///
+/// This assumes that turns go around the table in order. Calls would skip players'
+/// turns, but the match logic doesn't support calls yet.
+fn turn(seat: Wind, index: usize) -> usize {
+    index * 4 + seat as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bot::testing::*, render};
+
+    fn tile(mpsz: &str) -> Tile {
+        render::parse_ascii(mpsz).unwrap()[0]
+    }
+
+    #[test]
+    fn reads_discards() {
+        let mut view = bot_view("2468m2468p88s677z");
+        discard(&mut view, Wind::East, "4m5z9m3p");
+        discard(&mut view, Wind::West, "66z88s2s");
+        view.riichi.insert(Wind::East, 3);
+
+        let model = DangerModel::new(&view);
+        let east = &model.opponents()[0];
+        assert_eq!(Wind::East, east.seat);
+        assert_eq!(1.0, east.tenpai);
+
+        // Genbutsu, including tiles discarded after the riichi.
+        assert_eq!(0.0, model.wait_chance(east, tile("9m")));
+        assert_eq!(0.0, model.wait_chance(east, tile("2s")));
+        assert!(model.wait_chance(east, tile("3s")) > 0.0);
+
+        // Suji: discarding 4m rules out a two-sided wait on 1m.
+        assert!(model.wait_chance(east, tile("1m")) < model.wait_chance(east, tile("1p")));
+
+        // Kabe: every 8s is visible, so 9s can only be waited on as a pair.
+        assert!(model.wait_chance(east, tile("9s")) < model.wait_chance(east, tile("9p")));
+
+        // Honors with fewer unseen copies are safer.
+        assert!(model.wait_chance(east, tile("6z")) < model.wait_chance(east, tile("7z")));
+
+        // Tiles near the riichi tile are more dangerous.
+        assert!(model.wait_chance(east, tile("5p")) > model.wait_chance(east, tile("5s")));
+
+        let ranked = model.rank(view.hand.tiles().iter().copied());
+        assert_eq!(0.0, ranked[0].1);
+        assert!(ranked.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    }
+
+    #[test]
+    fn threat_grows_over_time() {
+        let mut view = bot_view("2468m2468p88s677z");
+        let model = DangerModel::new(&view);
+        assert_eq!(0.0, model.max_threat());
+        assert_eq!(0.0, model.deal_in_chance(tile("5m")));
+
+        discard(&mut view, Wind::North, "19m19p19s1234z");
+        let model = DangerModel::new(&view);
+        assert!(model.max_threat() > 0.0);
+        assert!(model.max_threat() < 1.0);
+        assert!(model.deal_in_chance(tile("5m")) > 0.0);
+    }
+}
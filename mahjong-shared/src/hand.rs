@@ -1,4 +1,7 @@
-use crate::tile::{self, TileId, TileInstance};
+use crate::{
+    sync::{self, HandSnapshot, InvalidSnapshot},
+    tile::{self, TileId, TileInstance, Wind},
+};
 use cs_bindgen::prelude::*;
 use fehler::{throw, throws};
 use itertools::Itertools;
@@ -32,9 +35,9 @@ pub struct Hand {
 
     // "Inactive" tiles, i.e. ones that are in open melds (or a closed kong) and cannot
     // be discarded.
-    open_chows: Vec<[TileInstance; 3]>,
-    open_pongs: Vec<[TileInstance; 3]>,
-    open_kongs: Vec<[TileInstance; 4]>,
+    open_chows: Vec<OpenMeld<[TileInstance; 3]>>,
+    open_pongs: Vec<OpenMeld<[TileInstance; 3]>>,
+    open_kongs: Vec<OpenMeld<[TileInstance; 4]>>,
     closed_kongs: Vec<[TileInstance; 4]>,
 
     // The player's discard pile.
@@ -187,15 +190,15 @@ impl Hand {
         self.current_draw.as_ref()
     }
 
-    pub fn open_chows(&self) -> &[[TileInstance; 3]] {
+    pub fn open_chows(&self) -> &[OpenMeld<[TileInstance; 3]>] {
         &self.open_chows
     }
 
-    pub fn open_pongs(&self) -> &[[TileInstance; 3]] {
+    pub fn open_pongs(&self) -> &[OpenMeld<[TileInstance; 3]>] {
         &self.open_pongs
     }
 
-    pub fn open_kongs(&self) -> &[[TileInstance; 4]] {
+    pub fn open_kongs(&self) -> &[OpenMeld<[TileInstance; 4]>] {
         &self.open_kongs
     }
 
@@ -206,6 +209,111 @@ impl Hand {
     pub fn discards(&self) -> &[TileInstance] {
         &self.discards
     }
+
+    /// Creates a compact snapshot of the hand. See `MatchState::snapshot`.
+    pub fn snapshot(&self) -> HandSnapshot {
+        fn ids(tiles: &[TileInstance]) -> Vec<TileId> {
+            tiles.iter().map(|tile| tile.id).collect()
+        }
+
+        fn meld<T, U>(meld: &OpenMeld<T>, tiles: impl Fn(&T) -> U) -> OpenMeld<U> {
+            OpenMeld {
+                tiles: tiles(&meld.tiles),
+                called: meld.called,
+                called_from: meld.called_from,
+            }
+        }
+
+        let ids_3 = |[a, b, c]: &[TileInstance; 3]| [a.id, b.id, c.id];
+        let ids_4 = |[a, b, c, d]: &[TileInstance; 4]| [a.id, b.id, c.id, d.id];
+
+        HandSnapshot {
+            tiles: ids(&self.tiles),
+            current_draw: self.current_draw.map(|tile| tile.id),
+            open_chows: self.open_chows.iter().map(|m| meld(m, ids_3)).collect(),
+            open_pongs: self.open_pongs.iter().map(|m| meld(m, ids_3)).collect(),
+            open_kongs: self.open_kongs.iter().map(|m| meld(m, ids_4)).collect(),
+            closed_kongs: self.closed_kongs.iter().map(ids_4).collect(),
+            discards: ids(&self.discards),
+        }
+    }
+
+    /// Recreates a hand from a snapshot. See `MatchState::from_snapshot`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot contains an unknown tile ID.
+    pub fn from_snapshot(snapshot: &HandSnapshot) -> Result<Self, InvalidSnapshot> {
+        fn instances(ids: &[TileId]) -> Result<Vec<TileInstance>, InvalidSnapshot> {
+            ids.iter().map(|&id| sync::instance(id)).collect()
+        }
+
+        fn meld<T, U>(
+            meld: &OpenMeld<T>,
+            tiles: impl Fn(&T) -> Result<U, InvalidSnapshot>,
+        ) -> Result<OpenMeld<U>, InvalidSnapshot> {
+            Ok(OpenMeld {
+                tiles: tiles(&meld.tiles)?,
+                called: meld.called,
+                called_from: meld.called_from,
+            })
+        }
+
+        let instances_3 = |&[a, b, c]: &[TileId; 3]| -> Result<_, InvalidSnapshot> {
+            Ok([sync::instance(a)?, sync::instance(b)?, sync::instance(c)?])
+        };
+        let instances_4 = |&[a, b, c, d]: &[TileId; 4]| -> Result<_, InvalidSnapshot> {
+            Ok([
+                sync::instance(a)?,
+                sync::instance(b)?,
+                sync::instance(c)?,
+                sync::instance(d)?,
+            ])
+        };
+
+        Ok(Hand {
+            tiles: instances(&snapshot.tiles)?,
+            current_draw: snapshot.current_draw.map(sync::instance).transpose()?,
+            open_chows: snapshot
+                .open_chows
+                .iter()
+                .map(|m| meld(m, instances_3))
+                .collect::<Result<_, _>>()?,
+            open_pongs: snapshot
+                .open_pongs
+                .iter()
+                .map(|m| meld(m, instances_3))
+                .collect::<Result<_, _>>()?,
+            open_kongs: snapshot
+                .open_kongs
+                .iter()
+                .map(|m| meld(m, instances_4))
+                .collect::<Result<_, _>>()?,
+            closed_kongs: snapshot
+                .closed_kongs
+                .iter()
+                .map(instances_4)
+                .collect::<Result<_, _>>()?,
+            discards: instances(&snapshot.discards)?,
+        })
+    }
+}
+
+/// A meld formed by calling another player's discard.
+///
+/// In addition to the tiles in the meld, tracks which tile was called and which
+/// player it was called from. This is needed in order to display the meld, since
+/// the called tile is traditionally rotated to indicate where it came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenMeld<T> {
+    /// The tiles in the meld, including the called tile.
+    pub tiles: T,
+
+    /// The tile that was taken from another player's discards.
+    pub called: TileId,
+
+    /// The seat of the player whose discard was called.
+    pub called_from: Wind,
 }
 
 /// A possible call when another player discards a tile.
@@ -1,4 +1,10 @@
-use crate::{match_state::*, messages::*};
+use crate::{
+    codec::{CodecError, Encoding},
+    match_state::*,
+    messages::*,
+    sync::{Checksum, MatchSnapshot},
+    tile::{TileId, Wind},
+};
 use cs_bindgen::prelude::*;
 use tracing::*;
 
@@ -8,9 +14,19 @@ use tracing::*;
 pub use anyhow;
 pub use strum;
 
+pub mod analysis;
+pub mod bot;
+pub mod codec;
+pub mod danger;
 pub mod hand;
 pub mod match_state;
 pub mod messages;
+pub mod mjai;
+pub mod render;
+pub mod simulation;
+pub mod spectator;
+pub mod sync;
+pub mod tenhou;
 pub mod tile;
 
 cs_bindgen::export!();
@@ -20,6 +36,33 @@ cs_bindgen::export!();
 pub struct ClientState {
     credentials: Option<Credentials>,
     state: Option<PlayerState>,
+
+    /// The reason the server rejected the most recent handshake, if any.
+    handshake_error: Option<HandshakeError>,
+
+    /// The ID to assign to the next request sent to the server.
+    next_request_id: RequestId,
+
+    /// The encoding negotiated during the handshake, used for every message after
+    /// the handshake in both directions.
+    encoding: Encoding,
+
+    /// The sequence number expected for the next match event. Events with a lower
+    /// sequence number were already received or are included in the most recent
+    /// snapshot of the match.
+    next_sequence: usize,
+
+    /// The ID of the most recent resync request, used to recognize the server's
+    /// response to it.
+    resync_request: Option<RequestId>,
+
+    /// The match state from the most recent successful start match or resync
+    /// response.
+    received_state: Option<MatchState>,
+
+    /// The reason the server rejected the most recent start match or resync request,
+    /// if it was rejected.
+    request_error: Option<ErrorResponse>,
 }
 
 #[cs_bindgen]
@@ -33,26 +76,28 @@ impl ClientState {
         self.credentials = Some(Credentials { id, token });
     }
 
-    pub fn create_handshake_request(&self) -> String {
-        let client_version =
-            Version::parse(env!("CARGO_PKG_VERSION")).expect("Failed to parse client version");
-
-        let request = HandshakeRequest {
-            client_version,
-            credentials: self.credentials.clone(),
-        };
+    /// Creates the handshake request for a new connection, offering every encoding
+    /// that the client supports.
+    ///
+    /// The handshake is always sent as JSON in a text frame, and messages are
+    /// encoded as JSON until the server responds.
+    pub fn create_handshake_request(&mut self) -> String {
+        self.encoding = Encoding::Json;
 
+        let mut request = HandshakeRequest::new(self.credentials.clone());
+        request.encodings = Encoding::SUPPORTED.to_vec();
         serde_json::to_string(&request).expect("Failed to serialize `HandshakeRequest`")
     }
 
     /// Deserializes and handles the handshake response received from the server.
     ///
-    /// Returns `true` if the handshake response was able to be processed and the server
-    /// accepted the handshake request, returns `false` if the server rejected the
-    /// request or an error otherwise occurred during the process.
-    pub fn handle_handshake_response(&mut self, json: String) -> bool {
-        match serde_json::from_str::<HandshakeResponse>(&json) {
-            Ok(message) => {
+    /// If the server rejected the handshake, the returned status indicates why, and
+    /// a message describing the error can be retrieved with `handshake_error`.
+    pub fn handle_handshake_response(&mut self, response: Vec<u8>) -> HandshakeStatus {
+        self.handshake_error = None;
+
+        match serde_json::from_slice::<HandshakeResult>(&response) {
+            Ok(Ok(message)) => {
                 if let Some(new_credentials) = message.new_credentials {
                     info!(
                         "Overwriting existing credentials, new: {:?}, prev: {:?}",
@@ -63,23 +108,197 @@ impl ClientState {
                 }
 
                 self.state = Some(message.account_data);
-                true
+                self.encoding = message.encoding;
+                HandshakeStatus::Success
+            }
+
+            Ok(Err(err)) => {
+                warn!(%err, "Server rejected handshake");
+
+                let status = match err {
+                    HandshakeError::InvalidCredentials => HandshakeStatus::InvalidCredentials,
+                    HandshakeError::UpdateRequired { .. }
+                    | HandshakeError::UnsupportedProtocol { .. } => HandshakeStatus::UpdateRequired,
+                };
+
+                self.handshake_error = Some(err);
+                status
+            }
+
+            Err(err) => {
+                warn!(%err, "Failed to parse handshake response");
+                HandshakeStatus::InvalidResponse
+            }
+        }
+    }
+
+    /// Returns a description of why the server rejected the most recent handshake, or
+    /// an empty string if the handshake wasn't rejected.
+    pub fn handshake_error(&self) -> String {
+        self.handshake_error
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default()
+    }
+
+    /// Checks if a message received from the server is a heartbeat.
+    ///
+    /// Heartbeats may arrive at any point during the session, and the client must
+    /// respond to each one with the message created by `create_heartbeat`.
+    pub fn is_heartbeat(&self, message: Vec<u8>) -> bool {
+        matches!(self.decode(&message), Ok(ServerMessage::Heartbeat))
+    }
+
+    /// Returns a description of the error if a message received from the server is
+    /// an error response, or an empty string otherwise.
+    pub fn error_message(&self, message: Vec<u8>) -> String {
+        match self.decode(&message) {
+            Ok(ServerMessage::Error(err)) => describe_error(&err),
+            _ => String::new(),
+        }
+    }
+
+    pub fn create_heartbeat(&mut self) -> Vec<u8> {
+        self.create_request(ClientRequest::Heartbeat)
+    }
+
+    pub fn create_start_match_request(&mut self) -> Vec<u8> {
+        self.create_request(ClientRequest::StartMatch)
+    }
+
+    /// Creates the request message for sending a discard action to the server.
+    pub fn create_discard_tile_request(
+        &mut self,
+        id: MatchId,
+        player: Wind,
+        tile: TileId,
+    ) -> Vec<u8> {
+        self.create_request(ClientRequest::DiscardTile(DiscardTileRequest {
+            id,
+            player,
+            tile,
+        }))
+    }
+
+    /// Creates a request for a fresh snapshot of the match, used when the local
+    /// state is out of sync with the server.
+    pub fn create_resync_request(&mut self, id: MatchId) -> Vec<u8> {
+        self.resync_request = Some(self.next_request_id);
+        self.create_request(ClientRequest::Resync(ResyncRequest { id }))
+    }
+
+    /// Handles the response to a start match request.
+    ///
+    /// If the match started, its initial state can be retrieved with
+    /// `received_match_state`. If the server rejected the request, a description of
+    /// why can be retrieved with `request_error`.
+    pub fn handle_start_match_response(&mut self, response: Vec<u8>) -> ResponseStatus {
+        match self.decode(&response) {
+            Ok(ServerMessage::Response {
+                response: Response::StartMatch(response),
+                ..
+            }) => self.receive_snapshot(&response.snapshot, 0),
+
+            message => self.reject_response(message, "StartMatchResponse"),
+        }
+    }
+
+    /// Checks if a message received from the server is the response to the most
+    /// recent resync request, including an error response if the server rejected
+    /// the request.
+    pub fn is_resync_response(&self, message: Vec<u8>) -> bool {
+        match self.decode(&message) {
+            Ok(ServerMessage::Response {
+                response: Response::Resync(_),
+                ..
+            }) => true,
+
+            Ok(ServerMessage::Error(err)) => {
+                err.request_id.is_some() && err.request_id == self.resync_request
             }
 
-            Err(_) => false,
+            _ => false,
+        }
+    }
+
+    /// Handles the response to a resync request.
+    ///
+    /// If the server sent a fresh snapshot, the recreated match state can be
+    /// retrieved with `received_match_state`. If the server rejected the request, a
+    /// description of why can be retrieved with `request_error`.
+    pub fn handle_resync_response(&mut self, response: Vec<u8>) -> ResponseStatus {
+        match self.decode(&response) {
+            Ok(ServerMessage::Response {
+                response: Response::Resync(response),
+                ..
+            }) => self.receive_snapshot(&response.snapshot, response.events_applied),
+
+            message => self.reject_response(message, "ResyncResponse"),
         }
     }
 
-    pub fn create_start_match_request(&self) -> String {
-        let request = ClientRequest::StartMatch;
-        serde_json::to_string(&request).expect("Failed to serialize request")
+    /// Returns a copy of the match state from the most recent response handled with
+    /// `ResponseStatus::Success`.
+    ///
+    /// Must only be called once a start match or resync response has been handled
+    /// successfully.
+    pub fn received_match_state(&self) -> MatchState {
+        self.received_state
+            .clone()
+            .expect("No match state has been received")
+    }
+
+    /// Returns a description of why the server rejected the most recent start match
+    /// or resync request, or an empty string if the request wasn't rejected.
+    pub fn request_error(&self) -> String {
+        self.request_error
+            .as_ref()
+            .map(describe_error)
+            .unwrap_or_default()
     }
 
-    pub fn handle_start_match_response(&self, response: String) -> MatchState {
-        let response = serde_json::from_str::<StartMatchResponse>(&response)
-            .expect("Failed to deserialize `StartMatchResponse`");
+    /// Decodes a match event received from the server and checks its sequence
+    /// number against the events received so far.
+    ///
+    /// Only an event returned as `EventStatus::Received` should be applied to the
+    /// match state, with `MatchState::handle_event`. Any other message is reported
+    /// rather than treated as an error, since the server may send other messages
+    /// while the match is in progress.
+    pub fn handle_event(&mut self, message: Vec<u8>) -> EventStatus {
+        let (sequence, event, checksum) = match self.decode(&message) {
+            Ok(ServerMessage::Event {
+                sequence,
+                event,
+                checksum,
+            }) => (sequence, event, checksum),
+
+            Ok(message) => {
+                debug!(?message, "Received a message that isn't a match event");
+                return EventStatus::Unexpected;
+            }
+
+            Err(err) => {
+                warn!(%err, "Failed to parse match event");
+                return EventStatus::Unexpected;
+            }
+        };
+
+        // NOTE: Events sent before a resync may still arrive after the resync
+        // response, but they're already included in the snapshot.
+        if sequence < self.next_sequence {
+            return EventStatus::Stale;
+        }
+
+        if sequence > self.next_sequence {
+            warn!(
+                expected = self.next_sequence,
+                sequence, "Missed match events, client must resync"
+            );
+            return EventStatus::Missed;
+        }
 
-        response.state
+        self.next_sequence += 1;
+        EventStatus::Received { event, checksum }
     }
 
     pub fn account_id(&self) -> AccountId {
@@ -90,3 +309,279 @@ impl ClientState {
         self.state.as_ref().unwrap().points
     }
 }
+
+impl ClientState {
+    /// Assigns the next request ID to `request` and encodes it with the session's
+    /// encoding.
+    ///
+    /// The server decodes both text and binary frames with the negotiated encoding,
+    /// so the client can always send requests as binary frames.
+    fn create_request(&mut self, request: ClientRequest) -> Vec<u8> {
+        let message = ClientMessage {
+            request_id: self.next_request_id,
+            request,
+        };
+        self.next_request_id = self.next_request_id.next();
+
+        self.encoding
+            .encode(&message)
+            .expect("Failed to serialize request")
+    }
+
+    /// Decodes a message received from the server with the session's encoding.
+    fn decode(&self, message: &[u8]) -> Result<ServerMessage, CodecError> {
+        self.encoding.decode(message)
+    }
+
+    /// Recreates the match state from a snapshot received in response to a request,
+    /// with `next_sequence` as the sequence number of the next event to expect.
+    fn receive_snapshot(
+        &mut self,
+        snapshot: &MatchSnapshot,
+        next_sequence: usize,
+    ) -> ResponseStatus {
+        self.request_error = None;
+
+        match MatchState::from_snapshot(snapshot) {
+            Ok(state) => {
+                self.next_sequence = next_sequence;
+                self.received_state = Some(state);
+                ResponseStatus::Success
+            }
+
+            Err(err) => {
+                warn!(%err, "Server sent an invalid match snapshot");
+                ResponseStatus::InvalidResponse
+            }
+        }
+    }
+
+    /// Handles a message that isn't the response the client was expecting, which
+    /// may be the server rejecting the request.
+    fn reject_response(
+        &mut self,
+        message: Result<ServerMessage, CodecError>,
+        expected: &str,
+    ) -> ResponseStatus {
+        self.request_error = None;
+
+        match message {
+            Ok(ServerMessage::Error(err)) => {
+                warn!(%err, "Server rejected request");
+                self.request_error = Some(err);
+                ResponseStatus::Rejected
+            }
+
+            Ok(message) => {
+                warn!(?message, "Expected `{}`", expected);
+                ResponseStatus::InvalidResponse
+            }
+
+            Err(err) => {
+                warn!(%err, "Failed to parse response");
+                ResponseStatus::InvalidResponse
+            }
+        }
+    }
+}
+
+fn describe_error(err: &ErrorResponse) -> String {
+    format!("{:?}: {}", err.kind, err.message)
+}
+
+/// The result of handling a message from the server with `ClientState::handle_event`.
+#[cs_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventStatus {
+    /// The next event in the match. `checksum` is the checksum of the server's match
+    /// state after the event, see `MatchState::is_synced`.
+    Received {
+        event: MatchEvent,
+        checksum: Checksum,
+    },
+
+    /// The event is already included in the most recent snapshot of the match, and
+    /// should be ignored.
+    Stale,
+
+    /// One or more events before this one were never received. The client should
+    /// request a fresh snapshot of the match with `create_resync_request`.
+    Missed,
+
+    /// The message isn't a match event, e.g. a late response to an earlier request,
+    /// or it couldn't be parsed.
+    Unexpected,
+}
+
+/// The outcome of handling the response to a request with
+/// `ClientState::handle_start_match_response` or `ClientState::handle_resync_response`.
+#[cs_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseStatus {
+    /// The server sent the state of the match, which can be retrieved with
+    /// `ClientState::received_match_state`.
+    Success,
+
+    /// The server rejected the request. A description of why can be retrieved with
+    /// `ClientState::request_error`.
+    Rejected,
+
+    /// The message wasn't the expected response, or couldn't be parsed.
+    InvalidResponse,
+}
+
+/// The outcome of the handshake with the server.
+#[cs_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeStatus {
+    /// The server accepted the handshake and the session has started.
+    Success,
+
+    /// The credentials stored by the client are not valid. The client should discard
+    /// them and connect again without credentials to create a new account.
+    InvalidCredentials,
+
+    /// The client is not compatible with the server, and the player must update the
+    /// client before they can play.
+    UpdateRequired,
+
+    /// The response from the server could not be parsed.
+    InvalidResponse,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile;
+
+    fn event(encoding: Encoding, sequence: usize) -> Vec<u8> {
+        let state = MatchState::new(MatchId::new(3), tile::TILE_SET.clone());
+        encoding
+            .encode(&ServerMessage::Event {
+                sequence,
+                event: MatchEvent::MatchEnded,
+                checksum: state.checksum(),
+            })
+            .unwrap()
+    }
+
+    // Test that events are only received once and in order, and that a gap in the
+    // sequence numbers is reported.
+    #[test]
+    fn event_sequence() {
+        let mut client = ClientState::new();
+
+        assert!(matches!(
+            client.handle_event(event(Encoding::Json, 0)),
+            EventStatus::Received { .. }
+        ));
+        assert_eq!(
+            EventStatus::Stale,
+            client.handle_event(event(Encoding::Json, 0))
+        );
+        assert_eq!(
+            EventStatus::Missed,
+            client.handle_event(event(Encoding::Json, 2))
+        );
+        assert!(matches!(
+            client.handle_event(event(Encoding::Json, 1)),
+            EventStatus::Received { .. }
+        ));
+
+        let heartbeat = serde_json::to_vec(&ServerMessage::Heartbeat).unwrap();
+        assert_eq!(EventStatus::Unexpected, client.handle_event(heartbeat));
+        assert_eq!(
+            EventStatus::Unexpected,
+            client.handle_event(b"not json".to_vec())
+        );
+    }
+
+    // Test that the client offers every supported encoding and switches to the one
+    // the server selects once the handshake completes.
+    #[test]
+    fn negotiated_encoding() {
+        let mut client = ClientState::new();
+
+        let request = client.create_handshake_request();
+        let request = serde_json::from_str::<HandshakeRequest>(&request).unwrap();
+        assert_eq!(Encoding::SUPPORTED, &request.encodings[..]);
+
+        let response: HandshakeResult = Ok(HandshakeResponse {
+            server_version: request.client_version,
+            new_credentials: None,
+            account_data: PlayerState { points: 0 },
+            active_match: None,
+            encoding: Encoding::MessagePack,
+        });
+        let response = serde_json::to_vec(&response).unwrap();
+        assert_eq!(
+            HandshakeStatus::Success,
+            client.handle_handshake_response(response)
+        );
+
+        let heartbeat = client.create_heartbeat();
+        assert!(Encoding::MessagePack
+            .decode::<ClientMessage>(&heartbeat)
+            .is_ok());
+        assert!(client.is_heartbeat(
+            Encoding::MessagePack
+                .encode(&ServerMessage::Heartbeat)
+                .unwrap()
+        ));
+        assert!(matches!(
+            client.handle_event(event(Encoding::MessagePack, 0)),
+            EventStatus::Received { .. }
+        ));
+    }
+
+    // Test that rejected start match and resync requests are reported to the caller
+    // instead of panicking, and that the error for a resync request is recognized as
+    // its response.
+    #[test]
+    fn rejected_requests() {
+        let mut client = ClientState::new();
+        let error = |request_id| {
+            serde_json::to_vec(&ServerMessage::Error(ErrorResponse {
+                request_id,
+                kind: ErrorKind::InvalidRequest,
+                message: "Rejected".into(),
+            }))
+            .unwrap()
+        };
+
+        client.create_start_match_request();
+        assert_eq!(
+            ResponseStatus::Rejected,
+            client.handle_start_match_response(error(Some(RequestId::new(0))))
+        );
+        assert_eq!("InvalidRequest: Rejected", client.request_error());
+        assert_eq!(
+            ResponseStatus::InvalidResponse,
+            client.handle_start_match_response(b"not json".to_vec())
+        );
+        assert_eq!("", client.request_error());
+
+        let state = MatchState::new(MatchId::new(3), tile::TILE_SET.clone());
+        let response = serde_json::to_vec(&ServerMessage::Response {
+            request_id: RequestId::new(0),
+            response: Response::StartMatch(StartMatchResponse {
+                snapshot: state.snapshot(),
+            }),
+        })
+        .unwrap();
+        assert_eq!(
+            ResponseStatus::Success,
+            client.handle_start_match_response(response)
+        );
+        assert_eq!(state, client.received_match_state());
+
+        client.create_resync_request(state.id());
+        assert!(!client.is_resync_response(error(Some(RequestId::new(0)))));
+        assert!(!client.is_resync_response(error(None)));
+        assert!(client.is_resync_response(error(Some(RequestId::new(1)))));
+        assert_eq!(
+            ResponseStatus::Rejected,
+            client.handle_resync_response(error(Some(RequestId::new(1))))
+        );
+    }
+}
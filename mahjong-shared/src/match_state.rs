@@ -1,13 +1,23 @@
 //! Functionality for actually playing a mahjong match.
 
-use crate::{hand::Hand, messages::*, tile::*};
+use crate::{hand::Hand, messages::*, sync::Checksum, tile::*};
+use anyhow::anyhow;
 use cs_bindgen::prelude::*;
 use derive_more::Display;
 use fehler::{throw, throws};
 use maplit::hashmap;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, fmt::Debug};
+use strum::EnumString;
 use thiserror::Error;
+use tracing::*;
+
+/// The stand-in dora indicator for every match.
+///
+/// Matches don't have a dead wall yet, so there's no real dora indicator. Anything
+/// that still needs one, such as MJAI engines and Tenhou logs, uses this tile instead.
+// TODO: Use the real dora indicator once the match has a dead wall.
+pub const DORA_INDICATOR: Tile = Tile::Wind(Wind::North);
 
 #[cs_bindgen]
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -78,6 +88,50 @@ impl MatchState {
         // Update to the next player's turn, cycling through the seats in wind order.
         self.current_turn = self.current_turn.next();
     }
+
+    /// Applies an event received from the server to the local state.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event can't be applied to the local state, which
+    /// indicates that the local state is out of sync with the server.
+    #[throws(anyhow::Error)]
+    pub fn apply_event(&mut self, event: &MatchEvent) {
+        match *event {
+            MatchEvent::TileDiscarded { seat, tile } => {
+                if self.current_turn != seat {
+                    throw!(anyhow!(
+                        "Discard event for {:?} does not match current turn {:?}",
+                        seat,
+                        self.current_turn,
+                    ));
+                }
+
+                self.discard_tile(seat, tile)?;
+            }
+
+            MatchEvent::TileDrawn { seat, tile } => {
+                if self.current_turn != seat {
+                    throw!(anyhow!(
+                        "Draw event for {:?} does not match current turn {:?}",
+                        seat,
+                        self.current_turn,
+                    ));
+                }
+
+                let draw = self.draw_for_player(seat)?;
+                if draw != tile {
+                    throw!(anyhow!(
+                        "Local draw {:?} does not match draw event {:?}",
+                        draw,
+                        tile,
+                    ));
+                }
+            }
+
+            MatchEvent::MatchEnded => {}
+        }
+    }
 }
 
 #[cs_bindgen]
@@ -101,6 +155,10 @@ impl MatchState {
     // TODO: Combine `player_has_current_draw` and `get_current_draw` into a single
     // function that returns an `Option<Tile>` once cs-bindgen supports `Option`.
 
+    pub fn player_discards(&self, seat: Wind) -> Vec<TileInstance> {
+        self.players.get(&seat).unwrap().discards().into()
+    }
+
     pub fn player_has_current_draw(&self, seat: Wind) -> bool {
         self.players.get(&seat).unwrap().current_draw().is_some()
     }
@@ -124,46 +182,45 @@ impl MatchState {
         self.draw_for_player(seat).is_ok()
     }
 
-    /// Creates the request message for sending the discard action to the server.
-    pub fn request_discard_tile(&mut self, player: Wind, tile: TileId) -> String {
-        let request = ClientRequest::DiscardTile(DiscardTileRequest {
-            id: self.id,
-            player,
-            tile,
-        });
-        serde_json::to_string(&request).unwrap()
-    }
-
-    pub fn handle_event(&mut self, json: String) -> MatchEvent {
-        let event = serde_json::from_str(&json).unwrap();
-
-        // Apply the event to the local state.
-        match &event {
-            &MatchEvent::TileDiscarded { seat, tile } => {
-                assert_eq!(
-                    self.current_turn, seat,
-                    "Draw event does not match current turn"
-                );
-
-                self.discard_tile(seat, tile)
-                    .expect("Failed to discard locally");
+    /// Applies an event received with `ClientState::handle_event` to the state,
+    /// returning `false` if the event couldn't be applied.
+    ///
+    /// Use `is_synced` afterwards to check that the state still matches the server's
+    /// state. If the event couldn't be applied, the state will be out of sync.
+    // TODO: Remove this function once we can export `apply_event` directly.
+    pub fn handle_event(&mut self, event: MatchEvent) -> bool {
+        match self.apply_event(&event) {
+            Ok(()) => true,
+            Err(err) => {
+                warn!(%err, ?event, "Failed to apply event locally");
+                false
             }
+        }
+    }
 
-            &MatchEvent::TileDrawn { seat, tile } => {
-                assert_eq!(
-                    self.current_turn, seat,
-                    "Draw event does not match current turn"
-                );
-
-                let draw = self.draw_for_player(seat).expect("Unable to draw locally");
-                assert_eq!(draw, tile, "Local draw does not match draw event");
-            }
+    /// Checks that the state matches the server's state, using the checksum that the
+    /// server sends with each event.
+    ///
+    /// If this returns `false`, the client should request a fresh snapshot of the
+    /// match with `ClientState::create_resync_request`.
+    pub fn is_synced(&self, checksum: Checksum) -> bool {
+        self.checksum() == checksum
+    }
+}
 
-            MatchEvent::MatchEnded => {}
-        }
+/// The set of rules used for a match.
+// TODO: Support rule variations (e.g. red fives or open tanyao) once the match logic
+// is complete enough for them to make a difference.
+#[cs_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumString)]
+pub enum Ruleset {
+    /// Standard Japanese Riichi Mahjong.
+    Riichi,
+}
 
-        // Forward the event to the host environment
-        event
+impl Default for Ruleset {
+    fn default() -> Self {
+        Ruleset::Riichi
     }
 }
 
@@ -4,15 +4,29 @@
 // framework once we move the communication layer into Rust.
 
 use crate::{
-    match_state::{MatchId, MatchState},
+    match_state::{MatchId, Ruleset},
+    spectator::{SpectatorEvent, SpectatorSnapshot, SpectatorView},
+    sync::{Checksum, MatchSnapshot},
     tile::{TileId, Wind},
 };
 use cs_bindgen::prelude::*;
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
 
+pub use crate::codec::Encoding;
 pub use semver::Version;
 
+/// The version of the wire protocol used to communicate between the client and the
+/// server.
+///
+/// The protocol version is tracked separately from the crate version, since most
+/// releases don't change the protocol. Increment this whenever a change is made to
+/// the message definitions that would prevent an older client from communicating
+/// with the server.
+pub const PROTOCOL_VERSION: u32 = 7;
+
 /// Initial handshake request sent by the client after establishing a connection to
 /// the server.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -24,8 +38,87 @@ pub struct HandshakeRequest {
     /// playing.
     pub client_version: Version,
 
+    /// The version of the protocol that the client uses.
+    ///
+    /// Clients from before the protocol was versioned don't send a protocol version,
+    /// and are treated as using protocol version 0.
+    #[serde(default)]
+    pub protocol_version: u32,
+
     /// The ID and token for the account that the client is attempting to log into.
     pub credentials: Option<Credentials>,
+
+    /// The encodings the client supports for messages after the handshake, in order
+    /// of preference.
+    ///
+    /// Every client supports JSON, so it doesn't need to be listed.
+    #[serde(default)]
+    pub encodings: Vec<Encoding>,
+}
+
+impl HandshakeRequest {
+    /// Creates a handshake request for the current version of the client.
+    pub fn new(credentials: Option<Credentials>) -> Self {
+        let client_version =
+            Version::parse(env!("CARGO_PKG_VERSION")).expect("Failed to parse client version");
+
+        Self {
+            client_version,
+            protocol_version: PROTOCOL_VERSION,
+            credentials,
+            encodings: Vec::new(),
+        }
+    }
+
+    /// Checks that the client making the request is able to connect to a server
+    /// running `server_version`.
+    ///
+    /// The client must be using the same protocol version as the server, must be at
+    /// least `min_client_version`, and must be semver-compatible with the server,
+    /// i.e. have the same major version (or the same minor version for pre-1.0
+    /// versions).
+    pub fn check_version(
+        &self,
+        server_version: &Version,
+        min_client_version: &Version,
+    ) -> Result<(), HandshakeError> {
+        if self.protocol_version > PROTOCOL_VERSION {
+            return Err(HandshakeError::UnsupportedProtocol {
+                client_protocol: self.protocol_version,
+                server_protocol: PROTOCOL_VERSION,
+            });
+        }
+
+        // The oldest version the client can update to and still be able to connect.
+        let required = std::cmp::max(
+            min_client_version.clone(),
+            oldest_compatible(server_version),
+        );
+
+        let compatible = if server_version.major == 0 {
+            self.client_version.major == 0 && self.client_version.minor == server_version.minor
+        } else {
+            self.client_version.major == server_version.major
+        };
+
+        if self.protocol_version < PROTOCOL_VERSION || !compatible || self.client_version < required
+        {
+            return Err(HandshakeError::UpdateRequired {
+                min_client_version: required,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the oldest version that is semver-compatible with `version`.
+fn oldest_compatible(version: &Version) -> Version {
+    if version.major == 0 {
+        Version::new(0, version.minor, 0)
+    } else {
+        Version::new(version.major, 0, 0)
+    }
 }
 
 /// Response to a client's handshake request.
@@ -33,8 +126,52 @@ pub struct HandshakeRequest {
 pub struct HandshakeResponse {
     pub server_version: Version,
 
+    /// New credentials for the account.
+    ///
+    /// The server issues a new token each time the client logs in, invalidating the
+    /// previous token. The client must store the new credentials in order to log
+    /// back in later.
     pub new_credentials: Option<Credentials>,
     pub account_data: PlayerState,
+
+    /// The match the account is still playing in, if any.
+    ///
+    /// If the client disconnected in the middle of a match, the client can rejoin
+    /// the match by sending a `ResumeMatch` request.
+    #[serde(default)]
+    pub active_match: Option<MatchId>,
+
+    /// The encoding the server selected for all messages after the handshake.
+    #[serde(default)]
+    pub encoding: Encoding,
+}
+
+/// The full message sent by the server in response to a handshake request.
+///
+/// If the server rejects the handshake, it closes the connection after sending the
+/// error.
+pub type HandshakeResult = Result<HandshakeResponse, HandshakeError>;
+
+/// Reason the server rejected a client's handshake request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Error)]
+pub enum HandshakeError {
+    #[error("The account ID or token provided by the client is not valid")]
+    InvalidCredentials,
+
+    /// The client is too old to connect to the server, and the player must update
+    /// their client before playing.
+    #[error("Client version is not supported, update to at least version {min_client_version}")]
+    UpdateRequired { min_client_version: Version },
+
+    /// The client uses a newer protocol than the server supports.
+    #[error(
+        "Client uses protocol version {client_protocol}, but the server only supports \
+        protocol version {server_protocol}"
+    )]
+    UnsupportedProtocol {
+        client_protocol: u32,
+        server_protocol: u32,
+    },
 }
 
 /// Unique ID for a game account.
@@ -54,6 +191,10 @@ impl AccountId {
     pub fn new(id: u64) -> Self {
         Self(id)
     }
+
+    pub fn raw(self) -> u64 {
+        self.0
+    }
 }
 
 /// Unique ID for a client session.
@@ -79,20 +220,236 @@ pub struct PlayerState {
 pub struct Credentials {
     pub id: AccountId,
 
-    // TODO: Use a more structured type for the account token. For now we'll just use a
-    // psuedo-random string until we have some actual authentication system in place.
+    // TODO: Use a more structured type for the account token. For now the token is
+    // the hex-encoded string generated by the server.
     pub token: String,
 }
 
+/// Identifies a request sent by the client.
+///
+/// The client assigns a new ID to each request it sends, and the server includes
+/// the ID in any error it sends in response to the request.
+#[derive(Debug, Display, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+#[display("{}", _0)]
+pub struct RequestId(u32);
+
+impl RequestId {
+    pub fn new(id: u32) -> Self {
+        Self(id)
+    }
+
+    pub fn raw(self) -> u32 {
+        self.0
+    }
+
+    /// Returns the ID following this one, wrapping around once all IDs are used.
+    pub fn next(self) -> Self {
+        Self(self.0.wrapping_add(1))
+    }
+}
+
+/// A request sent by the client, along with the ID the client assigned to it.
+///
+/// Every message the client sends after the handshake is a `ClientMessage`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientMessage {
+    pub request_id: RequestId,
+    pub request: ClientRequest,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ClientRequest {
     StartMatch,
     DiscardTile(DiscardTileRequest),
+    ResumeMatch(ResumeMatchRequest),
+    Resync(ResyncRequest),
+
+    /// Joins the matchmaking queue to be matched with other players. The server sends
+    /// `ServerMessage::MatchFound` once the client has been placed in a match.
+    JoinQueue,
+    LeaveQueue,
+
+    /// Creates a private room and seats the client in it as the room's host.
+    CreateRoom(CreateRoomRequest),
+    JoinRoom(JoinRoomRequest),
+    LeaveRoom,
+
+    /// Starts the match for the room the client is hosting. Each player in the room
+    /// receives `ServerMessage::MatchFound`.
+    StartRoom(StartRoomRequest),
+
+    /// Starts watching a match in progress. The server sends the match's events to
+    /// the client as `ServerMessage::SpectatorEvent` until the match ends or the
+    /// client sends `StopSpectating`.
+    Spectate(SpectateRequest),
+    StopSpectating,
+
+    /// Response to a heartbeat sent by the server.
+    Heartbeat,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StartMatchResponse {
-    pub state: MatchState,
+    /// The initial state of the match.
+    pub snapshot: MatchSnapshot,
+}
+
+/// Request to rejoin a match that the client was disconnected from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumeMatchRequest {
+    pub id: MatchId,
+
+    /// The number of match events the client received before it was disconnected,
+    /// i.e. one more than the `sequence` of the last event it received.
+    ///
+    /// Any events after these are included in the response so that the client can
+    /// catch up. Clients that didn't keep their local state can send 0 and use the
+    /// snapshot instead.
+    pub events_received: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumeMatchResponse {
+    /// The current state of the match.
+    pub snapshot: MatchSnapshot,
+
+    /// The seat that the client controls.
+    pub seat: Wind,
+
+    /// The events that occurred since the client was disconnected, in order.
+    ///
+    /// Applying these to the client's last known state produces `snapshot`.
+    pub missed_events: Vec<MatchEvent>,
+}
+
+/// Request for a fresh snapshot of a match, sent when the client detects that its
+/// local state is out of sync with the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResyncRequest {
+    pub id: MatchId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResyncResponse {
+    /// The current state of the match.
+    pub snapshot: MatchSnapshot,
+
+    /// The number of events that have occurred in the match, i.e. the `sequence` of
+    /// the next event the client will receive.
+    ///
+    /// Events with a lower sequence number may still arrive after the response, and
+    /// should be ignored since they're already reflected in `snapshot`.
+    pub events_applied: usize,
+}
+
+/// The client's place in the matchmaking queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueStatus {
+    /// The client's position in the queue, starting from 1 for the player who has
+    /// been waiting the longest.
+    ///
+    /// Players aren't always matched in queue order, e.g. if the server pairs
+    /// players by rating.
+    pub position: usize,
+
+    /// The number of players in the queue, including the client.
+    pub players_queued: usize,
+}
+
+/// Sent when the client has been placed in a match, either by matchmaking or
+/// because the host of the client's room started the match.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchFound {
+    /// The initial state of the match.
+    pub snapshot: MatchSnapshot,
+
+    /// The seat that the client controls. Matchmaking assigns seats randomly.
+    pub seat: Wind,
+}
+
+/// Short code that the host of a private room shares to invite other players.
+///
+/// Codes are case-insensitive. Use `RoomCode::new` to normalize codes entered by
+/// the player.
+#[derive(Debug, Display, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+#[display("{}", _0)]
+pub struct RoomCode(String);
+
+impl RoomCode {
+    pub fn new(code: &str) -> Self {
+        Self(code.trim().to_ascii_uppercase())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateRoomRequest {
+    /// The name of the room, displayed to players in the room's lobby.
+    pub name: String,
+
+    /// The ruleset for the room's match, or `None` to use the server's default.
+    pub ruleset: Option<Ruleset>,
+
+    /// The seat the host takes in the room's match.
+    pub seat: Wind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JoinRoomRequest {
+    pub code: RoomCode,
+    pub seat: Wind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StartRoomRequest {
+    /// Whether empty seats are given to computer-controlled players. If `false`, the
+    /// match can only start once every seat is taken.
+    pub fill_with_bots: bool,
+}
+
+/// The current state of a private room's lobby.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub code: RoomCode,
+    pub name: String,
+    pub ruleset: Ruleset,
+    pub host: AccountId,
+
+    /// The players in the room, in wind order starting from East. Seats that no one
+    /// has taken aren't included.
+    pub seats: Vec<RoomSeat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomSeat {
+    pub seat: Wind,
+    pub account: AccountId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpectateRequest {
+    pub id: MatchId,
+    pub view: SpectatorView,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpectateResponse {
+    /// The state of the match before its first event.
+    ///
+    /// The server then sends every event in the match, starting from the first, so
+    /// that the client can catch up to the match in progress. With
+    /// `SpectatorView::Revealed`, each event is only sent once the spectator delay
+    /// has passed since it happened.
+    ///
+    /// The snapshot always hides the players' hands. With `SpectatorView::Revealed`,
+    /// the starting hands are revealed by `SpectatorEvent::HandsRevealed` once the
+    /// spectator delay has passed since the match started.
+    pub snapshot: SpectatorSnapshot,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -103,7 +460,7 @@ pub struct DiscardTileRequest {
 }
 
 #[cs_bindgen]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MatchEvent {
     TileDrawn { seat: Wind, tile: TileId },
     TileDiscarded { seat: Wind, tile: TileId },
@@ -112,3 +469,391 @@ pub enum MatchEvent {
     // there may not be a winner.
     MatchEnded,
 }
+
+/// Envelope for every message sent by the server after the handshake.
+///
+/// The `type` field identifies the kind of message, so that the client can tell a
+/// response to one of its requests apart from events that the server sends on its
+/// own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    /// Sent when the connection is first established and then periodically for the
+    /// rest of the session. The client must respond with `ClientRequest::Heartbeat`,
+    /// otherwise the server will disconnect it once the idle timeout expires.
+    Heartbeat,
+
+    /// The response to the client's request with the ID `request_id`.
+    Response {
+        request_id: RequestId,
+        response: Response,
+    },
+
+    /// An event in the match that the client is playing in.
+    ///
+    /// `sequence` is the index of the event among all events in the match, starting
+    /// from 0. Clients can use it to detect missed events, and to tell the server
+    /// which events they have received when resuming a match.
+    ///
+    /// `checksum` is the checksum of the server's match state after applying the
+    /// event. If the client's state has a different checksum after applying the
+    /// event, the client is out of sync and should send a `Resync` request.
+    Event {
+        sequence: usize,
+        event: MatchEvent,
+        checksum: Checksum,
+    },
+
+    /// Sent while the client is in the matchmaking queue whenever its place in the
+    /// queue changes.
+    QueueStatus(QueueStatus),
+
+    /// Sent when the client has been placed in a match by matchmaking or by the host
+    /// of its room. The client has already joined the match, and will receive its
+    /// events from then on.
+    MatchFound(MatchFound),
+
+    /// Sent to the players in a room whenever another player joins or leaves.
+    RoomUpdated(RoomInfo),
+
+    /// Sent to the players in a room when the host leaves before starting the match.
+    RoomClosed,
+
+    /// An event in the match that the client is spectating.
+    ///
+    /// `sequence` is the index of the event among all events in the match, the same
+    /// as for `Event`. `SpectatorEvent::HandsRevealed` isn't a match event, and has
+    /// the same sequence number as the event that follows it.
+    SpectatorEvent {
+        sequence: usize,
+        event: SpectatorEvent,
+    },
+
+    /// Sent when the server fails to handle a request from the client.
+    Error(ErrorResponse),
+}
+
+impl ServerMessage {
+    /// Returns `true` if `json` is a serialized `ServerMessage::Heartbeat`.
+    pub fn is_heartbeat(json: &str) -> bool {
+        matches!(
+            serde_json::from_str::<ServerMessage>(json),
+            Ok(ServerMessage::Heartbeat)
+        )
+    }
+}
+
+/// The body of a successful response to a client request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Response {
+    StartMatch(StartMatchResponse),
+    ResumeMatch(ResumeMatchResponse),
+    Resync(ResyncResponse),
+
+    /// The client's initial place in the matchmaking queue.
+    JoinQueue(QueueStatus),
+    LeaveQueue,
+
+    CreateRoom(RoomInfo),
+    JoinRoom(RoomInfo),
+    LeaveRoom,
+    StartRoom,
+
+    Spectate(SpectateResponse),
+    StopSpectating,
+}
+
+/// Describes why the server failed to handle a request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Error)]
+#[error("{message}")]
+pub struct ErrorResponse {
+    /// The ID of the request that failed, or `None` if the server couldn't determine
+    /// which request the message was, e.g. because it wasn't valid JSON.
+    pub request_id: Option<RequestId>,
+
+    pub kind: ErrorKind,
+
+    /// Human-readable description of the error, suitable for logging or displaying
+    /// to the player.
+    pub message: String,
+}
+
+/// The category of error that caused a request to fail.
+///
+/// Clients should use the kind to determine how to recover from the error, e.g. an
+/// `IncorrectTurn` error means the client's match state is out of sync with the
+/// server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ErrorKind {
+    /// The message couldn't be parsed as a request.
+    InvalidMessage,
+
+    /// The request isn't valid in the session's current state, e.g. discarding a
+    /// tile when the client isn't in a match.
+    InvalidRequest,
+
+    /// The player attempted to act when it wasn't their turn.
+    IncorrectTurn,
+
+    /// The tile being discarded isn't in the player's hand.
+    TileNotInHand,
+
+    /// The player attempted to discard without having drawn a tile.
+    NoDraw,
+
+    /// There are no tiles left in the wall.
+    InsufficientTiles,
+
+    /// The server encountered an unexpected error. The request may be retried.
+    Internal,
+}
+
+/// Reason the server closed the connection with a client.
+///
+/// The reason is sent to the client as the status code of the websocket close
+/// frame, using codes in the range reserved for applications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DisconnectReason {
+    /// The client did not send its handshake request in time.
+    HandshakeTimeout,
+
+    /// The client did not send any messages within the idle timeout.
+    IdleTimeout,
+
+    /// The server rejected the client's handshake request.
+    HandshakeRejected,
+
+    /// Another client logged into the same account.
+    LoggedInElsewhere,
+}
+
+impl DisconnectReason {
+    pub fn close_code(self) -> u16 {
+        match self {
+            DisconnectReason::HandshakeTimeout => 4000,
+            DisconnectReason::IdleTimeout => 4001,
+            DisconnectReason::HandshakeRejected => 4002,
+            DisconnectReason::LoggedInElsewhere => 4003,
+        }
+    }
+
+    pub fn from_close_code(code: u16) -> Option<Self> {
+        match code {
+            4000 => Some(DisconnectReason::HandshakeTimeout),
+            4001 => Some(DisconnectReason::IdleTimeout),
+            4002 => Some(DisconnectReason::HandshakeRejected),
+            4003 => Some(DisconnectReason::LoggedInElsewhere),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            DisconnectReason::HandshakeTimeout => "Timed out waiting for handshake",
+            DisconnectReason::IdleTimeout => "Timed out waiting for client activity",
+            DisconnectReason::HandshakeRejected => "Handshake rejected",
+            DisconnectReason::LoggedInElsewhere => "Logged in from another client",
+        };
+
+        f.write_str(description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(client_version: &str) -> HandshakeRequest {
+        HandshakeRequest {
+            client_version: Version::parse(client_version).unwrap(),
+            protocol_version: PROTOCOL_VERSION,
+            credentials: None,
+            encodings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn compatible_versions() {
+        let server = Version::parse("0.2.3").unwrap();
+        let min = Version::parse("0.2.1").unwrap();
+
+        assert_eq!(Ok(()), request("0.2.1").check_version(&server, &min));
+        assert_eq!(Ok(()), request("0.2.5").check_version(&server, &min));
+
+        let server = Version::parse("1.4.0").unwrap();
+        let min = Version::parse("1.0.0").unwrap();
+        assert_eq!(Ok(()), request("1.0.0").check_version(&server, &min));
+        assert_eq!(Ok(()), request("1.7.2").check_version(&server, &min));
+    }
+
+    #[test]
+    fn incompatible_versions() {
+        let server = Version::parse("0.2.3").unwrap();
+        let min = Version::parse("0.2.1").unwrap();
+
+        // Below the minimum supported version.
+        assert!(request("0.2.0").check_version(&server, &min).is_err());
+
+        // Pre-1.0 minor versions are not compatible with each other.
+        assert!(request("0.1.9").check_version(&server, &min).is_err());
+        assert!(request("0.3.0").check_version(&server, &min).is_err());
+
+        let server = Version::parse("1.4.0").unwrap();
+        let min = Version::parse("1.0.0").unwrap();
+        assert!(request("2.0.0").check_version(&server, &min).is_err());
+    }
+
+    // The client is told to update to a version that can actually connect, even if
+    // the configured minimum version is older than that.
+    #[test]
+    fn reported_min_version() {
+        let server = Version::parse("0.2.3").unwrap();
+
+        assert_eq!(
+            Err(HandshakeError::UpdateRequired {
+                min_client_version: Version::new(0, 2, 1),
+            }),
+            request("0.1.9").check_version(&server, &Version::new(0, 2, 1)),
+        );
+
+        assert_eq!(
+            Err(HandshakeError::UpdateRequired {
+                min_client_version: Version::new(0, 2, 0),
+            }),
+            request("0.1.9").check_version(&server, &Version::new(0, 1, 0)),
+        );
+    }
+
+    #[test]
+    fn protocol_mismatch() {
+        let server = Version::parse("0.2.3").unwrap();
+        let min = Version::parse("0.2.0").unwrap();
+
+        let mut old = request("0.2.3");
+        old.protocol_version = PROTOCOL_VERSION - 1;
+        assert!(matches!(
+            old.check_version(&server, &min),
+            Err(HandshakeError::UpdateRequired { .. })
+        ));
+
+        let mut new = request("0.2.3");
+        new.protocol_version = PROTOCOL_VERSION + 1;
+        assert!(matches!(
+            new.check_version(&server, &min),
+            Err(HandshakeError::UnsupportedProtocol { .. })
+        ));
+    }
+
+    // Clients from before the protocol version was added don't include it in the
+    // handshake, and must be told to update rather than failing to parse.
+    #[test]
+    fn missing_protocol_version() {
+        let json = r#"{"client_version":"0.1.0","credentials":null}"#;
+        let request = serde_json::from_str::<HandshakeRequest>(json).unwrap();
+        assert_eq!(0, request.protocol_version);
+    }
+
+    #[test]
+    fn close_codes_round_trip() {
+        for &reason in &[
+            DisconnectReason::HandshakeTimeout,
+            DisconnectReason::IdleTimeout,
+            DisconnectReason::HandshakeRejected,
+            DisconnectReason::LoggedInElsewhere,
+        ] {
+            assert_eq!(
+                Some(reason),
+                DisconnectReason::from_close_code(reason.close_code())
+            );
+        }
+
+        assert_eq!(None, DisconnectReason::from_close_code(1000));
+    }
+
+    #[test]
+    fn heartbeat_is_distinct_from_events() {
+        let heartbeat = serde_json::to_string(&ServerMessage::Heartbeat).unwrap();
+        assert!(ServerMessage::is_heartbeat(&heartbeat));
+
+        let event = serde_json::to_string(&ServerMessage::Event {
+            sequence: 0,
+            event: MatchEvent::MatchEnded,
+            checksum: Checksum::new(0),
+        })
+        .unwrap();
+        assert!(!ServerMessage::is_heartbeat(&event));
+
+        let error = serde_json::to_string(&ServerMessage::Error(ErrorResponse {
+            request_id: Some(RequestId::new(0)),
+            kind: ErrorKind::Internal,
+            message: String::from("Internal server error"),
+        }))
+        .unwrap();
+        assert!(!ServerMessage::is_heartbeat(&error));
+    }
+
+    #[test]
+    fn server_messages_round_trip() {
+        let messages = vec![
+            ServerMessage::Heartbeat,
+            ServerMessage::Event {
+                sequence: 3,
+                event: MatchEvent::TileDiscarded {
+                    seat: Wind::South,
+                    tile: crate::tile::TILE_SET[12].id,
+                },
+                checksum: Checksum::new(0x0123_4567_89ab_cdef),
+            },
+            ServerMessage::Error(ErrorResponse {
+                request_id: None,
+                kind: ErrorKind::InvalidMessage,
+                message: String::from("Invalid JSON"),
+            }),
+            ServerMessage::QueueStatus(QueueStatus {
+                position: 2,
+                players_queued: 3,
+            }),
+            ServerMessage::Response {
+                request_id: RequestId::new(7),
+                response: Response::LeaveQueue,
+            },
+            ServerMessage::RoomUpdated(RoomInfo {
+                code: RoomCode::new("K7QX2M"),
+                name: String::from("Friday night"),
+                ruleset: Ruleset::Riichi,
+                host: AccountId::new(4),
+                seats: vec![RoomSeat {
+                    seat: Wind::West,
+                    account: AccountId::new(4),
+                }],
+            }),
+            ServerMessage::RoomClosed,
+            ServerMessage::SpectatorEvent {
+                sequence: 5,
+                event: SpectatorEvent::TileDrawn {
+                    seat: Wind::North,
+                    tile: None,
+                },
+            },
+        ];
+
+        for message in messages {
+            let json = serde_json::to_string(&message).unwrap();
+            assert_eq!(message, serde_json::from_str(&json).unwrap(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn room_codes_normalized() {
+        assert_eq!(RoomCode::new("K7QX2M"), RoomCode::new(" k7qx2m\n"));
+        assert_eq!("K7QX2M", RoomCode::new("k7Qx2m").as_str());
+    }
+
+    #[test]
+    fn request_id_wraps() {
+        assert_eq!(RequestId::new(1), RequestId::new(0).next());
+        assert_eq!(RequestId::new(0), RequestId::new(u32::MAX).next());
+    }
+}
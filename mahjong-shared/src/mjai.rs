@@ -0,0 +1,544 @@
+//! Translation to and from MJAI, the protocol spoken by most open-source mahjong AIs.
+//!
+//! MJAI messages are JSON objects tagged with a `type` field, sent one per line. The
+//! game sends every event in the match to each AI from the perspective of its seat,
+//! and the AI responds to every event, either with an action or with `none`. Players
+//! are identified by their actor index, which counts from the dealer at the start of
+//! the game. Matches currently consist of a single hand, so actor 0 is always East.
+//!
+//! Tiles are written as `1m`-`9m`, `1p`-`9p` and `1s`-`9s` for simples, `E`, `S`,
+//! `W` and `N` for winds, `P`, `F` and `C` for the white, green and red dragons, and
+//! `?` for tiles that the player can't see. Red fives are written as e.g. `5mr`, and
+//! are read as regular fives since we don't support red fives yet.
+//!
+//! The match logic doesn't support calls, riichi or wins yet, so only draws, discards
+//! and the end of the match come from `MatchEvent`s. The other messages are included
+//! so that AIs can be told about them once they're supported, and so that tools
+//! tracking riichi themselves can send them.
+
+use crate::{
+    hand::{Call, Hand},
+    match_state::{MatchId, DORA_INDICATOR},
+    messages::{ClientRequest, DiscardTileRequest},
+    render,
+    spectator::SpectatorEvent,
+    tile::{Dragon, SimpleTile, Suit, Tile, TileId, TileInstance, Wind},
+};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, convert::TryFrom, fmt};
+use strum::IntoEnumIterator;
+
+/// A message in the MJAI protocol.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    StartGame {
+        /// The actor index of the player receiving the message.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<u8>,
+
+        #[serde(default)]
+        names: Vec<String>,
+    },
+
+    StartKyoku {
+        bakaze: Pai,
+        kyoku: u8,
+        honba: u8,
+        kyotaku: u8,
+        oya: u8,
+        dora_marker: Pai,
+
+        /// The starting hand of each player, with tiles the player receiving the
+        /// message can't see written as `?`.
+        tehais: Vec<Vec<Pai>>,
+    },
+
+    Tsumo {
+        actor: u8,
+        pai: Pai,
+    },
+
+    Dahai {
+        actor: u8,
+        pai: Pai,
+
+        /// Whether the discarded tile is the one the player just drew.
+        tsumogiri: bool,
+    },
+
+    Chi {
+        actor: u8,
+        target: u8,
+        pai: Pai,
+        consumed: Vec<Pai>,
+    },
+
+    Pon {
+        actor: u8,
+        target: u8,
+        pai: Pai,
+        consumed: Vec<Pai>,
+    },
+
+    Daiminkan {
+        actor: u8,
+        target: u8,
+        pai: Pai,
+        consumed: Vec<Pai>,
+    },
+
+    Kakan {
+        actor: u8,
+        pai: Pai,
+        consumed: Vec<Pai>,
+    },
+
+    Ankan {
+        actor: u8,
+        consumed: Vec<Pai>,
+    },
+
+    Dora {
+        dora_marker: Pai,
+    },
+
+    Reach {
+        actor: u8,
+    },
+
+    ReachAccepted {
+        actor: u8,
+    },
+
+    Hora {
+        actor: u8,
+        target: u8,
+
+        /// The winning tile. Some AIs leave this out of their own win declarations.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pai: Option<Pai>,
+    },
+
+    Ryukyoku {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+
+    EndKyoku,
+    EndGame,
+
+    /// Response from an AI that doesn't want to act on the last event.
+    None,
+
+    Error {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+}
+
+/// A tile as written in MJAI messages, or `None` if the tile is hidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pai(pub Option<Tile>);
+
+impl Pai {
+    pub const UNKNOWN: Pai = Pai(None);
+}
+
+impl From<Tile> for Pai {
+    fn from(tile: Tile) -> Self {
+        Pai(Some(tile))
+    }
+}
+
+impl fmt::Display for Pai {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tile = match self.0 {
+            Some(tile) => tile,
+            None => return f.write_str("?"),
+        };
+
+        match tile {
+            Tile::Simple(..) => f.write_str(&render::ascii(tile)),
+
+            Tile::Wind(wind) => f.write_str(match wind {
+                Wind::East => "E",
+                Wind::South => "S",
+                Wind::West => "W",
+                Wind::North => "N",
+            }),
+
+            Tile::Dragon(dragon) => f.write_str(match dragon {
+                Dragon::White => "P",
+                Dragon::Green => "F",
+                Dragon::Red => "C",
+            }),
+        }
+    }
+}
+
+impl From<Pai> for String {
+    fn from(pai: Pai) -> Self {
+        pai.to_string()
+    }
+}
+
+impl TryFrom<String> for Pai {
+    type Error = anyhow::Error;
+
+    fn try_from(text: String) -> Result<Self> {
+        let tile = match text.as_str() {
+            "?" => return Ok(Pai::UNKNOWN),
+            "E" => Wind::East.into(),
+            "S" => Wind::South.into(),
+            "W" => Wind::West.into(),
+            "N" => Wind::North.into(),
+            "P" => Dragon::White.into(),
+            "F" => Dragon::Green.into(),
+            "C" => Dragon::Red.into(),
+
+            _ => {
+                let simple = text.strip_suffix('r').unwrap_or(&text);
+                let mut chars = simple.chars();
+                let number = chars.next().and_then(|c| c.to_digit(10));
+                let suit = match chars.next() {
+                    Some('m') => Suit::Characters,
+                    Some('p') => Suit::Coins,
+                    Some('s') => Suit::Bamboo,
+                    _ => bail!("Invalid MJAI tile {:?}", text),
+                };
+
+                match number {
+                    Some(number @ 1..=9) if chars.next().is_none() => {
+                        SimpleTile::new(suit, number as u8).into()
+                    }
+                    _ => bail!("Invalid MJAI tile {:?}", text),
+                }
+            }
+        };
+
+        Ok(Pai(Some(tile)))
+    }
+}
+
+/// Returns the actor index used for the player in `seat`.
+pub fn actor(seat: Wind) -> u8 {
+    seat as u8
+}
+
+/// Returns the seat of the player with the actor index `actor`.
+pub fn seat(actor: u8) -> Result<Wind> {
+    Wind::iter()
+        .nth(actor as usize)
+        .ok_or_else(|| anyhow!("Invalid MJAI actor {}", actor))
+}
+
+/// Translates a match into MJAI messages from the perspective of one seat.
+///
+/// The translator keeps track of each player's last draw, so that it can tell
+/// whether their discards were tsumogiri.
+#[derive(Debug, Clone)]
+pub struct Translator {
+    seat: Wind,
+    draws: HashMap<Wind, Option<TileId>>,
+}
+
+impl Translator {
+    pub fn new(seat: Wind) -> Self {
+        Self {
+            seat,
+            draws: HashMap::new(),
+        }
+    }
+
+    pub fn seat(&self) -> Wind {
+        self.seat
+    }
+
+    /// Returns the messages that start the game, given the player's starting hand.
+    pub fn start(&self, hand: &[TileInstance]) -> Vec<Message> {
+        let tehais = Wind::iter()
+            .map(|seat| {
+                if seat == self.seat {
+                    hand.iter().map(|instance| instance.tile.into()).collect()
+                } else {
+                    vec![Pai::UNKNOWN; 13]
+                }
+            })
+            .collect();
+
+        vec![
+            Message::StartGame {
+                id: Some(actor(self.seat)),
+                names: Wind::iter().map(|seat| format!("{:?}", seat)).collect(),
+            },
+            Message::StartKyoku {
+                bakaze: Tile::from(Wind::East).into(),
+                kyoku: 1,
+                honba: 0,
+                kyotaku: 0,
+                oya: actor(Wind::East),
+                dora_marker: DORA_INDICATOR.into(),
+                tehais,
+            },
+        ]
+    }
+
+    /// Translates `event` into the messages sent to the player.
+    ///
+    /// Tiles drawn by other players are hidden, even if they're visible in `event`.
+    /// Other players' discards are only marked as tsumogiri if their draws are
+    /// visible, since otherwise there's no way to tell.
+    /// Use `SpectatorEvent::new` with `SpectatorView::Revealed` to translate a
+    /// `MatchEvent`.
+    pub fn event(&mut self, event: &SpectatorEvent) -> Vec<Message> {
+        match *event {
+            SpectatorEvent::TileDrawn { seat, tile } => {
+                self.draws.insert(seat, tile);
+                let pai = match tile {
+                    Some(id) if seat == self.seat => crate::tile::by_id(id).into(),
+                    _ => Pai::UNKNOWN,
+                };
+
+                vec![Message::Tsumo {
+                    actor: actor(seat),
+                    pai,
+                }]
+            }
+
+            SpectatorEvent::TileDiscarded { seat, tile } => {
+                let draw = self.draws.remove(&seat).flatten();
+                vec![Message::Dahai {
+                    actor: actor(seat),
+                    pai: crate::tile::by_id(tile).into(),
+                    tsumogiri: draw == Some(tile),
+                }]
+            }
+
+            SpectatorEvent::MatchEnded => vec![
+                Message::Ryukyoku {
+                    reason: Some("fanpai".into()),
+                },
+                Message::EndKyoku,
+                Message::EndGame,
+            ],
+
+            // The player's own hand was already sent with `start_kyoku`, and other
+            // players' hands are never visible to them.
+            SpectatorEvent::HandsRevealed { .. } => Vec::new(),
+        }
+    }
+
+    /// Translates a `dahai` message sent by the player into the request that makes
+    /// the discard.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message isn't a discard by the player, or if the tile
+    /// isn't in their hand.
+    pub fn request(&self, id: MatchId, hand: &Hand, message: &Message) -> Result<ClientRequest> {
+        match *message {
+            Message::Dahai {
+                actor: message_actor,
+                pai,
+                tsumogiri,
+            } if message_actor == actor(self.seat) => {
+                let tile = find_tile(hand, pai, tsumogiri)?;
+                Ok(ClientRequest::DiscardTile(DiscardTileRequest {
+                    id,
+                    player: self.seat,
+                    tile,
+                }))
+            }
+
+            _ => bail!("Unsupported MJAI action {:?}", message),
+        }
+    }
+
+    /// Translates a call sent by the player in response to a discard of `discard`.
+    ///
+    /// Returns `None` if the message isn't a call, and an error if the player can't
+    /// make the call with their hand.
+    pub fn call(
+        &self,
+        hand: &Hand,
+        discard: TileInstance,
+        message: &Message,
+    ) -> Result<Option<Call>> {
+        let call = match message {
+            Message::Pon { .. } => Call::Pon,
+            Message::Daiminkan { .. } => Call::Kan,
+
+            Message::Chi { consumed, .. } => {
+                let tiles = consumed
+                    .iter()
+                    .map(|&pai| find_tile(hand, pai, false))
+                    .collect::<Result<Vec<_>>>()?;
+                match tiles[..] {
+                    [first, second] => Call::Chii(first, second),
+                    _ => bail!("Chi must consume two tiles, but consumed {:?}", consumed),
+                }
+            }
+
+            _ => return Ok(None),
+        };
+
+        // Players can only chii the discards of the player right before them.
+        let can_chii = match *message {
+            Message::Chi { target, .. } => seat(target)?.next() == self.seat,
+            _ => false,
+        };
+        if !hand.find_possible_calls(&discard, can_chii).contains(&call) {
+            bail!("Can't call {:?} on {}", call, discard.tile);
+        }
+
+        Ok(Some(call))
+    }
+}
+
+/// Finds the instance of `pai` in `hand`, preferring the current draw if the discard
+/// is tsumogiri.
+fn find_tile(hand: &Hand, pai: Pai, tsumogiri: bool) -> Result<TileId> {
+    let tile = pai.0.ok_or_else(|| anyhow!("Can't use a hidden tile"))?;
+    let draw = hand.current_draw().filter(|draw| draw.tile == tile);
+    let concealed = hand.tiles().iter().find(|instance| instance.tile == tile);
+
+    let instance = if tsumogiri {
+        draw.or(concealed)
+    } else {
+        concealed.or(draw)
+    };
+
+    instance
+        .map(|instance| instance.id)
+        .ok_or_else(|| anyhow!("{} isn't in the player's hand", tile))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bot::testing::*, tile::TILE_SET};
+
+    #[test]
+    fn parses_messages() {
+        let message: Message =
+            serde_json::from_str(r#"{"type":"dahai","actor":2,"pai":"5mr","tsumogiri":false}"#)
+                .unwrap();
+        assert_eq!(
+            Message::Dahai {
+                actor: 2,
+                pai: Tile::from(SimpleTile::new(Suit::Characters, 5)).into(),
+                tsumogiri: false,
+            },
+            message,
+        );
+
+        let message: Message =
+            serde_json::from_str(r#"{"type":"hora","actor":0,"target":3}"#).unwrap();
+        assert_eq!(
+            Message::Hora {
+                actor: 0,
+                target: 3,
+                pai: None,
+            },
+            message,
+        );
+
+        assert_eq!(
+            r#"{"type":"tsumo","actor":1,"pai":"?"}"#,
+            serde_json::to_string(&Message::Tsumo {
+                actor: 1,
+                pai: Pai::UNKNOWN,
+            })
+            .unwrap(),
+        );
+        assert_eq!(
+            r#"{"type":"none"}"#,
+            serde_json::to_string(&Message::None).unwrap(),
+        );
+
+        assert!(serde_json::from_str::<Pai>(r#""0m""#).is_err());
+        assert!(serde_json::from_str::<Pai>(r#""5z""#).is_err());
+        for instance in TILE_SET.iter() {
+            let pai = Pai::from(instance.tile);
+            let json = serde_json::to_string(&pai).unwrap();
+            assert_eq!(pai, serde_json::from_str(&json).unwrap());
+        }
+    }
+
+    #[test]
+    fn translates_events() {
+        let view = bot_view("123m456p789s56s11z4z");
+        let draw = *view.hand.current_draw().unwrap();
+        let mut translator = Translator::new(Wind::South);
+
+        let start = translator.start(view.hand.tiles());
+        match &start[1] {
+            Message::StartKyoku { tehais, .. } => {
+                assert_eq!(vec![Pai::UNKNOWN; 13], tehais[0]);
+                assert_eq!(Pai::from(view.hand.tiles()[0].tile), tehais[1][0]);
+            }
+            message => panic!("Unexpected message {:?}", message),
+        }
+
+        // Other players' draws are hidden, even when the event reveals them.
+        let hidden = TILE_SET[0];
+        assert_eq!(
+            vec![Message::Tsumo {
+                actor: 0,
+                pai: Pai::UNKNOWN,
+            }],
+            translator.event(&SpectatorEvent::TileDrawn {
+                seat: Wind::East,
+                tile: Some(hidden.id),
+            }),
+        );
+        assert_eq!(
+            vec![Message::Dahai {
+                actor: 0,
+                pai: hidden.tile.into(),
+                tsumogiri: true,
+            }],
+            translator.event(&SpectatorEvent::TileDiscarded {
+                seat: Wind::East,
+                tile: hidden.id,
+            }),
+        );
+
+        assert_eq!(
+            vec![Message::Tsumo {
+                actor: 1,
+                pai: draw.tile.into(),
+            }],
+            translator.event(&SpectatorEvent::TileDrawn {
+                seat: Wind::South,
+                tile: Some(draw.id),
+            }),
+        );
+
+        // Tsumogiri discards use the current draw.
+        let message = Message::Dahai {
+            actor: 1,
+            pai: draw.tile.into(),
+            tsumogiri: true,
+        };
+        match translator
+            .request(MatchId::new(1), &view.hand, &message)
+            .unwrap()
+        {
+            ClientRequest::DiscardTile(request) => assert_eq!(draw.id, request.tile),
+            request => panic!("Unexpected request {:?}", request),
+        }
+
+        let other = Message::Dahai {
+            actor: 2,
+            pai: draw.tile.into(),
+            tsumogiri: true,
+        };
+        assert!(translator
+            .request(MatchId::new(1), &view.hand, &other)
+            .is_err());
+    }
+}
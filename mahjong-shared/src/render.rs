@@ -0,0 +1,370 @@
+//! Text rendering for tiles, hands, and discard ponds.
+//!
+//! Two rendering styles are supported:
+//!
+//! * [`Style::Unicode`] uses the glyphs from the Unicode "Mahjong Tiles" block
+//!   (U+1F000 to U+1F02B). These look nice, but not every terminal or font is able
+//!   to display them.
+//! * [`Style::Ascii`] uses the common "mpsz" shorthand, where each tile is written
+//!   as its number followed by a letter for its suit: `m` for characters, `p` for
+//!   coins, `s` for bamboo, and `z` for honors. Honors are numbered East, South,
+//!   West, North, White, Green, Red, e.g. `1z` is East and `7z` is the red dragon.
+//!
+//! These are primarily intended for debugging purposes, e.g. for log output and
+//! test failure messages, and for text-based tools.
+//!
+//! [`Style::Unicode`]: enum.Style.html#variant.Unicode
+//! [`Style::Ascii`]: enum.Style.html#variant.Ascii
+
+use crate::{
+    hand::{Hand, OpenMeld},
+    tile::{Dragon, SimpleTile, Suit, Tile, TileId, TileInstance, Wind},
+};
+use anyhow::{bail, Result};
+
+/// The number of tiles in each row of a discard pond.
+pub const DISCARDS_PER_ROW: usize = 6;
+
+/// The style to use when rendering tiles as text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Style {
+    Unicode,
+    Ascii,
+}
+
+/// Returns the text representation of a single tile.
+pub fn tile(tile: Tile, style: Style) -> String {
+    match style {
+        Style::Unicode => glyph(tile).to_string(),
+        Style::Ascii => ascii(tile),
+    }
+}
+
+/// Returns the Unicode glyph for `tile`.
+pub fn glyph(tile: Tile) -> char {
+    let offset = match tile {
+        Tile::Wind(Wind::East) => 0x00,
+        Tile::Wind(Wind::South) => 0x01,
+        Tile::Wind(Wind::West) => 0x02,
+        Tile::Wind(Wind::North) => 0x03,
+        Tile::Dragon(Dragon::Red) => 0x04,
+        Tile::Dragon(Dragon::Green) => 0x05,
+        Tile::Dragon(Dragon::White) => 0x06,
+        Tile::Simple(simple) => {
+            let base = match simple.suit {
+                Suit::Characters => 0x07,
+                Suit::Bamboo => 0x10,
+                Suit::Coins => 0x19,
+            };
+
+            base + u32::from(simple.number) - 1
+        }
+    };
+
+    std::char::from_u32(0x1F000 + offset).expect("Invalid mahjong tile code point")
+}
+
+/// Returns the "mpsz" shorthand for `tile`, e.g. `5p` or `7z`.
+pub fn ascii(tile: Tile) -> String {
+    match tile {
+        Tile::Simple(simple) => {
+            let suit = match simple.suit {
+                Suit::Characters => 'm',
+                Suit::Coins => 'p',
+                Suit::Bamboo => 's',
+            };
+
+            format!("{}{}", simple.number, suit)
+        }
+
+        Tile::Wind(wind) => {
+            let number = match wind {
+                Wind::East => 1,
+                Wind::South => 2,
+                Wind::West => 3,
+                Wind::North => 4,
+            };
+
+            format!("{}z", number)
+        }
+
+        Tile::Dragon(dragon) => {
+            let number = match dragon {
+                Dragon::White => 5,
+                Dragon::Green => 6,
+                Dragon::Red => 7,
+            };
+
+            format!("{}z", number)
+        }
+    }
+}
+
+/// Parses tiles written in the "mpsz" shorthand, the reverse of rendering them with
+/// `Style::Ascii`.
+///
+/// Consecutive tiles in the same suit may share the suit letter, so `123m` is the
+/// same as `1m 2m 3m`. Whitespace between tiles is ignored.
+///
+/// # Errors
+///
+/// Returns an error if `text` contains anything other than valid tiles.
+pub fn parse_ascii(text: &str) -> Result<Vec<Tile>> {
+    let mut tiles = Vec::new();
+    let mut numbers = Vec::new();
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        if let Some(number) = c.to_digit(10) {
+            numbers.push(number as u8);
+            continue;
+        }
+
+        if numbers.is_empty() {
+            bail!("Suit {:?} isn't preceded by a tile number", c);
+        }
+
+        for number in numbers.drain(..) {
+            let tile = match (c, number) {
+                (_, 0) => bail!("Invalid tile number 0"),
+                ('m', _) => SimpleTile::new(Suit::Characters, number).into(),
+                ('p', _) => SimpleTile::new(Suit::Coins, number).into(),
+                ('s', _) => SimpleTile::new(Suit::Bamboo, number).into(),
+                ('z', 1) => Wind::East.into(),
+                ('z', 2) => Wind::South.into(),
+                ('z', 3) => Wind::West.into(),
+                ('z', 4) => Wind::North.into(),
+                ('z', 5) => Dragon::White.into(),
+                ('z', 6) => Dragon::Green.into(),
+                ('z', 7) => Dragon::Red.into(),
+                ('z', _) => bail!("Invalid honor number {}", number),
+                _ => bail!("Unknown suit {:?}", c),
+            };
+
+            tiles.push(tile);
+        }
+    }
+
+    if !numbers.is_empty() {
+        bail!("Tile numbers at the end of {:?} have no suit", text);
+    }
+
+    Ok(tiles)
+}
+
+/// Renders a sequence of tiles in the order given.
+///
+/// ASCII tiles are separated by spaces. Unicode tiles are rendered without any
+/// separator, since the glyphs are already visually distinct.
+pub fn tiles<'a, I>(tiles: I, style: Style) -> String
+where
+    I: IntoIterator<Item = &'a TileInstance>,
+{
+    join(
+        tiles.into_iter().map(|instance| tile(instance.tile, style)),
+        style,
+    )
+}
+
+/// Renders an open meld belonging to the player at `seat`.
+///
+/// Traditionally the called tile is rotated sideways, and its position within the
+/// meld indicates which player it was called from: leftmost for the player to the
+/// left, in the middle for the player across, and rightmost for the player to the
+/// right. Since we can't rotate text, the called tile is instead wrapped in square
+/// brackets, e.g. `[3p] 4p 5p`.
+pub fn meld<T>(meld: &OpenMeld<T>, seat: Wind, style: Style) -> String
+where
+    T: AsRef<[TileInstance]>,
+{
+    let (called, rest): (Vec<&TileInstance>, Vec<_>) = meld
+        .tiles
+        .as_ref()
+        .iter()
+        .partition(|instance| instance.id == meld.called);
+    let called = called
+        .first()
+        .unwrap_or_else(|| panic!("Called tile {:?} is not in the meld", meld.called));
+
+    // Determine where the called tile goes based on where the caller sits relative
+    // to the player who discarded it.
+    let position = if meld.called_from.next() == seat {
+        0
+    } else if meld.called_from == seat.next() {
+        rest.len()
+    } else {
+        1
+    };
+
+    let mut rendered = rest
+        .iter()
+        .map(|instance| tile(instance.tile, style))
+        .collect::<Vec<_>>();
+    rendered.insert(position, format!("[{}]", tile(called.tile, style)));
+
+    join(rendered, style)
+}
+
+/// Renders the full hand for the player at `seat`.
+///
+/// The concealed tiles are sorted and rendered first, followed by the current draw
+/// (if any), then any closed kongs and open melds, each group separated by `|`.
+pub fn hand(hand: &Hand, seat: Wind, style: Style) -> String {
+    let mut concealed = hand.tiles().to_vec();
+    concealed.sort_by_key(|instance| sort_key(instance.tile));
+
+    let mut groups = vec![tiles(&concealed, style)];
+
+    if let Some(draw) = hand.current_draw() {
+        groups.push(tile(draw.tile, style));
+    }
+
+    groups.extend(
+        hand.closed_kongs()
+            .iter()
+            .map(|kong| tiles(kong.iter(), style)),
+    );
+    groups.extend(hand.open_chows().iter().map(|m| meld(m, seat, style)));
+    groups.extend(hand.open_pongs().iter().map(|m| meld(m, seat, style)));
+    groups.extend(hand.open_kongs().iter().map(|m| meld(m, seat, style)));
+
+    groups.join(" | ")
+}
+
+/// Renders a discard pond, in the order the tiles were discarded.
+///
+/// Discards are split into rows of [`DISCARDS_PER_ROW`] tiles, matching how they
+/// are laid out on a physical table. Rows are separated by newlines.
+///
+/// [`DISCARDS_PER_ROW`]: constant.DISCARDS_PER_ROW.html
+pub fn discards(discards: &[TileInstance], style: Style) -> String {
+    discards
+        .chunks(DISCARDS_PER_ROW)
+        .map(|row| tiles(row, style))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the text representation of the tile with the specified ID.
+pub fn tile_id(id: TileId, style: Style) -> String {
+    tile(crate::tile::by_id(id), style)
+}
+
+/// Sort key that groups tiles by suit, following the "mpsz" order used by the
+/// ASCII shorthand.
+fn sort_key(tile: Tile) -> (u8, u8) {
+    match tile {
+        Tile::Simple(simple) => {
+            let suit = match simple.suit {
+                Suit::Characters => 0,
+                Suit::Coins => 1,
+                Suit::Bamboo => 2,
+            };
+
+            (suit, simple.number)
+        }
+
+        Tile::Wind(wind) => (3, wind as u8),
+        Tile::Dragon(dragon) => (4, dragon as u8),
+    }
+}
+
+fn join<I>(rendered: I, style: Style) -> String
+where
+    I: IntoIterator<Item = String>,
+{
+    let separator = match style {
+        Style::Unicode => "",
+        Style::Ascii => " ",
+    };
+
+    rendered.into_iter().collect::<Vec<_>>().join(separator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile::TILE_SET;
+
+    fn instance(tile: impl Into<Tile>) -> TileInstance {
+        let tile = tile.into();
+        *TILE_SET
+            .iter()
+            .find(|instance| instance.tile == tile)
+            .unwrap()
+    }
+
+    #[test]
+    fn unicode_glyphs() {
+        assert_eq!('\u{1F000}', glyph(Wind::East.into()));
+        assert_eq!('\u{1F004}', glyph(Dragon::Red.into()));
+        assert_eq!('\u{1F006}', glyph(Dragon::White.into()));
+        assert_eq!(
+            '\u{1F007}',
+            glyph(SimpleTile::new(Suit::Characters, 1).into())
+        );
+        assert_eq!('\u{1F018}', glyph(SimpleTile::new(Suit::Bamboo, 9).into()));
+        assert_eq!('\u{1F021}', glyph(SimpleTile::new(Suit::Coins, 9).into()));
+    }
+
+    #[test]
+    fn ascii_names() {
+        assert_eq!("5p", ascii(SimpleTile::new(Suit::Coins, 5).into()));
+        assert_eq!("1m", ascii(SimpleTile::new(Suit::Characters, 1).into()));
+        assert_eq!("9s", ascii(SimpleTile::new(Suit::Bamboo, 9).into()));
+        assert_eq!("1z", ascii(Wind::East.into()));
+        assert_eq!("5z", ascii(Dragon::White.into()));
+        assert_eq!("7z", ascii(Dragon::Red.into()));
+    }
+
+    #[test]
+    fn parse_shorthand() {
+        let tiles = parse_ascii("19m 5p 12s 57z").unwrap();
+        assert_eq!(
+            "1m 9m 5p 1s 2s 5z 7z",
+            tiles
+                .iter()
+                .map(|&tile| ascii(tile))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+
+        assert!(parse_ascii("123").is_err());
+        assert!(parse_ascii("8z").is_err());
+        assert!(parse_ascii("0m").is_err());
+        assert!(parse_ascii("m1").is_err());
+    }
+
+    #[test]
+    fn meld_marks_called_tile() {
+        let three = instance(SimpleTile::new(Suit::Coins, 3));
+        let four = instance(SimpleTile::new(Suit::Coins, 4));
+        let five = instance(SimpleTile::new(Suit::Coins, 5));
+
+        let mut chow = OpenMeld {
+            tiles: [three, four, five],
+            called: three.id,
+            called_from: Wind::East,
+        };
+
+        // Called from the player to the left.
+        assert_eq!("[3p] 4p 5p", meld(&chow, Wind::South, Style::Ascii));
+
+        // Called from the player across.
+        chow.called_from = Wind::North;
+        assert_eq!("4p [3p] 5p", meld(&chow, Wind::South, Style::Ascii));
+
+        // Called from the player to the right.
+        chow.called_from = Wind::West;
+        assert_eq!("4p 5p [3p]", meld(&chow, Wind::South, Style::Ascii));
+    }
+
+    #[test]
+    fn discard_rows() {
+        let pond = TILE_SET
+            .iter()
+            .step_by(4)
+            .take(8)
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!("1p 2p 3p 4p 5p 6p\n7p 8p", discards(&pond, Style::Ascii));
+    }
+}
@@ -0,0 +1,540 @@
+//! Bot-vs-bot self-play, for balancing bots and testing rules changes at scale.
+//!
+//! A `Simulation` plays complete matches between four bots using `MatchState`
+//! directly, without going through the server, and `Stats` aggregates the results.
+//! The match logic only supports drawing and discarding so far, so the simulation
+//! adjudicates riichi and wins itself, using `analysis` to check that they're legal.
+//! Calls aren't supported yet, so bots are never offered any. Games are always
+//! played with `Ruleset::Riichi`, since it's the only ruleset so far.
+
+use crate::{
+    analysis,
+    bot::{
+        Bot, BotKind, BotView, CallDecision, MjaiBot, MonteCarloBot, SearchBudget, TurnDecision,
+    },
+    match_state::{MatchId, MatchState},
+    messages::MatchEvent,
+    tile::{self, Wind},
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, process::Command};
+use strum::IntoEnumIterator;
+use tracing::*;
+
+/// Settings for a series of self-play games.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Simulation {
+    /// The bots playing in each game. The seats rotate every game, so that each bot
+    /// plays in each seat equally often.
+    pub lineup: [BotKind; 4],
+
+    /// The seed for the first game. Each game uses the next seed, so that any single
+    /// game can be replayed on its own.
+    pub seed: u64,
+
+    /// The budget for any `MonteCarloBot`s in the lineup. This should only limit the
+    /// number of samples, otherwise the results of a game depend on how fast the
+    /// machine running it is.
+    pub search_budget: SearchBudget,
+
+    /// The shell command that starts the engine for any `Mjai` bots in the lineup.
+    /// Each bot starts its own copy of the engine for every game.
+    pub mjai_engine: Option<String>,
+}
+
+impl Simulation {
+    pub fn new(lineup: [BotKind; 4]) -> Self {
+        Self {
+            lineup,
+            seed: 0,
+            search_budget: SearchBudget::samples(50),
+            mjai_engine: None,
+        }
+    }
+
+    /// Returns the seat of each bot in the lineup for the game at `index`.
+    pub fn seats(&self, index: u64) -> [Wind; 4] {
+        let mut seats = [Wind::East; 4];
+        let rotated = Wind::iter().cycle().skip((index % 4) as usize);
+        for (seat, wind) in seats.iter_mut().zip(rotated) {
+            *seat = wind;
+        }
+        seats
+    }
+
+    /// Plays the game at `index` through to the end.
+    pub fn play(&self, index: u64) -> GameResult {
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(index));
+        let mut tiles = tile::TILE_SET.clone();
+        tiles.shuffle(&mut rng);
+
+        let seats = self.seats(index);
+        let bots = self
+            .lineup
+            .iter()
+            .zip(&seats)
+            .map(|(&kind, &seat)| (seat, self.create_bot(kind, rng.gen())))
+            .collect();
+
+        let mut game = Game {
+            state: MatchState::new(MatchId::new(index as u32), tiles),
+            bots,
+            riichi: HashMap::new(),
+            turns: 0,
+            invalid_decisions: 0,
+        };
+        let outcome = game.play();
+
+        let mut riichi = game.riichi.keys().copied().collect::<Vec<_>>();
+        riichi.sort();
+
+        GameResult {
+            index,
+            seats,
+            outcome,
+            turns: game.turns,
+            riichi,
+            invalid_decisions: game.invalid_decisions,
+        }
+    }
+
+    fn create_bot(&self, kind: BotKind, seed: u64) -> Box<dyn Bot> {
+        match kind {
+            BotKind::MonteCarlo => Box::new(MonteCarloBot::with_budget(seed, self.search_budget)),
+
+            BotKind::Mjai => match &self.mjai_engine {
+                Some(engine) => {
+                    let mut command = Command::new("sh");
+                    command.arg("-c").arg(engine);
+                    match MjaiBot::spawn(command) {
+                        Ok(bot) => Box::new(bot),
+                        Err(err) => {
+                            error!("{:?}", err);
+                            kind.create(seed)
+                        }
+                    }
+                }
+
+                None => kind.create(seed),
+            },
+
+            _ => kind.create(seed),
+        }
+    }
+}
+
+/// The result of a single self-play game.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameResult {
+    pub index: u64,
+
+    /// The seat of each bot in the lineup.
+    pub seats: [Wind; 4],
+
+    pub outcome: Outcome,
+
+    /// The number of discards made during the game.
+    pub turns: usize,
+
+    /// The seats that declared riichi.
+    pub riichi: Vec<Wind>,
+
+    /// The number of decisions that the bots made that weren't legal, e.g. declaring
+    /// a win with an incomplete hand. Illegal discards are replaced by discarding the
+    /// current draw, and other illegal decisions are ignored.
+    pub invalid_decisions: usize,
+}
+
+/// How a game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Win {
+        winner: Wind,
+
+        /// The player who dealt in, or `None` if the winner won on their own draw.
+        discarder: Option<Wind>,
+
+        han: u8,
+        points: u32,
+    },
+
+    /// The wall ran out before anyone won.
+    Draw,
+}
+
+/// The state of a game in progress.
+#[derive(Debug)]
+struct Game {
+    state: MatchState,
+    bots: HashMap<Wind, Box<dyn Bot>>,
+
+    /// The players that have declared riichi, along with the index of their riichi
+    /// tile within their discards.
+    riichi: HashMap<Wind, usize>,
+
+    turns: usize,
+    invalid_decisions: usize,
+}
+
+impl Game {
+    fn play(&mut self) -> Outcome {
+        loop {
+            let seat = self.state.current_turn;
+            if self.state.wall.is_empty() {
+                return Outcome::Draw;
+            }
+
+            let draw = self
+                .state
+                .draw_for_player(seat)
+                .expect("Failed to draw from a non-empty wall");
+            self.broadcast(&MatchEvent::TileDrawn { seat, tile: draw });
+
+            let view = self.view(seat);
+            let decision = self.bots.get_mut(&seat).unwrap().take_turn(&view);
+            let mut discard = match decision {
+                TurnDecision::Tsumo => {
+                    let riichi = self.riichi.contains_key(&seat);
+                    match analysis::han(&view.hand, None, seat, riichi) {
+                        Some(han) => return win(seat, None, han),
+                        None => {
+                            self.invalid_decisions += 1;
+                            draw
+                        }
+                    }
+                }
+
+                TurnDecision::Riichi(tile) => {
+                    if view.can_riichi(tile) {
+                        self.riichi.insert(seat, view.hand.discards().len());
+                    } else {
+                        self.invalid_decisions += 1;
+                    }
+                    tile
+                }
+
+                TurnDecision::Discard(tile) => tile,
+            };
+
+            // Once a player has declared riichi, they can only discard their draws.
+            let riichi_tile = self.riichi.get(&seat) == Some(&view.hand.discards().len());
+            if self.riichi.contains_key(&seat) && !riichi_tile && discard != draw {
+                self.invalid_decisions += 1;
+                discard = draw;
+            }
+
+            if self.state.discard_tile(seat, discard).is_err() {
+                self.invalid_decisions += 1;
+                self.state
+                    .discard_tile(seat, draw)
+                    .expect("Failed to discard the current draw");
+            }
+
+            let discard = *self.state.player(seat).discards().last().unwrap();
+            self.turns += 1;
+            self.broadcast(&MatchEvent::TileDiscarded {
+                seat,
+                tile: discard.id,
+            });
+
+            // The other players get a chance to win on the discard, in turn order.
+            let mut other = seat.next();
+            while other != seat {
+                let view = self.view(other);
+                let bot = self.bots.get_mut(&other).unwrap();
+                match bot.respond_to_discard(&view, discard, &[]) {
+                    CallDecision::Pass => {}
+
+                    CallDecision::Ron => {
+                        let riichi = self.riichi.contains_key(&other);
+                        match analysis::han(&view.hand, Some(discard.tile), other, riichi) {
+                            Some(han) => return win(other, Some(seat), han),
+                            None => self.invalid_decisions += 1,
+                        }
+                    }
+
+                    CallDecision::Call(_) => self.invalid_decisions += 1,
+                }
+
+                other = other.next();
+            }
+        }
+    }
+
+    /// Creates the view of the match for the bot in `seat`.
+    fn view(&self, seat: Wind) -> BotView {
+        BotView {
+            riichi: self.riichi.clone(),
+            ..BotView::new(&self.state, seat)
+        }
+    }
+
+    fn broadcast(&mut self, event: &MatchEvent) {
+        for seat in Wind::iter() {
+            let view = self.view(seat);
+            let event = view.redact(event);
+            self.bots.get_mut(&seat).unwrap().observe(&view, &event);
+        }
+    }
+}
+
+fn win(winner: Wind, discarder: Option<Wind>, han: u8) -> Outcome {
+    Outcome::Win {
+        winner,
+        discarder,
+        han,
+        points: analysis::points(han, winner == Wind::East),
+    }
+}
+
+/// Aggregate results over a series of self-play games.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    pub games: u64,
+    pub draws: u64,
+
+    /// The total number of discards made over every game.
+    pub turns: u64,
+
+    pub invalid_decisions: u64,
+
+    /// The results for each bot in the lineup.
+    pub players: Vec<PlayerStats>,
+}
+
+/// Aggregate results for one bot in the lineup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerStats {
+    pub bot: BotKind,
+    pub wins: u64,
+
+    /// How many of the bot's wins were on its own draw.
+    pub tsumo: u64,
+
+    pub deal_ins: u64,
+    pub riichi: u64,
+
+    /// The total points scored by the bot's wins.
+    pub points_won: u64,
+
+    /// The total points lost by dealing in.
+    pub points_dealt_in: u64,
+}
+
+impl Stats {
+    pub fn new(lineup: &[BotKind; 4]) -> Self {
+        Self {
+            games: 0,
+            draws: 0,
+            turns: 0,
+            invalid_decisions: 0,
+            players: lineup
+                .iter()
+                .map(|&bot| PlayerStats {
+                    bot,
+                    wins: 0,
+                    tsumo: 0,
+                    deal_ins: 0,
+                    riichi: 0,
+                    points_won: 0,
+                    points_dealt_in: 0,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn record(&mut self, result: &GameResult) {
+        self.games += 1;
+        self.turns += result.turns as u64;
+        self.invalid_decisions += result.invalid_decisions as u64;
+
+        for (player, seat) in self.players.iter_mut().zip(&result.seats) {
+            if result.riichi.contains(seat) {
+                player.riichi += 1;
+            }
+        }
+
+        match result.outcome {
+            Outcome::Win {
+                winner,
+                discarder,
+                points,
+                ..
+            } => {
+                for (player, &seat) in self.players.iter_mut().zip(&result.seats) {
+                    if seat == winner {
+                        player.wins += 1;
+                        player.points_won += points as u64;
+                        if discarder.is_none() {
+                            player.tsumo += 1;
+                        }
+                    } else if Some(seat) == discarder {
+                        player.deal_ins += 1;
+                        player.points_dealt_in += points as u64;
+                    }
+                }
+            }
+
+            Outcome::Draw => self.draws += 1,
+        }
+    }
+
+    /// Combines the results from `other`, which must have been played with the same
+    /// lineup.
+    pub fn merge(&mut self, other: &Stats) {
+        assert_eq!(
+            self.players.len(),
+            other.players.len(),
+            "Can't merge stats for different lineups"
+        );
+
+        self.games += other.games;
+        self.draws += other.draws;
+        self.turns += other.turns;
+        self.invalid_decisions += other.invalid_decisions;
+
+        for (player, other) in self.players.iter_mut().zip(&other.players) {
+            assert_eq!(
+                player.bot, other.bot,
+                "Can't merge stats for different lineups"
+            );
+            player.wins += other.wins;
+            player.tsumo += other.tsumo;
+            player.deal_ins += other.deal_ins;
+            player.riichi += other.riichi;
+            player.points_won += other.points_won;
+            player.points_dealt_in += other.points_dealt_in;
+        }
+    }
+
+    /// Returns the fraction of games that ended without a winner.
+    pub fn draw_rate(&self) -> f64 {
+        ratio(self.draws, self.games)
+    }
+
+    /// Returns the average number of discards made per game.
+    pub fn average_turns(&self) -> f64 {
+        ratio(self.turns, self.games)
+    }
+
+    /// Returns the fraction of games won by the bot at `player` in the lineup.
+    pub fn win_rate(&self, player: usize) -> f64 {
+        ratio(self.players[player].wins, self.games)
+    }
+
+    /// Returns the fraction of games in which the bot at `player` in the lineup dealt
+    /// into another player's hand.
+    pub fn deal_in_rate(&self, player: usize) -> f64 {
+        ratio(self.players[player].deal_ins, self.games)
+    }
+
+    /// Returns the fraction of games in which the bot at `player` in the lineup
+    /// declared riichi.
+    pub fn riichi_rate(&self, player: usize) -> f64 {
+        ratio(self.players[player].riichi, self.games)
+    }
+
+    /// Returns the average points scored by the wins of the bot at `player` in the
+    /// lineup.
+    pub fn average_value(&self, player: usize) -> f64 {
+        let player = &self.players[player];
+        ratio(player.points_won, player.wins)
+    }
+}
+
+/// Divides `count` by `total`, treating an empty total as zero.
+fn ratio(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn games_are_reproducible() {
+        let simulation = Simulation::new([
+            BotKind::FirstTile,
+            BotKind::Efficiency,
+            BotKind::Defensive,
+            BotKind::Efficiency,
+        ]);
+        assert_eq!(simulation.play(3), simulation.play(3));
+        assert_eq!(
+            [Wind::West, Wind::North, Wind::East, Wind::South],
+            simulation.seats(2),
+        );
+    }
+
+    #[test]
+    fn collects_stats() {
+        let lineup = [
+            BotKind::Efficiency,
+            BotKind::FirstTile,
+            BotKind::FirstTile,
+            BotKind::FirstTile,
+        ];
+        let simulation = Simulation::new(lineup);
+
+        let mut stats = Stats::new(&lineup);
+        for index in 0..8 {
+            stats.record(&simulation.play(index));
+        }
+
+        assert_eq!(8, stats.games);
+        assert_eq!(0, stats.invalid_decisions);
+
+        // Only the efficiency bot ever declares a win.
+        let wins = stats.players[0].wins;
+        assert!(wins > 0);
+        assert_eq!(stats.games, wins + stats.draws);
+        assert!(stats.players[1..].iter().all(|player| player.wins == 0));
+        assert!(stats.average_value(0) >= 1000.0);
+
+        let mut merged = Stats::new(&lineup);
+        merged.merge(&stats);
+        merged.merge(&stats);
+        assert_eq!(2 * wins, merged.players[0].wins);
+        assert_eq!(stats.win_rate(0), merged.win_rate(0));
+    }
+
+    // Test that an `Mjai` bot plays a full game by talking to its engine, using an
+    // engine that discards every tile it draws and logs what it's sent.
+    #[test]
+    #[cfg(unix)]
+    fn plays_against_mjai_engine() {
+        let log = std::env::temp_dir().join(format!("mjai-engine-{}.log", std::process::id()));
+        let engine = format!(
+            concat!(
+                r#"tee '{}' | sed -u -E "#,
+                r#"-e 's/^\{{"type":"tsumo","actor":([0-3]),"pai":"([^"?]+)"\}}$/"#,
+                r#"{{"type":"dahai","actor":\1,"pai":"\2","tsumogiri":true}}/' "#,
+                r#"-e t -e 's/.*/{{"type":"none"}}/'"#,
+            ),
+            log.display(),
+        );
+        let simulation = Simulation {
+            mjai_engine: Some(engine),
+            ..Simulation::new([
+                BotKind::Mjai,
+                BotKind::FirstTile,
+                BotKind::FirstTile,
+                BotKind::FirstTile,
+            ])
+        };
+
+        let result = simulation.play(0);
+        let sent = std::fs::read_to_string(&log).unwrap();
+        let _ = std::fs::remove_file(&log);
+
+        assert_eq!(0, result.invalid_decisions);
+        assert_eq!(Outcome::Draw, result.outcome);
+        assert!(sent.contains(r#""type":"start_kyoku""#));
+        assert!(sent.contains(r#""type":"dahai""#));
+    }
+}
@@ -0,0 +1,349 @@
+//! The view of a match given to spectators.
+//!
+//! Spectators don't receive the same `MatchSnapshot` and `MatchEvent`s as the
+//! players in the match, since those describe every tile in the match. Instead the
+//! server converts them into a `SpectatorSnapshot` and `SpectatorEvent`s for the
+//! spectator's `SpectatorView`, which determines whether the tiles in each player's
+//! hand are visible.
+
+use crate::{
+    hand::OpenMeld,
+    match_state::MatchId,
+    messages::MatchEvent,
+    sync::{HandSnapshot, MatchSnapshot},
+    tile::{TileId, Wind},
+};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// How much of the match a spectator can see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SpectatorView {
+    /// Events are sent as soon as they happen, but the concealed tiles in each
+    /// player's hand are hidden. Spectators can see the same tiles as any player in
+    /// the match, other than that player's own hand.
+    Hidden,
+
+    /// Every player's hand is visible, but events are sent after a delay so that a
+    /// spectator can't pass on what they see to a player in the match.
+    ///
+    /// The spectator starts with the hands hidden, and the starting hands are
+    /// revealed with `SpectatorEvent::HandsRevealed` once the delay has passed since
+    /// the match started.
+    Revealed,
+}
+
+/// The state of a match as seen by a spectator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpectatorSnapshot {
+    pub id: MatchId,
+    pub view: SpectatorView,
+
+    /// The hand for each seat, in wind order starting from East.
+    pub hands: Vec<SpectatorHand>,
+
+    /// The number of tiles left in the wall.
+    pub tiles_remaining: usize,
+
+    pub current_turn: Wind,
+}
+
+impl SpectatorSnapshot {
+    /// Creates the spectator's view of the match described by `snapshot`.
+    pub fn new(snapshot: &MatchSnapshot, view: SpectatorView) -> Self {
+        Self {
+            id: snapshot.id,
+            view,
+            hands: snapshot
+                .hands
+                .iter()
+                .map(|hand| SpectatorHand::new(hand, view))
+                .collect(),
+            tiles_remaining: snapshot.wall.len(),
+            current_turn: snapshot.current_turn,
+        }
+    }
+
+    pub fn hand(&self, seat: Wind) -> &SpectatorHand {
+        &self.hands[seat as usize]
+    }
+
+    /// Applies an event received from the server to the spectator's view of the
+    /// match.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event can't be applied, which indicates that the
+    /// spectator's view is out of sync with the server.
+    pub fn apply_event(&mut self, event: &SpectatorEvent) -> Result<()> {
+        match *event {
+            SpectatorEvent::HandsRevealed { ref hands } => {
+                if hands.len() != self.hands.len() {
+                    bail!(
+                        "Revealed {} hands, but the match has {}",
+                        hands.len(),
+                        self.hands.len(),
+                    );
+                }
+
+                self.hands = hands.clone();
+                self.view = SpectatorView::Revealed;
+            }
+
+            SpectatorEvent::TileDrawn { seat, tile } => {
+                if self.current_turn != seat {
+                    bail!(
+                        "Draw event for {:?} does not match current turn {:?}",
+                        seat,
+                        self.current_turn,
+                    );
+                }
+
+                self.tiles_remaining = self
+                    .tiles_remaining
+                    .checked_sub(1)
+                    .ok_or_else(|| anyhow!("Draw event for {:?} but the wall is empty", seat))?;
+
+                let hand = &mut self.hands[seat as usize];
+                if hand.has_draw {
+                    bail!("Draw event for {:?} but they already have a draw", seat);
+                }
+                hand.has_draw = true;
+                hand.current_draw = tile;
+            }
+
+            SpectatorEvent::TileDiscarded { seat, tile } => {
+                if self.current_turn != seat {
+                    bail!(
+                        "Discard event for {:?} does not match current turn {:?}",
+                        seat,
+                        self.current_turn,
+                    );
+                }
+
+                self.hands[seat as usize].discard_tile(tile)?;
+                self.current_turn = seat.next();
+            }
+
+            SpectatorEvent::MatchEnded => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// A player's hand as seen by a spectator.
+///
+/// Tiles in open melds and discards are always visible.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpectatorHand {
+    /// The concealed tiles in the hand, not including the current draw. Tiles that
+    /// the spectator can't see are `None`.
+    pub tiles: Vec<Option<TileId>>,
+
+    /// Whether the player has drawn a tile that they haven't discarded yet.
+    pub has_draw: bool,
+
+    /// The tile the player has drawn, if the spectator can see it.
+    pub current_draw: Option<TileId>,
+
+    pub open_chows: Vec<OpenMeld<[TileId; 3]>>,
+    pub open_pongs: Vec<OpenMeld<[TileId; 3]>>,
+    pub open_kongs: Vec<OpenMeld<[TileId; 4]>>,
+    pub closed_kongs: Vec<[TileId; 4]>,
+    pub discards: Vec<TileId>,
+}
+
+impl SpectatorHand {
+    fn new(hand: &HandSnapshot, view: SpectatorView) -> Self {
+        let visible = view == SpectatorView::Revealed;
+        Self {
+            tiles: hand
+                .tiles
+                .iter()
+                .map(|&tile| if visible { Some(tile) } else { None })
+                .collect(),
+            has_draw: hand.current_draw.is_some(),
+            current_draw: hand.current_draw.filter(|_| visible),
+            open_chows: hand.open_chows.clone(),
+            open_pongs: hand.open_pongs.clone(),
+            open_kongs: hand.open_kongs.clone(),
+            closed_kongs: hand.closed_kongs.clone(),
+            discards: hand.discards.clone(),
+        }
+    }
+
+    /// Moves `tile` into the discards, following the same rules as
+    /// `Hand::discard_tile`.
+    ///
+    /// If the tile isn't visible to the spectator, one of the hidden tiles is removed
+    /// in its place.
+    fn discard_tile(&mut self, tile: TileId) -> Result<()> {
+        if !self.has_draw {
+            bail!("Discard event for {:?} without a draw", tile);
+        }
+
+        self.tiles.push(self.current_draw.take());
+        self.has_draw = false;
+
+        let index = self
+            .tiles
+            .iter()
+            .position(|&concealed| concealed == Some(tile))
+            .or_else(|| self.tiles.iter().position(Option::is_none))
+            .ok_or_else(|| anyhow!("Discarded tile {:?} is not in hand", tile))?;
+        self.tiles.remove(index);
+
+        self.discards.push(tile);
+        Ok(())
+    }
+}
+
+/// A match event as seen by a spectator.
+///
+/// The same as the corresponding `MatchEvent`, except that tiles the spectator can't
+/// see are `None`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpectatorEvent {
+    TileDrawn {
+        seat: Wind,
+        tile: Option<TileId>,
+    },
+    TileDiscarded {
+        seat: Wind,
+        tile: TileId,
+    },
+    MatchEnded,
+
+    /// Reveals every player's starting hand to a spectator with
+    /// `SpectatorView::Revealed`, replacing the hidden hands in the spectator's
+    /// snapshot.
+    ///
+    /// Doesn't correspond to a `MatchEvent`, so it's sent with the sequence number
+    /// of the event that follows it.
+    HandsRevealed {
+        hands: Vec<SpectatorHand>,
+    },
+}
+
+impl SpectatorEvent {
+    /// Converts `event` into the event sent to spectators with `view`.
+    pub fn new(event: &MatchEvent, view: SpectatorView) -> Self {
+        match *event {
+            MatchEvent::TileDrawn { seat, tile } => SpectatorEvent::TileDrawn {
+                seat,
+                tile: Some(tile).filter(|_| view == SpectatorView::Revealed),
+            },
+
+            MatchEvent::TileDiscarded { seat, tile } => {
+                SpectatorEvent::TileDiscarded { seat, tile }
+            }
+
+            MatchEvent::MatchEnded => SpectatorEvent::MatchEnded,
+        }
+    }
+
+    /// Creates the event that reveals the starting hands in `initial`, the state of
+    /// the match before its first event.
+    pub fn hands_revealed(initial: &MatchSnapshot) -> Self {
+        SpectatorEvent::HandsRevealed {
+            hands: SpectatorSnapshot::new(initial, SpectatorView::Revealed).hands,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{match_state::MatchState, tile};
+
+    fn state() -> MatchState {
+        let mut state = MatchState::new(MatchId::new(5), tile::TILE_SET.clone());
+        state.draw_for_player(Wind::East).unwrap();
+        state
+    }
+
+    #[test]
+    fn hidden_view_hides_concealed_tiles() {
+        let snapshot = state().snapshot();
+
+        let hidden = SpectatorSnapshot::new(&snapshot, SpectatorView::Hidden);
+        let east = hidden.hand(Wind::East);
+        assert!(east.tiles.iter().all(Option::is_none));
+        assert_eq!(snapshot.hands[0].tiles.len(), east.tiles.len());
+        assert!(east.has_draw);
+        assert_eq!(None, east.current_draw);
+
+        let revealed = SpectatorSnapshot::new(&snapshot, SpectatorView::Revealed);
+        let east = revealed.hand(Wind::East);
+        assert_eq!(snapshot.hands[0].current_draw, east.current_draw);
+        assert_eq!(
+            snapshot.hands[0].tiles,
+            east.tiles
+                .iter()
+                .map(|tile| tile.unwrap())
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    // Test that applying the events from a match keeps each view in sync with the
+    // view of the match's current state.
+    #[test]
+    fn views_follow_match() {
+        let mut state = state();
+        let initial = state.snapshot();
+        let mut views = vec![
+            SpectatorSnapshot::new(&initial, SpectatorView::Hidden),
+            SpectatorSnapshot::new(&initial, SpectatorView::Revealed),
+        ];
+
+        for turn in 0..8 {
+            // Alternate between discarding the draw and a tile from the hand, since
+            // they're handled differently.
+            let seat = state.current_turn;
+            let tile = match turn % 2 {
+                0 => state.player(seat).current_draw().unwrap().id,
+                _ => state.player(seat).tiles()[0].id,
+            };
+            state.discard_tile(seat, tile).unwrap();
+            let discard = MatchEvent::TileDiscarded { seat, tile };
+
+            let seat = state.current_turn;
+            let tile = state.draw_for_player(seat).unwrap();
+            let draw = MatchEvent::TileDrawn { seat, tile };
+
+            for view in &mut views {
+                for event in &[&discard, &draw] {
+                    view.apply_event(&SpectatorEvent::new(event, view.view))
+                        .unwrap();
+                }
+            }
+        }
+
+        let snapshot = state.snapshot();
+        for view in &views {
+            assert_eq!(&SpectatorSnapshot::new(&snapshot, view.view), view);
+        }
+    }
+
+    // Test that revealing the starting hands turns a hidden view of the match into a
+    // revealed one.
+    #[test]
+    fn reveal_hands() {
+        let initial = state().snapshot();
+        let mut view = SpectatorSnapshot::new(&initial, SpectatorView::Hidden);
+
+        view.apply_event(&SpectatorEvent::hands_revealed(&initial))
+            .unwrap();
+        assert_eq!(
+            SpectatorSnapshot::new(&initial, SpectatorView::Revealed),
+            view,
+        );
+
+        let mut hands = view.hands.clone();
+        hands.pop();
+        assert!(view
+            .apply_event(&SpectatorEvent::HandsRevealed { hands })
+            .is_err());
+    }
+}
@@ -0,0 +1,286 @@
+//! Compact match snapshots and checksums for keeping clients in sync with the server.
+//!
+//! Rather than sending the full `MatchState` whenever a client needs the state of a
+//! match, the server sends a `MatchSnapshot`, which identifies tiles by ID alone.
+//! After that the client is kept up to date by applying each `MatchEvent` to its
+//! local state. Each event is sent along with the checksum of the server's state
+//! after applying the event, so that the client can detect when its local state no
+//! longer matches the server's and request a fresh snapshot with
+//! `ClientRequest::Resync`.
+
+use crate::{
+    hand::{Hand, OpenMeld},
+    match_state::{MatchId, MatchState},
+    tile::{self, TileId, TileInstance, Wind},
+};
+use cs_bindgen::prelude::*;
+use derive_more::Display;
+use maplit::hashmap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use strum::IntoEnumIterator;
+use thiserror::Error;
+
+/// A compact representation of the full state of a match.
+///
+/// Tiles are identified by their `TileId`, since the tile value for an ID is always
+/// the same (see `tile::by_id`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchSnapshot {
+    pub id: MatchId,
+
+    /// The hand for each seat, in wind order starting from East.
+    pub hands: Vec<HandSnapshot>,
+
+    pub wall: Vec<TileId>,
+    pub current_turn: Wind,
+}
+
+impl MatchSnapshot {
+    /// Calculates the checksum for the state that the snapshot represents.
+    ///
+    /// The checksum only depends on the state of the match, so it's the same for the
+    /// snapshot and for the `MatchState` it was created from.
+    pub fn checksum(&self) -> Checksum {
+        let mut hasher = Fnv1a::new();
+
+        hasher.write(&self.id.raw().to_le_bytes());
+        hasher.write(&[self.current_turn as u8]);
+        hasher.write_tiles(&self.wall);
+
+        for hand in &self.hands {
+            hasher.write_tiles(&hand.tiles);
+            match &hand.current_draw {
+                Some(draw) => hasher.write_tiles(std::slice::from_ref(draw)),
+                None => hasher.write_tiles(&[]),
+            }
+
+            for meld in hand.open_chows.iter().chain(&hand.open_pongs) {
+                hasher.write_meld(meld);
+            }
+
+            for meld in &hand.open_kongs {
+                hasher.write_meld(meld);
+            }
+
+            for kong in &hand.closed_kongs {
+                hasher.write_tiles(kong);
+            }
+
+            hasher.write_tiles(&hand.discards);
+        }
+
+        Checksum(hasher.finish())
+    }
+}
+
+/// A compact representation of a player's hand. See `MatchSnapshot`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandSnapshot {
+    pub tiles: Vec<TileId>,
+    pub current_draw: Option<TileId>,
+    pub open_chows: Vec<OpenMeld<[TileId; 3]>>,
+    pub open_pongs: Vec<OpenMeld<[TileId; 3]>>,
+    pub open_kongs: Vec<OpenMeld<[TileId; 4]>>,
+    pub closed_kongs: Vec<[TileId; 4]>,
+    pub discards: Vec<TileId>,
+}
+
+impl MatchState {
+    /// Creates a compact snapshot of the match.
+    pub fn snapshot(&self) -> MatchSnapshot {
+        MatchSnapshot {
+            id: self.id,
+            hands: Wind::iter()
+                .map(|seat| self.player(seat).snapshot())
+                .collect(),
+            wall: self.wall.iter().map(|tile| tile.id).collect(),
+            current_turn: self.current_turn,
+        }
+    }
+
+    /// Recreates the match state from a snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot doesn't have a hand for every seat, or if it
+    /// contains unknown or duplicate tiles.
+    pub fn from_snapshot(snapshot: &MatchSnapshot) -> Result<Self, InvalidSnapshot> {
+        if snapshot.hands.len() != 4 {
+            return Err(InvalidSnapshot::WrongNumberOfHands(snapshot.hands.len()));
+        }
+
+        // Make sure that no tile appears in more than one place, since the match logic
+        // assumes that each tile is unique.
+        let mut seen = HashSet::new();
+        let hand_tiles = snapshot.hands.iter().flat_map(|hand| {
+            hand.tiles
+                .iter()
+                .chain(&hand.current_draw)
+                .chain(hand.open_chows.iter().flat_map(|meld| &meld.tiles))
+                .chain(hand.open_pongs.iter().flat_map(|meld| &meld.tiles))
+                .chain(hand.open_kongs.iter().flat_map(|meld| &meld.tiles))
+                .chain(hand.closed_kongs.iter().flatten())
+                .chain(&hand.discards)
+        });
+        for &id in snapshot.wall.iter().chain(hand_tiles) {
+            if !seen.insert(id) {
+                return Err(InvalidSnapshot::DuplicateTile(id));
+            }
+        }
+
+        let hand = |index: usize| Hand::from_snapshot(&snapshot.hands[index]);
+        Ok(Self {
+            id: snapshot.id,
+            players: hashmap! {
+                Wind::East => hand(0)?,
+                Wind::South => hand(1)?,
+                Wind::West => hand(2)?,
+                Wind::North => hand(3)?,
+            },
+            wall: snapshot
+                .wall
+                .iter()
+                .map(|&id| instance(id))
+                .collect::<Result<_, _>>()?,
+            current_turn: snapshot.current_turn,
+        })
+    }
+
+    /// Calculates the checksum of the current state of the match.
+    ///
+    /// Two match states have the same checksum if they're equal, so clients can
+    /// compare the checksum of their local state to the one sent by the server to
+    /// verify that they're in sync.
+    pub fn checksum(&self) -> Checksum {
+        self.snapshot().checksum()
+    }
+}
+
+/// Checksum of the state of a match. See `MatchState::checksum`.
+#[cs_bindgen]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+#[display("{:016x}", _0)]
+#[repr(transparent)]
+pub struct Checksum(u64);
+
+impl Checksum {
+    pub fn new(checksum: u64) -> Self {
+        Self(checksum)
+    }
+
+    pub fn raw(self) -> u64 {
+        self.0
+    }
+}
+
+/// Error returned when a `MatchSnapshot` doesn't describe a valid match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+pub enum InvalidSnapshot {
+    #[error("Snapshot has {0} hands, expected 4")]
+    WrongNumberOfHands(usize),
+
+    #[error("Unknown tile ID {0:?}")]
+    UnknownTile(TileId),
+
+    #[error("Tile {0:?} appears more than once in snapshot")]
+    DuplicateTile(TileId),
+}
+
+/// Looks up the tile instance for `id`, failing if the ID isn't part of the tile set.
+pub(crate) fn instance(id: TileId) -> Result<TileInstance, InvalidSnapshot> {
+    tile::TILE_SET
+        .iter()
+        .find(|instance| instance.id == id)
+        .copied()
+        .ok_or(InvalidSnapshot::UnknownTile(id))
+}
+
+/// 64-bit FNV-1a hash, used to calculate state checksums.
+///
+/// We use our own hasher rather than `std::hash`, since checksums need to be the same
+/// on every platform and with every version of the standard library.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    /// Writes a list of tiles, prefixed with its length so that tiles can't shift
+    /// between adjacent lists without changing the checksum.
+    fn write_tiles(&mut self, tiles: &[TileId]) {
+        self.write(&(tiles.len() as u32).to_le_bytes());
+        for tile in tiles {
+            self.write(&[tile.raw()]);
+        }
+    }
+
+    fn write_meld<T: AsRef<[TileId]>>(&mut self, meld: &OpenMeld<T>) {
+        self.write_tiles(meld.tiles.as_ref());
+        self.write(&[meld.called.raw(), meld.called_from as u8]);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> MatchState {
+        let mut state = MatchState::new(MatchId::new(3), tile::TILE_SET.clone());
+        state.draw_for_player(Wind::East).unwrap();
+        state
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let state = state();
+        let snapshot = state.snapshot();
+        assert_eq!(Ok(state), MatchState::from_snapshot(&snapshot));
+    }
+
+    #[test]
+    fn checksum_tracks_state() {
+        let mut state = state();
+        let initial = state.checksum();
+        assert_eq!(initial, state.snapshot().checksum());
+        assert_eq!(initial, state.clone().checksum());
+
+        let tile = state.player(Wind::East).tiles()[0].id;
+        state.discard_tile(Wind::East, tile).unwrap();
+        assert_ne!(initial, state.checksum());
+    }
+
+    #[test]
+    fn invalid_snapshot_rejected() {
+        let mut snapshot = state().snapshot();
+        let tile = snapshot.wall[0];
+        snapshot.hands[1].discards.push(tile);
+        assert_eq!(
+            Err(InvalidSnapshot::DuplicateTile(tile)),
+            MatchState::from_snapshot(&snapshot),
+        );
+
+        let mut snapshot = state().snapshot();
+        snapshot.hands.pop();
+        assert_eq!(
+            Err(InvalidSnapshot::WrongNumberOfHands(3)),
+            MatchState::from_snapshot(&snapshot),
+        );
+    }
+}
@@ -0,0 +1,820 @@
+//! Conversion to and from Tenhou's JSON game logs.
+//!
+//! Tenhou's logs are the de facto format for sharing mahjong game records, and can
+//! be opened in most replay viewers. Each round in a log lists every player's
+//! starting hand, the tiles they took and the tiles they discarded, from which the
+//! order of play can be reconstructed.
+//!
+//! Tiles in the logs are written as two-digit codes: `11`-`19` for characters,
+//! `21`-`29` for coins, `31`-`39` for bamboo, `41`-`44` for the east, south, west
+//! and north winds, and `45`-`47` for the white, green and red dragons. Red fives
+//! are `51`-`53`, and are read as regular fives since we don't support red fives
+//! yet. Discards are written as `60` when the player discarded the tile they just
+//! drew, and with an `r` prefix when they declared riichi with the discard.
+//!
+//! Tenhou's other formats identify tiles with a number from 0 to 135 instead, four
+//! for each kind of tile. `TileId` uses the same scheme, except that Tenhou orders
+//! the kinds of tile differently. `to_tenhou_id` and `from_tenhou_id` convert
+//! between the two.
+
+use crate::{
+    analysis,
+    match_state::{MatchId, MatchState, DORA_INDICATOR},
+    messages::MatchEvent,
+    simulation::Outcome,
+    tile::{self, Dragon, SimpleTile, Suit, Tile, TileId, TileInstance, Wind, TILE_SET},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, convert::TryFrom};
+use strum::IntoEnumIterator;
+
+/// The code for a discard of the tile the player just drew.
+const TSUMOGIRI: u8 = 60;
+
+/// The score each player starts with.
+const STARTING_SCORE: i32 = 25000;
+
+/// The label of a round that ended in a win.
+const WIN: &str = "和了";
+
+/// The label of a round that ended when the wall ran out.
+const EXHAUSTIVE_DRAW: &str = "流局";
+
+/// A game log in Tenhou's JSON format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameLog {
+    #[serde(default)]
+    pub title: Vec<String>,
+
+    /// The names of the players, in seat order starting from the first dealer.
+    #[serde(default)]
+    pub name: Vec<String>,
+
+    #[serde(default)]
+    pub rule: Rule,
+
+    pub log: Vec<Round>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    /// The display name of the rules.
+    #[serde(default)]
+    pub disp: String,
+
+    /// The number of red fives in the game.
+    #[serde(default)]
+    pub aka: u8,
+}
+
+/// A single round within a game log.
+///
+/// Tenhou writes each round as an array, rather than as an object with named
+/// fields.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<Value>", into = "Vec<Value>")]
+pub struct Round {
+    /// The index of the round within the game, where 0 is East 1 and 4 is South 1.
+    pub kyoku: u8,
+    pub honba: u8,
+
+    /// The number of riichi sticks left on the table from previous rounds.
+    pub kyotaku: u8,
+
+    /// Each player's score at the start of the round.
+    pub scores: Vec<i32>,
+
+    pub dora: Vec<u8>,
+    pub ura_dora: Vec<u8>,
+
+    /// The actions of each player, in seat order starting from the first dealer.
+    pub players: Vec<PlayerLog>,
+
+    pub result: RoundResult,
+}
+
+/// The actions of one player within a round.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayerLog {
+    /// The player's starting hand.
+    pub haipai: Vec<u8>,
+
+    /// The tiles the player drew or called, in order.
+    pub takes: Vec<Action>,
+
+    /// The tiles the player discarded, in order.
+    pub discards: Vec<Action>,
+}
+
+/// A single take or discard by a player.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Action {
+    Tile(u8),
+
+    /// A call, kong or riichi, written as a string such as `"p414141"` or `"r23"`.
+    Call(String),
+}
+
+/// How a round ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundResult {
+    /// The label for the result, e.g. `和了` for a win or `流局` for a draw.
+    pub label: String,
+
+    /// How much each player's score changed.
+    pub deltas: Vec<i32>,
+
+    /// Details of the win, if the round ended in one. Only the first win is kept if
+    /// more than one player won on the same discard.
+    pub win: Option<WinLog>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WinLog {
+    pub winner: u8,
+
+    /// The player who dealt in, which is the same as `winner` for a win on the
+    /// winner's own draw.
+    pub from: u8,
+
+    /// The player responsible for the win under the pao rule, or `winner` if no one
+    /// is.
+    pub pao: u8,
+
+    /// The score for the hand, e.g. `30符1飜1000点`.
+    pub score: String,
+
+    /// The yaku in the hand and their values, e.g. `断幺九(1飜)`.
+    pub yaku: Vec<String>,
+}
+
+/// A round imported from a game log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedRound {
+    /// The state at the start of the round.
+    ///
+    /// The wall is made up of the tiles drawn in the log in order, followed by the
+    /// tiles that no one drew. Matches don't have a dead wall yet, so those tiles
+    /// are left at the end of the live wall.
+    pub state: MatchState,
+
+    /// The draws and discards made during the round, which can be applied to
+    /// `state` to replay it.
+    pub events: Vec<MatchEvent>,
+
+    /// The players that declared riichi, along with the index of their riichi tile
+    /// within their discards.
+    pub riichi: HashMap<Wind, usize>,
+
+    pub outcome: Outcome,
+}
+
+/// Converts a match into a game log with a single round.
+///
+/// `state` is the state at the start of the match, and `events` are the events that
+/// occurred during the match, which must end with `MatchEvent::MatchEnded`. Matches
+/// don't support wins yet, so the round always ends in an exhaustive draw.
+///
+/// # Errors
+///
+/// Returns an error if `events` can't be applied to `state`, or if the match hasn't
+/// ended.
+pub fn export(state: &MatchState, events: &[MatchEvent]) -> Result<GameLog> {
+    let mut state = state.clone();
+    let mut players = Wind::iter()
+        .map(|seat| {
+            let mut haipai = state
+                .player(seat)
+                .tiles()
+                .iter()
+                .map(|instance| tile_code(instance.tile))
+                .collect::<Vec<_>>();
+            haipai.sort();
+
+            PlayerLog {
+                haipai,
+                ..Default::default()
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut draws = HashMap::new();
+    let mut ended = false;
+    for event in events {
+        if ended {
+            bail!("Event {:?} occurred after the match ended", event);
+        }
+
+        state
+            .apply_event(event)
+            .with_context(|| format!("Failed to apply event {:?}", event))?;
+
+        match *event {
+            MatchEvent::TileDrawn { seat, tile } => {
+                draws.insert(seat, tile);
+                let code = tile_code(tile::by_id(tile));
+                players[seat as usize].takes.push(Action::Tile(code));
+            }
+
+            MatchEvent::TileDiscarded { seat, tile } => {
+                let code = if draws.remove(&seat) == Some(tile) {
+                    TSUMOGIRI
+                } else {
+                    tile_code(tile::by_id(tile))
+                };
+                players[seat as usize].discards.push(Action::Tile(code));
+            }
+
+            MatchEvent::MatchEnded => ended = true,
+        }
+    }
+
+    if !ended {
+        bail!("Can't export a match that hasn't ended");
+    }
+
+    Ok(GameLog {
+        title: vec![format!("Match {}", state.id), String::new()],
+        name: Wind::iter().map(|seat| format!("{:?}", seat)).collect(),
+        rule: Rule {
+            disp: "Riichi".into(),
+            aka: 0,
+        },
+        log: vec![Round {
+            kyoku: 0,
+            honba: 0,
+            kyotaku: 0,
+            scores: vec![STARTING_SCORE; 4],
+
+            dora: vec![tile_code(DORA_INDICATOR)],
+            ura_dora: Vec::new(),
+
+            players,
+            result: RoundResult {
+                label: EXHAUSTIVE_DRAW.into(),
+                deltas: vec![0; 4],
+                win: None,
+            },
+        }],
+    })
+}
+
+/// Converts each round in `log` into the state at the start of the round and the
+/// events that occurred during it.
+///
+/// The dealer of each round is seated as East. Only draws, discards and riichi
+/// declarations are supported, since the match logic doesn't support calls yet.
+///
+/// # Errors
+///
+/// Returns an error if a round contains a call or kong, or if it isn't a valid
+/// round of mahjong.
+pub fn import(log: &GameLog) -> Result<Vec<ImportedRound>> {
+    log.log
+        .iter()
+        .enumerate()
+        .map(|(index, round)| {
+            import_round(MatchId::new(index as u32), round)
+                .with_context(|| format!("Failed to import round {}", index))
+        })
+        .collect()
+}
+
+fn import_round(id: MatchId, round: &Round) -> Result<ImportedRound> {
+    if round.players.len() != 4 {
+        bail!("Expected 4 players, found {}", round.players.len());
+    }
+
+    // Tenhou lists players in their seats for the first round, so the dealer moves
+    // around the list as the game goes on.
+    let dealer = (round.kyoku % 4) as usize;
+    let player = |seat: Wind| &round.players[(dealer + seat as usize) % 4];
+
+    let mut unused = TILE_SET.clone();
+    let mut take = |code: u8| -> Result<TileInstance> {
+        let tile = tile_from_code(code)?;
+        let index = unused
+            .iter()
+            .position(|instance| instance.tile == tile)
+            .ok_or_else(|| anyhow!("More than 4 copies of {}", tile))?;
+        Ok(unused.remove(index))
+    };
+
+    let mut hands = HashMap::new();
+    for seat in Wind::iter() {
+        let haipai = &player(seat).haipai;
+        if haipai.len() != 13 {
+            bail!("{:?} started with {} tiles", seat, haipai.len());
+        }
+
+        let hand = haipai
+            .iter()
+            .map(|&code| take(code))
+            .collect::<Result<Vec<_>>>()?;
+        hands.insert(seat, hand);
+    }
+    let starting_hands = hands.clone();
+
+    let mut events = Vec::new();
+    let mut riichi = HashMap::new();
+    let mut wall = Vec::new();
+    let mut taken = HashMap::<Wind, usize>::new();
+    let mut seat = Wind::East;
+    loop {
+        let turn = *taken.get(&seat).unwrap_or(&0);
+        let log = player(seat);
+        let draw = match log.takes.get(turn) {
+            Some(Action::Tile(code)) => take(*code)?,
+            Some(Action::Call(call)) => bail!("Unsupported call {:?} by {:?}", call, seat),
+            None => break,
+        };
+        taken.insert(seat, turn + 1);
+        wall.push(draw);
+        events.push(MatchEvent::TileDrawn {
+            seat,
+            tile: draw.id,
+        });
+
+        let hand = hands.get_mut(&seat).unwrap();
+        hand.push(draw);
+
+        let code = match log.discards.get(turn) {
+            Some(Action::Tile(code)) => *code,
+            Some(Action::Call(call)) => match call.strip_prefix('r') {
+                Some(code) => {
+                    riichi.insert(seat, turn);
+                    code.parse()
+                        .with_context(|| format!("Invalid riichi discard {:?}", call))?
+                }
+                None => bail!("Unsupported action {:?} by {:?}", call, seat),
+            },
+            None => break,
+        };
+
+        // Prefer discarding from the hand rather than the draw, since Tenhou marks
+        // discards of the draw separately.
+        let index = if code == TSUMOGIRI {
+            hand.len() - 1
+        } else {
+            let tile = tile_from_code(code)?;
+            hand.iter()
+                .position(|instance| instance.tile == tile)
+                .ok_or_else(|| {
+                    anyhow!("{:?} discarded {}, which isn't in their hand", seat, tile)
+                })?
+        };
+        let discard = hand.remove(index);
+        events.push(MatchEvent::TileDiscarded {
+            seat,
+            tile: discard.id,
+        });
+
+        seat = seat.next();
+    }
+
+    // `MatchState` deals the hands from the end of the tiles, starting with East, and
+    // then draws from the end of the wall.
+    let mut tiles = unused;
+    tiles.extend(wall.into_iter().rev());
+    for seat in Wind::iter().rev() {
+        tiles.extend(&starting_hands[&seat]);
+    }
+    let state = MatchState::new(id, tiles);
+
+    // Check that the events replay correctly, e.g. that no one discarded a tile
+    // before drawing it.
+    let mut replay = state.clone();
+    for event in &events {
+        replay
+            .apply_event(event)
+            .with_context(|| format!("Failed to replay event {:?}", event))?;
+    }
+
+    let outcome = match &round.result.win {
+        Some(win) => {
+            let seat_of = |player: u8| -> Result<Wind> {
+                if player >= 4 {
+                    bail!("Invalid player {}", player);
+                }
+                Ok(seat_at((player as usize + 4 - dealer) % 4))
+            };
+
+            let winner = seat_of(win.winner)?;
+            let han = win
+                .yaku
+                .iter()
+                .map(|yaku| parse_han(yaku))
+                .sum::<Result<u8>>()?;
+            Outcome::Win {
+                winner,
+                discarder: Some(seat_of(win.from)?).filter(|&from| from != winner),
+                han,
+                points: parse_points(&win.score)?,
+            }
+        }
+
+        None => Outcome::Draw,
+    };
+
+    Ok(ImportedRound {
+        state,
+        events,
+        riichi,
+        outcome,
+    })
+}
+
+/// Returns the code for `tile` in Tenhou's JSON logs.
+pub fn tile_code(tile: Tile) -> u8 {
+    match tile {
+        Tile::Simple(simple) => {
+            let suit = match simple.suit {
+                Suit::Characters => 10,
+                Suit::Coins => 20,
+                Suit::Bamboo => 30,
+            };
+            suit + simple.number
+        }
+
+        Tile::Wind(wind) => 41 + wind as u8,
+        Tile::Dragon(dragon) => 45 + dragon as u8,
+    }
+}
+
+/// Returns the tile for a code in Tenhou's JSON logs, the inverse of `tile_code`.
+///
+/// Red fives are returned as regular fives.
+pub fn tile_from_code(code: u8) -> Result<Tile> {
+    let tile = match (code / 10, code % 10) {
+        (1, number @ 1..=9) => SimpleTile::new(Suit::Characters, number).into(),
+        (2, number @ 1..=9) => SimpleTile::new(Suit::Coins, number).into(),
+        (3, number @ 1..=9) => SimpleTile::new(Suit::Bamboo, number).into(),
+        (4, 1) => Wind::East.into(),
+        (4, 2) => Wind::South.into(),
+        (4, 3) => Wind::West.into(),
+        (4, 4) => Wind::North.into(),
+        (4, 5) => Dragon::White.into(),
+        (4, 6) => Dragon::Green.into(),
+        (4, 7) => Dragon::Red.into(),
+        (5, 1) => SimpleTile::new(Suit::Characters, 5).into(),
+        (5, 2) => SimpleTile::new(Suit::Coins, 5).into(),
+        (5, 3) => SimpleTile::new(Suit::Bamboo, 5).into(),
+        _ => bail!("Invalid Tenhou tile code {}", code),
+    };
+
+    Ok(tile)
+}
+
+/// Converts `id` into Tenhou's 136-tile numbering.
+///
+/// Both schemes number the four copies of each kind of tile consecutively, so only
+/// the order of the kinds differs.
+pub fn to_tenhou_id(id: TileId) -> u8 {
+    let copy = id.raw() % 4;
+    tenhou_kind(tile::by_id(id)) * 4 + copy
+}
+
+/// Converts a tile in Tenhou's 136-tile numbering into a `TileId`, the inverse of
+/// `to_tenhou_id`.
+pub fn from_tenhou_id(id: u8) -> Result<TileId> {
+    let kind = (0..analysis::KINDS)
+        .map(analysis::kind)
+        .find(|&tile| tenhou_kind(tile) == id / 4)
+        .ok_or_else(|| anyhow!("Invalid Tenhou tile ID {}", id))?;
+    Ok(TILE_SET[analysis::kind_index(kind) * 4 + (id % 4) as usize].id)
+}
+
+/// Returns the index of `tile`'s kind in Tenhou's ordering, which goes through the
+/// characters, coins and bamboo, then the winds, and then the dragons.
+fn tenhou_kind(tile: Tile) -> u8 {
+    match tile_code(tile) {
+        code @ 11..=39 => (code / 10 - 1) * 9 + code % 10 - 1,
+        code => 27 + code - 41,
+    }
+}
+
+/// Returns the seat at `index` in wind order.
+fn seat_at(index: usize) -> Wind {
+    Wind::iter().nth(index).expect("Invalid seat index")
+}
+
+/// Parses the han from a yaku in a win, e.g. `断幺九(1飜)`. Yakuman are counted as
+/// 13 han.
+fn parse_han(yaku: &str) -> Result<u8> {
+    let value = yaku
+        .rsplit('(')
+        .next()
+        .and_then(|value| value.strip_suffix(')'))
+        .ok_or_else(|| anyhow!("Invalid yaku {:?}", yaku))?;
+
+    if value == "役満" {
+        return Ok(13);
+    }
+
+    value
+        .strip_suffix('飜')
+        .and_then(|han| han.parse().ok())
+        .ok_or_else(|| anyhow!("Invalid yaku {:?}", yaku))
+}
+
+/// Parses the total points for a hand from its score, e.g. `30符1飜1000点`.
+///
+/// Wins on the winner's own draw list what each player pays, either as
+/// `500-1000点` for what the other players and the dealer pay, or as `1000点∀` if
+/// every player pays the same.
+fn parse_points(score: &str) -> Result<u32> {
+    let invalid = || anyhow!("Invalid score {:?}", score);
+    let (value, suffix) = score.split_at(score.find('点').ok_or_else(invalid)?);
+    let start = value
+        .rfind(|c: char| !c.is_ascii_digit() && c != '-')
+        .map_or(0, |index| {
+            index + value[index..].chars().next().unwrap().len_utf8()
+        });
+
+    let payments = value[start..]
+        .split('-')
+        .map(|payment| payment.parse::<u32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>>>()?;
+
+    match payments[..] {
+        [all] if suffix.contains('∀') => Ok(all * 3),
+        [total] => Ok(total),
+        [others, dealer] => Ok(others * 2 + dealer),
+        _ => Err(invalid()),
+    }
+}
+
+impl TryFrom<Vec<Value>> for Round {
+    type Error = anyhow::Error;
+
+    fn try_from(values: Vec<Value>) -> Result<Self> {
+        if values.len() != 17 {
+            bail!("Expected a round with 17 fields, found {}", values.len());
+        }
+
+        let field = |index: usize| values[index].clone();
+        let info: Vec<u8> = serde_json::from_value(field(0))?;
+        let (kyoku, honba, kyotaku) = match info[..] {
+            [kyoku, honba, kyotaku] => (kyoku, honba, kyotaku),
+            _ => bail!("Invalid round info {:?}", info),
+        };
+
+        let players = (0..4)
+            .map(|player| {
+                let start = 4 + player * 3;
+                Ok(PlayerLog {
+                    haipai: serde_json::from_value(field(start))?,
+                    takes: serde_json::from_value(field(start + 1))?,
+                    discards: serde_json::from_value(field(start + 2))?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Round {
+            kyoku,
+            honba,
+            kyotaku,
+            scores: serde_json::from_value(field(1))?,
+            dora: serde_json::from_value(field(2))?,
+            ura_dora: serde_json::from_value(field(3))?,
+            players,
+            result: RoundResult::try_from(field(16))?,
+        })
+    }
+}
+
+impl From<Round> for Vec<Value> {
+    fn from(round: Round) -> Self {
+        let mut values = vec![
+            Value::from(vec![round.kyoku, round.honba, round.kyotaku]),
+            Value::from(round.scores),
+            Value::from(round.dora),
+            Value::from(round.ura_dora),
+        ];
+
+        for player in round.players {
+            values.push(Value::from(player.haipai));
+            values.push(serde_json::to_value(player.takes).unwrap());
+            values.push(serde_json::to_value(player.discards).unwrap());
+        }
+
+        values.push(round.result.into());
+        values
+    }
+}
+
+impl TryFrom<Value> for RoundResult {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        let values: Vec<Value> = serde_json::from_value(value)?;
+        let label = values
+            .first()
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("Round result is missing its label"))?
+            .to_string();
+        let deltas = match values.get(1) {
+            Some(deltas) => serde_json::from_value(deltas.clone())?,
+            None => Vec::new(),
+        };
+
+        let win = match values.get(2) {
+            Some(details) if label == WIN => {
+                let details: Vec<Value> = serde_json::from_value(details.clone())?;
+                if details.len() < 4 {
+                    bail!("Invalid win details {:?}", details);
+                }
+
+                Some(WinLog {
+                    winner: serde_json::from_value(details[0].clone())?,
+                    from: serde_json::from_value(details[1].clone())?,
+                    pao: serde_json::from_value(details[2].clone())?,
+                    score: serde_json::from_value(details[3].clone())?,
+                    yaku: serde_json::from_value(Value::from(details[4..].to_vec()))?,
+                })
+            }
+            _ => None,
+        };
+
+        Ok(RoundResult { label, deltas, win })
+    }
+}
+
+impl From<RoundResult> for Value {
+    fn from(result: RoundResult) -> Self {
+        let mut values = vec![Value::from(result.label)];
+        if !result.deltas.is_empty() {
+            values.push(Value::from(result.deltas));
+        }
+
+        if let Some(win) = result.win {
+            let mut details = vec![
+                Value::from(win.winner),
+                Value::from(win.from),
+                Value::from(win.pao),
+                Value::from(win.score),
+            ];
+            details.extend(win.yaku.into_iter().map(Value::from));
+            values.push(Value::from(details));
+        }
+
+        Value::from(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hand::Hand;
+
+    #[test]
+    fn tile_numbering() {
+        assert_eq!(11, tile_code(SimpleTile::new(Suit::Characters, 1).into()));
+        assert_eq!(29, tile_code(SimpleTile::new(Suit::Coins, 9).into()));
+        assert_eq!(41, tile_code(Wind::East.into()));
+        assert_eq!(47, tile_code(Dragon::Red.into()));
+        assert!(tile_from_code(40).is_err());
+        assert!(tile_from_code(TSUMOGIRI).is_err());
+
+        assert_eq!(
+            0,
+            to_tenhou_id(TILE_SET[analysis::kind_index(tile_from_code(11).unwrap()) * 4].id)
+        );
+        assert_eq!(
+            135,
+            to_tenhou_id(TILE_SET[analysis::kind_index(Dragon::Red.into()) * 4 + 3].id)
+        );
+        assert!(from_tenhou_id(136).is_err());
+
+        for instance in TILE_SET.iter() {
+            assert_eq!(
+                instance.tile,
+                tile_from_code(tile_code(instance.tile)).unwrap()
+            );
+            assert_eq!(
+                instance.id,
+                from_tenhou_id(to_tenhou_id(instance.id)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let mut tiles = TILE_SET.clone();
+        tiles.reverse();
+        let initial = MatchState::new(MatchId::new(7), tiles);
+
+        // Play out the match, with each player discarding the first tile in their
+        // hand every other turn.
+        let mut state = initial.clone();
+        let mut events = Vec::new();
+        while !state.wall.is_empty() {
+            let seat = state.current_turn;
+            let draw = state.draw_for_player(seat).unwrap();
+            events.push(MatchEvent::TileDrawn { seat, tile: draw });
+
+            let tile = if events.len() % 4 == 1 {
+                state.player(seat).tiles()[0].id
+            } else {
+                draw
+            };
+            state.discard_tile(seat, tile).unwrap();
+            events.push(MatchEvent::TileDiscarded { seat, tile });
+        }
+        events.push(MatchEvent::MatchEnded);
+
+        let log = export(&initial, &events).unwrap();
+        let json = serde_json::to_string(&log).unwrap();
+        let log: GameLog = serde_json::from_str(&json).unwrap();
+        assert_eq!(EXHAUSTIVE_DRAW, log.log[0].result.label);
+
+        // Importing assigns copies of each tile in a different order, but the tiles
+        // in each hand are the same.
+        let imported = import(&log).unwrap().remove(0);
+        assert_eq!(Outcome::Draw, imported.outcome);
+        let mut replay = imported.state.clone();
+        for event in &imported.events {
+            replay.apply_event(event).unwrap();
+        }
+        for seat in Wind::iter() {
+            let kinds = |hand: &Hand| {
+                let mut kinds = hand
+                    .tiles()
+                    .iter()
+                    .chain(hand.discards())
+                    .map(|instance| tile_code(instance.tile))
+                    .collect::<Vec<_>>();
+                kinds.sort();
+                kinds
+            };
+            assert_eq!(kinds(state.player(seat)), kinds(replay.player(seat)));
+        }
+    }
+
+    #[test]
+    fn imports_wins() {
+        let log: GameLog = serde_json::from_str(
+            r#"{
+                "title": ["Test", ""],
+                "name": ["A", "B", "C", "D"],
+                "rule": {"disp": "般南喰赤", "aka": 1},
+                "log": [
+                    [
+                        [0, 0, 0], [25000, 25000, 25000, 25000], [41], [],
+                        [11, 12, 13, 24, 25, 26, 37, 38, 39, 41, 41, 45, 45], [45], [],
+                        [14, 15, 16, 17, 18, 19, 21, 22, 23, 27, 28, 29, 31], [], [],
+                        [32, 33, 34, 35, 36, 42, 42, 42, 43, 43, 43, 44, 44], [], [],
+                        [46, 46, 46, 47, 47, 47, 44, 44, 14, 15, 16, 17, 18], [], [],
+                        ["和了", [48000, -16000, -16000, -16000], [0, 0, 0, "役満16000点∀", "天和(役満)"]]
+                    ],
+                    [
+                        [1, 0, 0], [73000, 9000, 9000, 9000], [41], [],
+                        [14, 14, 14, 15, 15, 15, 16, 16, 16, 17, 17, 17, 18], [], [],
+                        [11, 11, 11, 12, 12, 12, 13, 13, 13, 41, 42, 43, 44], [38], [60],
+                        [22, 23, 24, 33, 34, 35, 26, 27, 28, 36, 37, 52, 25], [], [],
+                        [19, 19, 19, 29, 29, 29, 39, 39, 39, 31, 31, 31, 18], [], [],
+                        ["和了", [0, -1000, 1000, 0], [2, 1, 2, "30符1飜1000点", "断幺九(1飜)"]]
+                    ]
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let rounds = import(&log).unwrap();
+        assert_eq!(
+            Outcome::Win {
+                winner: Wind::East,
+                discarder: None,
+                han: 13,
+                points: 48000,
+            },
+            rounds[0].outcome,
+        );
+        assert_eq!(1, rounds[0].events.len());
+
+        // The second player in the log is the dealer for the second round.
+        assert_eq!(
+            Outcome::Win {
+                winner: Wind::South,
+                discarder: Some(Wind::East),
+                han: 1,
+                points: 1000,
+            },
+            rounds[1].outcome,
+        );
+
+        let mut state = rounds[1].state.clone();
+        for event in &rounds[1].events {
+            state.apply_event(event).unwrap();
+        }
+        let discard = *state.player(Wind::East).discards().last().unwrap();
+        let south = state.player(Wind::South);
+        assert_eq!(
+            Some(1),
+            analysis::han(south, Some(discard.tile), Wind::South, false)
+        );
+    }
+}
@@ -3,6 +3,7 @@ use derive_more::*;
 use lazy_static::lazy_static;
 use num_traits::{ops::wrapping::WrappingAdd, One, PrimInt};
 use serde::*;
+use std::fmt;
 use strum::*;
 
 #[cs_bindgen]
@@ -30,6 +31,16 @@ impl Tile {
     }
 }
 
+/// Displays the tile using the ASCII "mpsz" shorthand, e.g. `5p` or `7z`.
+///
+/// See the [`render`](../render/index.html) module for other ways of displaying
+/// tiles.
+impl fmt::Display for Tile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&crate::render::ascii(*self))
+    }
+}
+
 #[cs_bindgen]
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, EnumIter, Serialize, Deserialize,
@@ -62,7 +73,18 @@ pub enum HonorTile {
 
 #[cs_bindgen]
 #[derive(
-    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, EnumIter, Serialize, Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    EnumIter,
+    EnumString,
+    Serialize,
+    Deserialize,
 )]
 pub enum Wind {
     East,
@@ -176,6 +198,12 @@ impl Dragon {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct TileId(u8);
 
+impl TileId {
+    pub fn raw(self) -> u8 {
+        self.0
+    }
+}
+
 /// An instance of a tile within a player's hand during a match.
 ///
 /// Combines a [`TileId`] with a [`Tile`] value in order to differentiate between
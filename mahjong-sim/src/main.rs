@@ -0,0 +1,153 @@
+//! Runs bot-vs-bot self-play games and reports aggregate stats.
+//!
+//! Games are played locally with `mahjong::simulation`, without a server, which makes
+//! it possible to play thousands of games in a few minutes. This is used to balance
+//! the bots against each other and to check how changes to the rules affect the
+//! game at scale. Only Riichi rules are supported for now, so there's no option to
+//! choose a ruleset.
+//!
+//! ```text
+//! cargo run --release -- --games 10000 --lineup Defensive,Efficiency,Efficiency,Efficiency
+//! ```
+//!
+//! `Mjai` bots in the lineup are played by an external MJAI engine, started with the
+//! command given by `--mjai-engine`:
+//!
+//! ```text
+//! cargo run --release -- --lineup Mjai,Efficiency,Efficiency,Efficiency --mjai-engine "./engine"
+//! ```
+
+use mahjong::{
+    anyhow::{bail, Result},
+    bot::{BotKind, SearchBudget},
+    simulation::{Simulation, Stats},
+};
+use std::{thread, time::Instant};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Play bots against each other and report aggregate stats")]
+struct Options {
+    /// The number of games to play.
+    #[structopt(long, default_value = "1000")]
+    games: u64,
+
+    /// The seed for the first game. Each game uses the next seed, so any game can be
+    /// replayed with `--seed <seed> --games 1`.
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+
+    /// The four bots to play, separated by commas. Seats rotate every game.
+    #[structopt(
+        long,
+        use_delimiter = true,
+        default_value = "Efficiency,Efficiency,Efficiency,Efficiency"
+    )]
+    lineup: Vec<BotKind>,
+
+    /// The number of samples `MonteCarlo` bots play out for each decision.
+    #[structopt(long, default_value = "50")]
+    samples: usize,
+
+    /// The shell command that starts the MJAI engine for `Mjai` bots. Each bot
+    /// starts its own copy of the engine for every game.
+    #[structopt(long)]
+    mjai_engine: Option<String>,
+
+    /// The number of threads to play games on.
+    #[structopt(long, default_value = "1")]
+    threads: u64,
+
+    /// Print the stats as JSON instead of a table.
+    #[structopt(long)]
+    json: bool,
+}
+
+fn main() -> Result<()> {
+    let options = Options::from_args();
+
+    let lineup = match options.lineup[..] {
+        [first, second, third, fourth] => [first, second, third, fourth],
+        _ => bail!(
+            "The lineup must have exactly 4 bots, but {} were given",
+            options.lineup.len()
+        ),
+    };
+    if options.threads == 0 {
+        bail!("At least one thread is needed to play games");
+    }
+    if lineup.contains(&BotKind::Mjai) && options.mjai_engine.is_none() {
+        bail!("`Mjai` bots need an engine, pass one with --mjai-engine");
+    }
+
+    let simulation = Simulation {
+        lineup,
+        seed: options.seed,
+        search_budget: SearchBudget::samples(options.samples),
+        mjai_engine: options.mjai_engine.clone(),
+    };
+
+    let start = Instant::now();
+    let workers = (0..options.threads)
+        .map(|worker| {
+            let simulation = simulation.clone();
+            let games = options.games;
+            let threads = options.threads;
+            thread::spawn(move || {
+                let mut stats = Stats::new(&simulation.lineup);
+                let mut index = worker;
+                while index < games {
+                    stats.record(&simulation.play(index));
+                    index += threads;
+                }
+                stats
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut stats = Stats::new(&lineup);
+    for worker in workers {
+        match worker.join() {
+            Ok(worker_stats) => stats.merge(&worker_stats),
+            Err(_) => bail!("A simulation thread panicked"),
+        }
+    }
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+
+    println!(
+        "Played {} games in {:.1}s",
+        stats.games,
+        start.elapsed().as_secs_f64(),
+    );
+    println!(
+        "Draws: {:.1}%, average length: {:.1} discards",
+        stats.draw_rate() * 100.0,
+        stats.average_turns(),
+    );
+    if stats.invalid_decisions > 0 {
+        println!("Invalid decisions: {}", stats.invalid_decisions);
+    }
+
+    println!();
+    println!(
+        "{:<8}{:<12}{:>8}{:>10}{:>9}{:>11}",
+        "Player", "Bot", "Wins", "Deal-ins", "Riichi", "Avg value",
+    );
+    for (player, player_stats) in stats.players.iter().enumerate() {
+        println!(
+            "{:<8}{:<12}{:>7.1}%{:>9.1}%{:>8.1}%{:>11.0}",
+            player + 1,
+            format!("{:?}", player_stats.bot),
+            stats.win_rate(player) * 100.0,
+            stats.deal_in_rate(player) * 100.0,
+            stats.riichi_rate(player) * 100.0,
+            stats.average_value(player),
+        );
+    }
+
+    Ok(())
+}